@@ -0,0 +1,167 @@
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use rusty_kv::store::btree_kv::btree::BTree;
+use rusty_kv::store::btree_kv::buffer_pool_manager::BufferManager;
+use rusty_kv::store::btree_kv::commons::PAGE_SIZE;
+use rusty_kv::store::btree_kv::disk_manager::{DiskManager, SyncMode};
+use std::hint::black_box;
+use std::path::Path;
+use tempfile::TempDir;
+
+// Page occupancies to benchmark at, in percent of the page body.
+const OCCUPANCIES: [usize; 3] = [25, 50, 90];
+
+fn key(index: usize) -> Vec<u8> {
+    format!("key{:05}", index).into_bytes()
+}
+
+fn value(index: usize) -> Vec<u8> {
+    format!("value{:027}", index).into_bytes()
+}
+
+///
+/// Creates a tree whose root leaf is filled to `occupancy` percent, and returns it with the
+/// number of rows saved. Page operations are benchmarked through the tree, which doesn't
+/// split until the root is full, so every operation hits the same page in the buffer pool.
+///
+fn fill_root(path: &Path, occupancy: usize) -> (BTree, usize) {
+    let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, path).unwrap();
+    let mut btree = BTree::new(bpm).unwrap();
+    let mut rows = 0;
+    loop {
+        let stats = btree.page_stats(btree.root()).unwrap();
+        let used = stats.used_bytes + stats.dead_bytes;
+        if used * 100 >= (used + stats.free_bytes) * occupancy {
+            break;
+        }
+        btree.save(&key(rows), &value(rows)).unwrap();
+        rows += 1;
+    }
+    (btree, rows)
+}
+
+fn bench_btree_page(c: &mut Criterion) {
+    let temp_dir = TempDir::new().unwrap();
+    let mut group = c.benchmark_group("btree page");
+    for occupancy in OCCUPANCIES {
+        let path = temp_dir.path().join(format!("page_{}.db", occupancy));
+        let (mut btree, rows) = fill_root(&path, occupancy);
+        let middle = rows / 2;
+
+        group.bench_with_input(BenchmarkId::new("save", occupancy), &middle, |b, &index| {
+            b.iter(|| btree.save(&key(index), &value(index)).unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("get", occupancy), &middle, |b, &index| {
+            b.iter(|| black_box(btree.get(&key(index)).unwrap()));
+        });
+        // The key is saved again, so the occupancy stays the same across iterations.
+        group.bench_with_input(
+            BenchmarkId::new("delete and save", occupancy),
+            &middle,
+            |b, &index| {
+                b.iter(|| {
+                    btree.delete(&key(index)).unwrap();
+                    btree.save(&key(index), &value(index)).unwrap();
+                });
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("range", occupancy), &rows, |b, &rows| {
+            b.iter(|| black_box(btree.range(&key(0), &key(rows)).unwrap().count()));
+        });
+    }
+    group.finish();
+}
+
+// Rows saved per iteration of the insert order benchmarks, few enough to fit in the root.
+const INSERTS: usize = 100;
+
+fn bench_insert_order(c: &mut Criterion) {
+    let temp_dir = TempDir::new().unwrap();
+    // Each iteration saves into a new tree, in its own file since data files are locked.
+    let mut trees = 0;
+    let mut new_tree = || {
+        trees += 1;
+        fill_root(&temp_dir.path().join(format!("inserts_{}.db", trees)), 0).0
+    };
+    let ascending: Vec<usize> = (0..INSERTS).collect();
+    // INSERTS and 37 are coprime, so this visits every index once, out of order.
+    let scattered: Vec<usize> = (0..INSERTS).map(|index| index * 37 % INSERTS).collect();
+
+    let mut group = c.benchmark_group("insert order");
+    for (name, indices) in [("ascending", &ascending), ("scattered", &scattered)] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                &mut new_tree,
+                |mut btree| {
+                    for &index in indices {
+                        btree.save(&key(index), &value(index)).unwrap();
+                    }
+                    btree
+                },
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn bench_buffer_pool_get(c: &mut Criterion) {
+    let temp_dir = TempDir::new().unwrap();
+    // A single frame, so that alternating between two pages misses every time.
+    let mut bpm =
+        BufferManager::new_with_path(PAGE_SIZE, &temp_dir.path().join("pool.db")).unwrap();
+    let first = bpm.allocate_page().unwrap();
+    let second = bpm.allocate_page().unwrap();
+    for page_id in [first, second] {
+        bpm.get(page_id)
+            .unwrap()
+            .with_page_mut(|data| data[PAGE_SIZE / 2] = 1);
+        bpm.flush_all().unwrap();
+    }
+
+    let mut group = c.benchmark_group("buffer pool get");
+    group.bench_function("hit", |b| {
+        b.iter(|| {
+            bpm.get(first).unwrap();
+        });
+    });
+    group.bench_function("miss", |b| {
+        b.iter(|| {
+            bpm.get(first).unwrap();
+            bpm.get(second).unwrap();
+        });
+    });
+    group.finish();
+}
+
+fn bench_verify_on_read(c: &mut Criterion) {
+    let temp_dir = TempDir::new().unwrap();
+    let mut disk_manager =
+        DiskManager::new(&temp_dir.path().join("verify.db"), SyncMode::Deferred).unwrap();
+    let page_id = disk_manager.allocate_page().unwrap();
+    disk_manager
+        .write_page(&page_id, &[7u8; PAGE_SIZE])
+        .unwrap();
+    let mut buffer = [0u8; PAGE_SIZE];
+
+    let mut group = c.benchmark_group("read page");
+    for (name, verify_on_read) in [("verified", true), ("unverified", false)] {
+        disk_manager.set_verify_on_read(verify_on_read);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                disk_manager
+                    .read_page(&page_id, black_box(&mut buffer))
+                    .unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_btree_page,
+    bench_insert_order,
+    bench_buffer_pool_get,
+    bench_verify_on_read
+);
+criterion_main!(benches);
@@ -0,0 +1,189 @@
+use super::btree_kv::cache_policy_engine::{
+    CachePolicyEngineFactory, EvictionPolicy, ICachePolicyEngine,
+};
+use super::{MapRustyKV, RustyKV};
+
+///
+/// An in-memory key-value store holding at most `capacity` entries. Saving a new key into
+/// a full store evicts the least recently used key, so the store behaves as an LRU cache.
+/// Saves and gets both count as a use of the key.
+///
+/// Recency is tracked by the same cache policy engine the buffer pool uses for pages.
+///
+pub struct BoundedMapRustyKV<T> {
+    inner: MapRustyKV<T>,
+    cache_policy_engine: Box<dyn ICachePolicyEngine<String>>,
+    capacity: usize,
+}
+
+impl<T> BoundedMapRustyKV<T> {
+    ///
+    /// Creates a new, empty instance of the key-value store.
+    ///
+    /// # Arguments
+    /// * `capacity` - The maximum number of entries. Must be greater than zero.
+    ///
+    /// # Examples
+    /// let kv_store: BoundedMapRustyKV<String> = BoundedMapRustyKV::new(100);
+    ///
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than zero");
+        BoundedMapRustyKV {
+            inner: MapRustyKV::new(),
+            cache_policy_engine: CachePolicyEngineFactory::get_engine(
+                EvictionPolicy::LRU,
+                capacity,
+            ),
+            capacity,
+        }
+    }
+
+    ///
+    /// Saves a key-value pair to the store. If the key already exists, its value is updated.
+    /// Otherwise, if the store is full, the least recently used key is evicted first.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key.
+    /// * `value` - The value to be associated with the key.
+    ///
+    /// # Returns
+    /// * `Some(String)` with the evicted key if an entry was evicted, `None` otherwise.
+    ///
+    /// # Examples
+    /// let mut kv_store: BoundedMapRustyKV<String> = BoundedMapRustyKV::new(1);
+    /// kv_store.save("key1", "value1".to_string());
+    /// assert_eq!(kv_store.save("key2", "value2".to_string()), Some("key1".to_string()));
+    ///
+    pub fn save(&mut self, key: &str, value: T) -> Option<String> {
+        let mut evicted = None;
+        if !self.inner.contains_key(key) && self.inner.len() == self.capacity {
+            let lru_key = self.cache_policy_engine.evict();
+            self.inner.delete(&lru_key);
+            evicted = Some(lru_key);
+        }
+        self.inner.save(key, value);
+        self.cache_policy_engine.touch(&key.to_string());
+        evicted
+    }
+
+    ///
+    /// Retrieves the value associated with a given key, marking the key as the most
+    /// recently used.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key to be retrieved.
+    ///
+    /// # Returns
+    /// * `Some(&T)` if the key exists, `None` otherwise.
+    ///
+    pub fn get(&mut self, key: &str) -> Option<&T> {
+        if self.inner.contains_key(key) {
+            self.cache_policy_engine.touch(&key.to_string());
+        }
+        self.inner.get(key)
+    }
+
+    ///
+    /// Deletes a key-value pair from the store.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key to be deleted.
+    ///
+    /// # Returns
+    /// * `true` if the key was found and deleted, `false` otherwise.
+    ///
+    pub fn delete(&mut self, key: &str) -> bool {
+        self.cache_policy_engine.remove(&key.to_string());
+        self.inner.delete(key)
+    }
+
+    ///
+    /// Checks if a key exists in the store. Unlike `get`, this doesn't count as a use.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key to be checked.
+    ///
+    /// # Returns
+    /// * `true` if the key exists, `false` otherwise.
+    ///
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.inner.contains_key(key)
+    }
+
+    ///
+    /// Removes every key-value pair from the store.
+    ///
+    pub fn clear(&mut self) {
+        for key in self.inner.keys() {
+            self.cache_policy_engine.remove(key);
+        }
+        self.inner.clear();
+    }
+
+    ///
+    /// Returns the number of key-value pairs in the store.
+    ///
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    ///
+    /// Returns `true` if the store contains no key-value pairs.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    ///
+    /// Returns the maximum number of key-value pairs the store holds.
+    ///
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used_key() {
+        let mut kv_store: BoundedMapRustyKV<u32> = BoundedMapRustyKV::new(3);
+        assert_eq!(kv_store.capacity(), 3);
+        for (index, key) in ["key1", "key2", "key3"].iter().enumerate() {
+            assert_eq!(kv_store.save(key, index as u32), None);
+        }
+        assert_eq!(kv_store.len(), 3);
+
+        // key1 is the oldest, but reading it makes key2 the least recently used.
+        assert_eq!(kv_store.get("key1"), Some(&0));
+        assert_eq!(kv_store.save("key4", 3), Some("key2".to_string()));
+        assert_eq!(kv_store.len(), 3);
+        assert!(!kv_store.contains_key("key2"));
+
+        // Updating a key counts as a use, and doesn't evict anything.
+        assert_eq!(kv_store.save("key3", 30), None);
+        assert_eq!(kv_store.save("key5", 4), Some("key1".to_string()));
+        assert_eq!(kv_store.save("key6", 5), Some("key4".to_string()));
+        assert_eq!(kv_store.get("key3"), Some(&30));
+        assert_eq!(kv_store.get("key1"), None);
+    }
+
+    #[test]
+    fn test_delete_and_clear_free_capacity() {
+        let mut kv_store: BoundedMapRustyKV<u32> = BoundedMapRustyKV::new(2);
+        kv_store.save("key1", 1);
+        kv_store.save("key2", 2);
+
+        assert!(kv_store.delete("key1"));
+        assert!(!kv_store.delete("key1"));
+        assert_eq!(kv_store.save("key3", 3), None);
+        assert_eq!(kv_store.save("key4", 4), Some("key2".to_string()));
+
+        kv_store.clear();
+        assert!(kv_store.is_empty());
+        assert_eq!(kv_store.save("key5", 5), None);
+        assert_eq!(kv_store.save("key6", 6), None);
+        assert_eq!(kv_store.save("key7", 7), Some("key5".to_string()));
+    }
+}
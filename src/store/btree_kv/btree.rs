@@ -0,0 +1,4069 @@
+use crate::store::btree_kv::buffer_pool_manager::BufferManager;
+use crate::store::btree_kv::commons::{PAGE_SIZE, PageId};
+use crate::store::btree_kv::comparator::{Comparator, Lexicographic};
+use crate::store::btree_kv::disk_manager::{DiskManager, SyncMode};
+use crate::store::btree_kv::error::{IntegrityError, RustyKVError};
+use crate::store::btree_kv::frame::Frame;
+use crate::store::btree_kv::helpers::byte_ordering::{cmp_lex_bytes, common_prefix_len};
+use crate::store::btree_kv::page::{
+    BTreePage, OTHER_PAGE_DATA_OFFSET, PageStats, PageType, SaveOutcome,
+};
+use crate::store::btree_kv::storage_backend::StorageBackend;
+use crate::store::write_batch::{BatchOperation, WriteBatch};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use std::mem::size_of;
+use std::path::Path;
+use std::{panic, thread};
+
+// Largest row that can be saved, including its slot map entry. Keeping rows well under a
+// page guarantees that both halves of a split page have enough room.
+const MAX_ENTRY_SIZE: usize = PAGE_SIZE / 4;
+
+// Fraction of the rows, by size, left in a page when it's split. Also used to divide rows
+// between siblings when rebalancing, and the last two pages of a level in bulk loads.
+const DEFAULT_FILL_FACTOR: f32 = 0.5;
+
+// Fraction of a page's capacity that its rows, by size, must take up after a delete, below
+// which it is merged with a sibling or borrows rows from it.
+const DEFAULT_MIN_OCCUPANCY: f32 = 0.5;
+
+// Capacity of the buffer pool that salvaged rows are saved through.
+const SALVAGE_POOL_SIZE: usize = 64 * PAGE_SIZE;
+
+// Size of a child page pointer stored as the value of an internal page row.
+const CHILD_POINTER_SIZE: usize = size_of::<u64>(); // 8 bytes
+
+// Values in leaf pages are prefixed with a tag. Inline values follow the tag. Values too
+// large to fit in a row are stored in a chain of overflow pages, and are replaced by the
+// Page ID of the first page in the chain and the length of the value.
+const VALUE_TAG_SIZE: usize = size_of::<u8>(); // 1 byte
+const VALUE_INLINE: u8 = 0;
+const VALUE_OVERFLOW: u8 = 1;
+const OVERFLOW_PAGE_ID_SIZE: usize = size_of::<u64>(); // 8 bytes
+const OVERFLOW_LENGTH_SIZE: usize = size_of::<u64>(); // 8 bytes
+const OVERFLOW_POINTER_SIZE: usize = VALUE_TAG_SIZE + OVERFLOW_PAGE_ID_SIZE + OVERFLOW_LENGTH_SIZE;
+
+// Overflow page layout. Each page is marked as not being a BTree page, and holds the Page ID
+// of the next page in the chain, followed by a chunk of the value.
+const OVERFLOW_NEXT_OFFSET: usize = OTHER_PAGE_DATA_OFFSET;
+const OVERFLOW_DATA_OFFSET: usize = OVERFLOW_NEXT_OFFSET + OVERFLOW_PAGE_ID_SIZE;
+const OVERFLOW_DATA_SIZE: usize = PAGE_SIZE - OVERFLOW_DATA_OFFSET;
+
+///
+/// Rows of a page, as owned key value pairs sorted by key.
+///
+type Entries = Vec<(Vec<u8>, Vec<u8>)>;
+
+///
+/// A page split off by an insert: the first key of the new page, and its Page ID.
+///
+type Split = (Vec<u8>, PageId);
+
+///
+/// State of a page after it was modified, that its parent page needs to act on.
+///
+enum Rebalance {
+    ///
+    /// The page is filled to at least the minimum occupancy.
+    ///
+    Balanced,
+    ///
+    /// The page is filled to less than the minimum occupancy, and needs to be merged with a
+    /// sibling or borrow rows from it.
+    ///
+    Underflow,
+    ///
+    /// The page overflowed and was split. The new sibling holds every key greater than or
+    /// equal to the separator, and needs to be added to the parent page.
+    ///
+    Split(Vec<u8>, PageId),
+}
+
+///
+/// Packs the rows of one level of a tree being bulk loaded into pages, in key order.
+///
+/// The last page written is held back until the next one fills up, so that if the last
+/// page of the level is less than half full, rows can be moved into it from its sibling.
+///
+struct LevelBuilder {
+    page_type: PageType,
+    // Order of the keys, deciding whether pages store a common prefix.
+    comparator: &'static dyn Comparator,
+    // Rows of the last full page, that hasn't been written yet.
+    pending: Option<Entries>,
+    // Rows of the page being filled.
+    entries: Entries,
+    size: usize,
+    // Smallest key and Page ID of every page written, to be added to the level above.
+    pages: Vec<(Vec<u8>, PageId)>,
+}
+
+impl LevelBuilder {
+    fn new(page_type: PageType, comparator: &'static dyn Comparator) -> Self {
+        LevelBuilder {
+            page_type,
+            comparator,
+            pending: None,
+            entries: Vec::new(),
+            size: 0,
+            pages: Vec::new(),
+        }
+    }
+
+    ///
+    /// Adds a row after every row added so far.
+    ///
+    fn push(
+        &mut self,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        mut new_page: impl FnMut(&[u8; PAGE_SIZE]) -> Result<PageId, Error>,
+    ) -> Result<(), RustyKVError> {
+        let entry_size = BTreePage::get_entry_size(&key, &value);
+        if self.size + entry_size > BTreePage::get_capacity() {
+            if let Some(pending) = self.pending.take() {
+                self.write(pending, &mut new_page)?;
+            }
+            self.pending = Some(std::mem::take(&mut self.entries));
+            self.size = 0;
+        }
+        self.entries.push((key, value));
+        self.size += entry_size;
+        Ok(())
+    }
+
+    ///
+    /// Writes the remaining pages of the level.
+    ///
+    /// # Returns
+    /// * `Ok(pages)`: Smallest key and Page ID of every page in the level, in key order.
+    /// * `Err(RustyKVError)` if a page couldn't be written.
+    ///
+    fn finish(
+        mut self,
+        mut new_page: impl FnMut(&[u8; PAGE_SIZE]) -> Result<PageId, Error>,
+    ) -> Result<Vec<(Vec<u8>, PageId)>, RustyKVError> {
+        let entries = std::mem::take(&mut self.entries);
+        match self.pending.take() {
+            Some(mut pending) if self.size < BTreePage::get_capacity() / 2 => {
+                // Even out the last two pages.
+                pending.extend(entries);
+                let (_, right) = <BTree>::split_entries(
+                    PageType::Leaf,
+                    &mut pending,
+                    DEFAULT_FILL_FACTOR,
+                    self.comparator,
+                )
+                .ok_or(RustyKVError::InsufficientSpace)?;
+                self.write(pending, &mut new_page)?;
+                self.write(right, &mut new_page)?;
+            }
+            Some(pending) => {
+                self.write(pending, &mut new_page)?;
+                self.write(entries, &mut new_page)?;
+            }
+            None if !entries.is_empty() => self.write(entries, &mut new_page)?,
+            None => {}
+        }
+        Ok(self.pages)
+    }
+
+    fn write(
+        &mut self,
+        mut entries: Entries,
+        mut new_page: impl FnMut(&[u8; PAGE_SIZE]) -> Result<PageId, Error>,
+    ) -> Result<(), RustyKVError> {
+        let first_key = entries[0].0.clone();
+        if self.page_type == PageType::Internal {
+            // The first key moves up to the level above, and the first child holds every
+            // key below the second separator.
+            entries[0].0.clear();
+        }
+        let page_id = new_page(&<BTree>::layout_node(
+            self.page_type,
+            &entries,
+            self.comparator,
+        )?)?;
+        self.pages.push((first_key, page_id));
+        Ok(())
+    }
+}
+
+///
+/// Result of salvaging a possibly corrupt data file with `BTree::salvage`.
+///
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SalvageReport {
+    ///
+    /// Page ID of the root page of the salvaged tree, to open it with `BTree::open`.
+    ///
+    pub root: PageId,
+    ///
+    /// Number of rows copied to the salvaged tree.
+    ///
+    pub rows: usize,
+    ///
+    /// Number of rows, of leaves that could be read, whose value couldn't be.
+    ///
+    pub lost_rows: usize,
+    ///
+    /// Pages that couldn't be recovered, in Page ID order.
+    ///
+    pub lost_pages: Vec<PageId>,
+}
+
+///
+/// A B+Tree of byte keys and values spanning multiple pages in the buffer pool.
+///
+/// Leaf pages map keys to values. Internal pages map separator keys to child pages: a
+/// child holds every key greater than or equal to its separator, and less than the next
+/// separator. The first row of an internal page has an empty key, so that its child
+/// holds every key less than the second separator.
+///
+/// Values that don't fit in a row are stored in a chain of overflow pages, so values are
+/// only limited by the size of the file. Keys are limited to a fraction of a page.
+///
+/// Pages are stored by the backend of the buffer pool, a data file by default. Bulk loads,
+/// snapshot imports, salvages and parallel scans work on data files, so they need a
+/// `DiskManager`.
+///
+pub struct BTree<B: StorageBackend = DiskManager> {
+    // Buffer pool holding the pages of the tree.
+    buffer_manager: BufferManager<B>,
+    // Page ID of the root page.
+    root: PageId,
+    // Fraction of the rows, by size, left in a page when it's split.
+    fill_factor: f32,
+    // Fraction of a page's capacity below which it's rebalanced after a delete.
+    min_occupancy: f32,
+    // Order of the keys.
+    comparator: &'static dyn Comparator,
+    // Deletes mark rows with a tombstone in their leaf, instead of removing them, if set.
+    tombstone_deletes: bool,
+    // Updates that shrink a value leave the bytes freed in their leaf, if set.
+    in_place_shrink: bool,
+    // Keeps the root page pinned in the buffer pool, if set.
+    pin_root: bool,
+    // Saves keep every value saved under a key, instead of overwriting it, if set.
+    duplicate_keys: bool,
+}
+
+impl<B: StorageBackend> BTree<B> {
+    ///
+    /// Creates an empty BTree, allocating its root page. Keys are ordered lexicographically.
+    ///
+    /// # Arguments
+    /// * `buffer_manager`: Buffer pool that the pages of the tree are stored in.
+    ///
+    /// # Returns
+    /// * `Ok(BTree)` if the tree was created.
+    /// * `Err(std::io::Error)` if the root page couldn't be allocated, or the data file is
+    ///   ordered by another comparator.
+    ///
+    pub fn new(buffer_manager: BufferManager<B>) -> Result<Self, Error> {
+        Self::with_comparator(buffer_manager, &Lexicographic)
+    }
+
+    ///
+    /// Creates an empty BTree whose keys are ordered by `comparator`, allocating its root
+    /// page. The name of the comparator is recorded in the data file, and the tree can only
+    /// be reopened with a comparator of the same name.
+    ///
+    /// Keys that compare as equal are the same key: saving one replaces the value of the
+    /// other. `scan_prefix` matches key bytes, so it's only supported by lexicographic
+    /// comparators.
+    ///
+    /// # Arguments
+    /// * `buffer_manager`: Buffer pool that the pages of the tree are stored in.
+    /// * `comparator`: Order of the keys.
+    ///
+    /// # Returns
+    /// * `Ok(BTree)` if the tree was created.
+    /// * `Err(std::io::Error)` of kind `InvalidInput` if the data file is ordered by another
+    ///   comparator, or its name is too long.
+    /// * `Err(std::io::Error)` if the root page couldn't be allocated.
+    ///
+    pub fn with_comparator(
+        mut buffer_manager: BufferManager<B>,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self, Error> {
+        match buffer_manager.get_comparator_name() {
+            Some(name) if name != comparator.name() => {
+                return Err(RustyKVError::ComparatorMismatch.into());
+            }
+            Some(_) => {}
+            None => buffer_manager.set_comparator_name(comparator.name())?,
+        }
+        let root = buffer_manager.allocate_page()?;
+        let mut btree = BTree {
+            buffer_manager,
+            root,
+            fill_factor: DEFAULT_FILL_FACTOR,
+            min_occupancy: DEFAULT_MIN_OCCUPANCY,
+            comparator,
+            tombstone_deletes: false,
+            in_place_shrink: true,
+            pin_root: false,
+            duplicate_keys: false,
+        };
+        btree.write_node(root, PageType::Leaf, &[])?;
+        Ok(btree)
+    }
+
+    ///
+    /// Opens an existing BTree whose keys are ordered lexicographically.
+    ///
+    /// # Arguments
+    /// * `buffer_manager`: Buffer pool that the pages of the tree are stored in.
+    /// * `root`: Page ID of the root page, as returned by `root`.
+    ///
+    /// # Returns
+    /// * `Ok(BTree)`: The BTree rooted at `root`.
+    /// * `Err(std::io::Error)` of kind `InvalidInput` if the data file is ordered by another
+    ///   comparator.
+    ///
+    pub fn open(buffer_manager: BufferManager<B>, root: PageId) -> Result<Self, Error> {
+        Self::open_with_comparator(buffer_manager, root, &Lexicographic)
+    }
+
+    ///
+    /// Opens an existing BTree whose keys are ordered by `comparator`, see
+    /// `with_comparator`. Data files that don't record a comparator predate them, and are
+    /// ordered lexicographically.
+    ///
+    /// # Arguments
+    /// * `buffer_manager`: Buffer pool that the pages of the tree are stored in.
+    /// * `root`: Page ID of the root page, as returned by `root`.
+    /// * `comparator`: Order of the keys. Must have the name of the comparator the tree was
+    ///   created with.
+    ///
+    /// # Returns
+    /// * `Ok(BTree)`: The BTree rooted at `root`.
+    /// * `Err(std::io::Error)` of kind `InvalidInput` if the data file is ordered by another
+    ///   comparator.
+    ///
+    pub fn open_with_comparator(
+        buffer_manager: BufferManager<B>,
+        root: PageId,
+        comparator: &'static dyn Comparator,
+    ) -> Result<Self, Error> {
+        let name = buffer_manager
+            .get_comparator_name()
+            .unwrap_or(Lexicographic.name());
+        if name != comparator.name() {
+            return Err(RustyKVError::ComparatorMismatch.into());
+        }
+        Ok(BTree {
+            buffer_manager,
+            root,
+            fill_factor: DEFAULT_FILL_FACTOR,
+            min_occupancy: DEFAULT_MIN_OCCUPANCY,
+            comparator,
+            tombstone_deletes: false,
+            in_place_shrink: true,
+            pin_root: false,
+            duplicate_keys: false,
+        })
+    }
+
+    ///
+    /// Sets how much of a full page's rows, by size, stay in it when it's split, the rest
+    /// moving to the new sibling. The default, 0.5, splits pages evenly. Keys saved in
+    /// ascending order always go to the last page, so a higher fill factor leaves fewer,
+    /// fuller pages behind; random keys are better served by an even split.
+    ///
+    /// Only affects splits, not how rows are divided when pages are rebalanced after a
+    /// delete. The fill factor isn't stored in the data file.
+    ///
+    /// # Arguments
+    /// * `fill_factor`: Fraction of the rows left in the split page, between 0 and 1
+    ///   exclusive. Both pages always get at least one row, and no more than fits.
+    ///
+    pub fn set_fill_factor(&mut self, fill_factor: f32) {
+        assert!(
+            fill_factor > 0.0 && fill_factor < 1.0,
+            "Fill factor must be between 0 and 1"
+        );
+        self.fill_factor = fill_factor;
+    }
+
+    ///
+    /// Sets how full, as a fraction of its capacity, a page must stay after a delete. A page
+    /// left below it is merged with a sibling if their rows fit in one page, and borrows
+    /// rows from it otherwise. The default, 0.5, keeps pages at least half full; a lower
+    /// threshold rebalances less often, at the cost of a sparser tree. Applies to leaves and
+    /// internal pages alike, but not to the root, which has no siblings.
+    ///
+    /// The minimum occupancy isn't stored in the data file.
+    ///
+    /// # Arguments
+    /// * `min_occupancy`: Fraction of the page capacity, between 0 and 0.5 inclusive. At 0,
+    ///   pages are never rebalanced, even once empty. Above 0.5, two pages that just fail
+    ///   to merge could be rebalanced on every delete.
+    ///
+    pub fn set_min_occupancy(&mut self, min_occupancy: f32) {
+        assert!(
+            (0.0..=0.5).contains(&min_occupancy),
+            "Minimum occupancy must be between 0 and 0.5"
+        );
+        self.min_occupancy = min_occupancy;
+    }
+
+    ///
+    /// Sets whether deletes mark rows with a tombstone in their leaf, instead of removing
+    /// them and shifting the rest of the leaf's slot map. Tombstones are invisible to reads,
+    /// and are dropped in bulk when the leaf is compacted, see
+    /// `BTreePage::set_uses_tombstones`. Disabled by default, and not stored in the data
+    /// file.
+    ///
+    /// # Arguments
+    /// * `tombstone_deletes`: Whether to delete with tombstones.
+    ///
+    pub fn set_tombstone_deletes(&mut self, tombstone_deletes: bool) {
+        self.tombstone_deletes = tombstone_deletes;
+    }
+
+    ///
+    /// Sets whether updates that shrink a value leave the bytes freed in their leaf, to be
+    /// reclaimed once the leaf is fragmented enough, or compact the leaf right away. See
+    /// `BTreePage::set_allows_in_place_shrink` for the trade-off. Allowed by default, and
+    /// not stored in the data file.
+    ///
+    /// # Arguments
+    /// * `allow`: Whether to shrink values in place.
+    ///
+    pub fn set_allow_in_place_shrink(&mut self, allow: bool) {
+        self.in_place_shrink = allow;
+    }
+
+    ///
+    /// Sets whether `save` keeps every value saved under a key, like a secondary index does,
+    /// instead of overwriting it. The values of a key are fetched with `get_all`, in the
+    /// order they were saved; `get` only fetches the first. `delete` deletes all of them.
+    /// Disabled by default, and not stored in the data file, so it has to be set every time
+    /// the tree is opened, before anything is saved.
+    ///
+    /// Every row with a key is kept in the same leaf, so the rows of a key must fit in a
+    /// page. Saving a row that doesn't fails with `RustyKVError::InsufficientSpace`.
+    ///
+    /// # Arguments
+    /// * `allow`: Whether to keep duplicate keys.
+    ///
+    pub fn set_allow_duplicate_keys(&mut self, allow: bool) {
+        self.duplicate_keys = allow;
+    }
+
+    ///
+    /// Sets whether the root page is kept pinned in the buffer pool. Every lookup starts at
+    /// the root, so pinning it saves reading it back from disk after scans evicted it, at
+    /// the cost of a frame that can never be reused. The pin moves to the new root whenever
+    /// the tree grows or shrinks a level. Disabled by default, and not stored in the data
+    /// file.
+    ///
+    /// # Arguments
+    /// * `pin_root`: Whether to pin the root page.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the root was pinned or unpinned.
+    /// * `Err(std::io::Error)` if the root page couldn't be fetched, e.g. because every
+    ///   frame is pinned.
+    ///
+    pub fn set_pin_root(&mut self, pin_root: bool) -> Result<(), Error> {
+        if pin_root && !self.pin_root {
+            self.buffer_manager.pin(self.root)?;
+        } else if !pin_root && self.pin_root {
+            self.buffer_manager.unpin(self.root);
+        }
+        self.pin_root = pin_root;
+        Ok(())
+    }
+
+    ///
+    /// Returns the Page ID of the root page. The root changes as the tree grows and shrinks,
+    /// so it has to be read after the last write to reopen the tree with `open`.
+    ///
+    pub fn root(&self) -> PageId {
+        self.root
+    }
+
+    ///
+    /// Reports the layout of a page of the tree.
+    ///
+    /// # Arguments
+    /// * `page_id`: Page ID of a leaf or internal page, e.g. `root`.
+    ///
+    /// # Returns
+    /// * `Ok(PageStats)`: Row count, space usage and average row sizes of the page.
+    /// * `Err(std::io::Error)` if the page couldn't be fetched.
+    ///
+    pub fn page_stats(&mut self, page_id: PageId) -> Result<PageStats, Error> {
+        let mut data = self.read_node(page_id)?;
+        Ok(BTreePage::with_comparator(&mut data, self.comparator).stats())
+    }
+
+    ///
+    /// Writes every page of the tree modified in the buffer pool to the data file, e.g.
+    /// before the file is read by another process.
+    ///
+    /// # Returns
+    /// * `Ok(())` if every dirty page was written.
+    /// * `Err(std::io::Error)` if a page couldn't be written.
+    ///
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.buffer_manager.flush_all()
+    }
+
+    ///
+    /// Writes a consistent copy of the tree's data file to `path`. Dirty pages are flushed
+    /// to disk first.
+    ///
+    /// # Arguments
+    /// * `path`: Path the snapshot is written to. An existing file is replaced.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the snapshot was written.
+    /// * `Err(std::io::Error)` if an error occurred while flushing or copying the file.
+    ///
+    pub fn export_snapshot(&mut self, path: &Path) -> Result<(), Error> {
+        self.buffer_manager.export_snapshot(path)
+    }
+
+    ///
+    /// Fetches the value of a key.
+    ///
+    /// # Arguments
+    /// * `key`: Key to look up.
+    ///
+    /// # Returns
+    /// * `Ok(Some(value))` if the key is present, `Ok(None)` otherwise.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let leaf = self.find_leaf(key)?;
+        let mut data = self.read_node(leaf)?;
+        let page = BTreePage::with_comparator(&mut data, self.comparator);
+        match page.get(key) {
+            None => Ok(None),
+            Some(row) => self.decode_value(row.get_verified_value()?).map(Some),
+        }
+    }
+
+    ///
+    /// Fetches every value of a key, see `set_allow_duplicate_keys`.
+    ///
+    /// # Arguments
+    /// * `key`: Key to look up.
+    ///
+    /// # Returns
+    /// * `Ok(values)`: The values of the key, in the order they were saved. Empty if the
+    ///   key isn't present.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn get_all(&mut self, key: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        let leaf = self.find_leaf(key)?;
+        let mut data = self.read_node(leaf)?;
+        let page = BTreePage::with_comparator(&mut data, self.comparator);
+        page.get_all(key)
+            .map(|row| self.decode_value(row.get_verified_value()?))
+            .collect()
+    }
+
+    ///
+    /// Fetches the values of several keys. The keys are looked up in key order, so that
+    /// keys in the same leaf page are fetched one after another from the buffer pool.
+    ///
+    /// # Arguments
+    /// * `keys`: Keys to look up.
+    ///
+    /// # Returns
+    /// * `Ok(values)`: The value of each key, in the same order as `keys`. `None` for keys
+    ///   that aren't present.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn get_many(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by(|a, b| self.comparator.compare(keys[*a], keys[*b]));
+
+        let mut values = vec![None; keys.len()];
+        for index in order {
+            values[index] = self.get(keys[index])?;
+        }
+        Ok(values)
+    }
+
+    ///
+    /// Fetches the value of a key, or `default` if the key isn't present.
+    ///
+    /// # Arguments
+    /// * `key`: Key to look up.
+    /// * `default`: Value returned if the key isn't present.
+    ///
+    /// # Returns
+    /// * `Ok(value)`: The value of the key, or a copy of `default`.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn get_or(&mut self, key: &[u8], default: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(self.get(key)?.unwrap_or_else(|| default.to_vec()))
+    }
+
+    ///
+    /// Fetches the value of a key, or an empty value if the key isn't present.
+    ///
+    /// # Arguments
+    /// * `key`: Key to look up.
+    ///
+    /// # Returns
+    /// * `Ok(value)`: The value of the key, or an empty Vec.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn get_or_default(&mut self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(self.get(key)?.unwrap_or_default())
+    }
+
+    ///
+    /// Checks if a key exists in the tree. The key filter in the header of the leaf that
+    /// would hold the key is checked first, reading only the header if the leaf isn't in the
+    /// buffer pool, so most absent keys are ruled out without fetching the whole leaf.
+    ///
+    /// # Arguments
+    /// * `key`: Key to look up.
+    ///
+    /// # Returns
+    /// * `Ok(true)` if the key is present, `Ok(false)` otherwise.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    /// # Impl Note
+    /// The page checksum can't be verified from the header alone, so a corrupt leaf may
+    /// report a key absent without an error. Only trees ordered by a lexicographic
+    /// comparator have key filters.
+    ///
+    pub fn contains_key(&mut self, key: &[u8]) -> Result<bool, Error> {
+        let leaf = self.find_leaf(key)?;
+        if self.comparator.is_lexicographic() {
+            let mut head = vec![0u8; BTreePage::get_head_size(key)];
+            self.buffer_manager.read_page_head(leaf, &mut head)?;
+            if !BTreePage::head_may_contain(&head, key) {
+                return Ok(false);
+            }
+        }
+        let mut data = self.read_node(leaf)?;
+        Ok(BTreePage::with_comparator(&mut data, self.comparator).contains_key(key))
+    }
+
+    ///
+    /// Fetches every row whose key starts with a prefix. Only the subtrees that can hold
+    /// such keys are visited. Keys are matched byte by byte, so only trees ordered by a
+    /// lexicographic comparator keep them together.
+    ///
+    /// # Arguments
+    /// * `prefix`: Prefix of the keys to fetch. An empty prefix fetches every row.
+    ///
+    /// # Returns
+    /// * `Ok(rows)`: Iterator over the matching keys and their values, in key order.
+    /// * `Err(std::io::Error)` of kind `Unsupported` if the comparator isn't lexicographic.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn scan_prefix(
+        &mut self,
+        prefix: &[u8],
+    ) -> Result<impl Iterator<Item = (Vec<u8>, Vec<u8>)> + use<B>, Error> {
+        if !self.comparator.is_lexicographic() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "prefix scans require a lexicographic comparator",
+            ));
+        }
+        let mut rows = Vec::new();
+        self.scan_prefix_subtree(self.root, prefix, &mut rows)?;
+        Ok(rows.into_iter())
+    }
+
+    ///
+    /// Fetches every row whose key falls within `[start, end)`. Only the subtrees that can
+    /// hold such keys are visited.
+    ///
+    /// # Arguments
+    /// * `start`: Inclusive lower bound of the range.
+    /// * `end`: Exclusive upper bound of the range.
+    ///
+    /// # Returns
+    /// * `Ok(rows)`: Iterator over the keys in the range and their values, in key order.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn range(
+        &mut self,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<impl Iterator<Item = (Vec<u8>, Vec<u8>)> + use<B>, Error> {
+        let mut rows = Vec::new();
+        if self.comparator.compare(start, end) == Ordering::Less {
+            self.range_subtree(self.root, start, end, &mut rows)?;
+        }
+        Ok(rows.into_iter())
+    }
+
+    ///
+    /// Counts the rows whose key falls within `[start, end)`, e.g. for a dashboard. Only
+    /// the subtrees that can hold such keys are visited, and the bounds are searched in the
+    /// slot maps of their leaves, so no value is read.
+    ///
+    /// # Arguments
+    /// * `start`: Inclusive lower bound of the range.
+    /// * `end`: Exclusive upper bound of the range.
+    ///
+    /// # Returns
+    /// * `Ok(count)`: Number of rows in the range.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn count_range(&mut self, start: &[u8], end: &[u8]) -> Result<usize, Error> {
+        if self.comparator.compare(start, end) != Ordering::Less {
+            return Ok(0);
+        }
+        self.count_range_subtree(self.root, start, end)
+    }
+
+    ///
+    /// Fetches every row whose key falls within `[start, end)`, like `range`, in descending
+    /// key order.
+    ///
+    /// # Arguments
+    /// * `start`: Inclusive lower bound of the range.
+    /// * `end`: Exclusive upper bound of the range.
+    ///
+    /// # Returns
+    /// * `Ok(rows)`: Iterator over the keys in the range and their values, largest key first.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn range_rev(
+        &mut self,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<impl Iterator<Item = (Vec<u8>, Vec<u8>)> + use<B>, Error> {
+        let mut rows = Vec::new();
+        if self.comparator.compare(start, end) == Ordering::Less {
+            self.range_rev_subtree(self.root, start, end, &mut rows)?;
+        }
+        Ok(rows.into_iter())
+    }
+
+    ///
+    /// Fetches the rows with the `n` greatest keys, e.g. the latest entries of a tree keyed
+    /// by timestamp. Leaves are read from the last one, until `n` rows were found.
+    ///
+    /// # Arguments
+    /// * `n`: Number of rows to fetch.
+    ///
+    /// # Returns
+    /// * `Ok(rows)`: Iterator over up to `n` keys and their values, largest key first.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn last(
+        &mut self,
+        n: usize,
+    ) -> Result<impl Iterator<Item = (Vec<u8>, Vec<u8>)> + use<B>, Error> {
+        let mut rows = Vec::new();
+        if n == 0 {
+            return Ok(rows.into_iter());
+        }
+        for leaf in self.get_leaves()?.into_iter().rev() {
+            let mut data = self.read_node(leaf)?;
+            let page = BTreePage::with_comparator(&mut data, self.comparator);
+            for row in page.iter_rev().take(n - rows.len()) {
+                let value = self.decode_value(row.get_verified_value()?)?;
+                rows.push((row.get_key().into_owned(), value));
+            }
+            if rows.len() == n {
+                break;
+            }
+        }
+        Ok(rows.into_iter())
+    }
+
+    ///
+    /// Fetches every key in the tree, without the values, e.g. to list them for debugging.
+    /// Every leaf is read, so this is as expensive as a full scan.
+    ///
+    /// # Returns
+    /// * `Ok(keys)`: The keys, in key order.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn keys(&mut self) -> Result<Vec<Vec<u8>>, Error> {
+        let mut keys = Vec::new();
+        for leaf in self.get_leaves()? {
+            let mut data = self.read_node(leaf)?;
+            keys.append(&mut BTreePage::with_comparator(&mut data, self.comparator).keys());
+        }
+        Ok(keys)
+    }
+
+    ///
+    /// Counts the rows whose key is strictly less than `key`, which is also the position
+    /// `key` has, or would have, in key order. Only the slot maps of the leaves up to the
+    /// one `key` belongs in are searched, so no value is read.
+    ///
+    /// # Arguments
+    /// * `key`: Key to rank. It doesn't need to be present in the tree.
+    ///
+    /// # Returns
+    /// * `Ok(rank)`: Number of rows with a smaller key.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched.
+    ///
+    pub fn rank(&mut self, key: &[u8]) -> Result<usize, Error> {
+        let target = self.find_leaf(key)?;
+        let mut rank = 0;
+        for leaf in self.get_leaves()? {
+            let mut data = self.read_node(leaf)?;
+            rank += BTreePage::with_comparator(&mut data, self.comparator).rank(key);
+            if leaf == target {
+                break;
+            }
+        }
+        Ok(rank)
+    }
+
+    ///
+    /// Saves a key value. If the key already exists, its value is updated. Pages that
+    /// overflow are split in two, and the split propagates up the tree, growing a new root
+    /// if the old one was split. Values too large to fit in a row are moved to overflow
+    /// pages.
+    ///
+    /// # Arguments
+    /// * `key`: Key of the row to save.
+    /// * `value`: Value of the row to save.
+    ///
+    /// # Returns
+    /// * `Ok(SaveOutcome)`: Whether the key was inserted or its value updated.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched or written. If the key is too
+    ///   large to be stored, the error wraps `RustyKVError::InsufficientSpace`.
+    ///
+    pub fn save(&mut self, key: &[u8], value: &[u8]) -> Result<SaveOutcome, Error> {
+        if BTreePage::get_entry_size(key, &[0u8; OVERFLOW_POINTER_SIZE]) > MAX_ENTRY_SIZE {
+            return Err(Error::other(RustyKVError::InsufficientSpace));
+        }
+
+        let value = self.encode_value(key, value)?;
+        let (outcome, split) = match self.insert(self.root, key, &value) {
+            Ok(result) => result,
+            Err(error) => {
+                // Only a leaf that can't be split fails for lack of space, before anything
+                // is written, so the overflow pages of the value aren't referenced.
+                let error = RustyKVError::from(error);
+                if let (RustyKVError::InsufficientSpace, Some((first, _))) =
+                    (&error, Self::get_overflow(&value))
+                {
+                    self.free_overflow(first)?;
+                }
+                return Err(error.into());
+            }
+        };
+        if let Some((separator, right)) = split {
+            self.grow_root(separator, right)?;
+        }
+        Ok(outcome)
+    }
+
+    ///
+    /// Saves a batch of key values. The batch is sorted by key first, so consecutive rows
+    /// land in the same leaf page, and its path from the root stays in the buffer pool. If
+    /// a key appears more than once in the batch, the last value wins.
+    ///
+    /// # Arguments
+    /// * `items`: Key values to save.
+    ///
+    /// # Returns
+    /// * `Ok(())` if every row was saved.
+    /// * `Err(std::io::Error)` if a row couldn't be saved. Rows before it in key order
+    ///   have been saved.
+    ///
+    pub fn save_batch(
+        &mut self,
+        items: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    ) -> Result<(), Error> {
+        let mut items: Entries = items.into_iter().collect();
+        // The sort is stable, so repeated keys are saved in batch order.
+        items.sort_by(|(a, _), (b, _)| self.comparator.compare(a, b));
+        for (key, value) in items {
+            self.save(&key, &value)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Applies every operation in a batch, in order, all-or-nothing. The previous values of
+    /// each key are recorded before it is changed, and if an operation fails, the operations
+    /// before it are undone.
+    ///
+    /// # Arguments
+    /// * `batch`: Puts and deletes to apply.
+    ///
+    /// # Returns
+    /// * `Ok(())` if every operation was applied.
+    /// * `Err(std::io::Error)` from the operation that failed. The tree is left as it was
+    ///   before the batch.
+    /// * `Err(std::io::Error)` of kind `Other` wrapping `RustyKVError::RollbackFailed` with
+    ///   the error of the operation that failed and the first error undoing the operations
+    ///   before it, e.g. an I/O error. Every operation is still undone if it can be, but the
+    ///   tree may be left with some of the batch applied.
+    ///
+    /// TODO: Without a write-ahead log, a crash midway through a batch leaves the
+    ///       operations applied so far in the data file.
+    ///
+    pub fn apply_batch(&mut self, batch: WriteBatch<Vec<u8>, Vec<u8>>) -> Result<(), Error> {
+        let mut undo_log: Vec<(Vec<u8>, Vec<Vec<u8>>)> = Vec::new();
+        for operation in batch {
+            let key = match &operation {
+                BatchOperation::Put(key, _) | BatchOperation::Delete(key) => key.clone(),
+            };
+            // Every value is recorded, in case the key is duplicated.
+            let result = self.get_all(&key).and_then(|previous| {
+                match &operation {
+                    BatchOperation::Put(key, value) => self.save(key, value).map(|_| ()),
+                    BatchOperation::Delete(key) => self.delete(key),
+                }?;
+                undo_log.push((key, previous));
+                Ok(())
+            });
+            if let Err(error) = result {
+                return match self.undo(undo_log) {
+                    Ok(()) => Err(error),
+                    Err(rollback_error) => {
+                        Err(RustyKVError::RollbackFailed(error, rollback_error).into())
+                    }
+                };
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Restores the values keys had before a batch, from the last one changed. Every key is
+    /// restored, even after one fails to be.
+    ///
+    /// # Returns
+    /// * `Ok(())` if every key was restored.
+    /// * `Err(std::io::Error)`: The first error restoring a key.
+    ///
+    fn undo(&mut self, undo_log: Vec<(Vec<u8>, Vec<Vec<u8>>)>) -> Result<(), Error> {
+        let mut result = Ok(());
+        for (key, previous) in undo_log.into_iter().rev() {
+            let restored = self.delete(&key).and_then(|()| {
+                previous
+                    .iter()
+                    .try_for_each(|value| self.save(&key, value).map(|_| ()))
+            });
+            result = result.and(restored);
+        }
+        result
+    }
+
+    ///
+    /// Deletes a key from the tree if it exists. Pages that end up less than half full
+    /// borrow rows from a sibling, or are merged into it, and the rebalancing propagates up
+    /// the tree. Merged pages are deallocated, and the tree shrinks by one level when the
+    /// root is left with a single child.
+    ///
+    /// # Arguments
+    /// * `key`: Key to be deleted.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the key was deleted, or wasn't present.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched, written or deallocated.
+    ///
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        match self.remove(self.root, key)? {
+            Rebalance::Balanced => {}
+            // The root doesn't have siblings, so it is allowed to be under the minimum
+            // occupancy.
+            Rebalance::Underflow => self.shrink_root()?,
+            // Replacing a separator in the root with a longer key can overflow it.
+            Rebalance::Split(separator, right) => self.grow_root(separator, right)?,
+        }
+        Ok(())
+    }
+
+    ///
+    /// Deletes several keys from the tree, rebalancing each page once at the end instead of
+    /// after every key. The keys are sorted first, and every key of a leaf is removed from it
+    /// at once. Then, going back up the tree, each internal page rebalances the children left
+    /// less than `min_occupancy` full with a sibling, as `delete` does, once per child.
+    ///
+    /// A page is only rebalanced once, so after deleting most of its rows and those of its
+    /// siblings, it may be left below `min_occupancy` until it's rebalanced again by a later
+    /// delete.
+    ///
+    /// # Arguments
+    /// * `keys`: Keys to be deleted. Keys that aren't present, or appear more than once, are
+    ///   skipped.
+    ///
+    /// # Returns
+    /// * `Ok(count)`: Number of keys deleted.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched, written or deallocated.
+    ///
+    pub fn delete_batch(&mut self, keys: &[&[u8]]) -> Result<usize, Error> {
+        let mut keys = keys.to_vec();
+        keys.sort_by(|a, b| self.comparator.compare(a, b));
+        keys.dedup_by(|a, b| self.comparator.compare(a, b) == Ordering::Equal);
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        let (deleted, rebalance) = self.remove_batch(self.root, &keys)?;
+        match rebalance {
+            Rebalance::Balanced => {}
+            Rebalance::Underflow => self.shrink_root()?,
+            Rebalance::Split(separator, right) => self.grow_root(separator, right)?,
+        }
+        Ok(deleted)
+    }
+
+    ///
+    /// Deletes every key that falls within `[start, end)`, e.g. to expire a range of
+    /// timestamped keys. The keys are copied out of the leaves first, since deleting them
+    /// rewrites the leaves, and then deleted with `delete_batch`.
+    ///
+    /// # Arguments
+    /// * `start`: Inclusive lower bound of the range.
+    /// * `end`: Exclusive upper bound of the range.
+    ///
+    /// # Returns
+    /// * `Ok(count)`: Number of keys deleted.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched, written or deallocated.
+    ///
+    pub fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<usize, Error> {
+        let mut rows = Vec::new();
+        if self.comparator.compare(start, end) == Ordering::Less {
+            self.collect_range_subtree(self.root, start, end, &mut rows)?;
+        }
+        let keys: Vec<&[u8]> = rows.iter().map(|(key, _)| key.as_slice()).collect();
+        self.delete_batch(&keys)
+    }
+
+    ///
+    /// Removes every key from the tree. Every page other than the root, including overflow
+    /// pages, is deallocated, and the root is left as an empty leaf.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the tree was cleared.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched, written or deallocated.
+    ///
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.free_subtree(self.root)?;
+        let mut data = self.read_node(self.root)?;
+        let mut page = BTreePage::with_comparator(&mut data, self.comparator);
+        page.clear();
+        page.set_page_type(PageType::Leaf);
+        self.write_page(self.root, &data)
+    }
+
+    ///
+    /// Compacts every leaf, reclaiming the space left behind by deleted rows and updated
+    /// values, and dropping tombstones. Leaves are compacted as they fill up anyway, so this
+    /// is only needed to reclaim the space of leaves that won't be written to again, e.g.
+    /// before `export_snapshot`. Leaves aren't rebalanced, so dropping tombstones may leave
+    /// them less than `min_occupancy` full.
+    ///
+    /// # Returns
+    /// * `Ok(count)`: Number of leaves compacted.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched or written.
+    ///
+    pub fn compact(&mut self) -> Result<usize, Error> {
+        let mut compacted = 0;
+        for leaf in self.get_leaves()? {
+            let mut data = self.read_node(leaf)?;
+            let mut page = BTreePage::with_comparator(&mut data, self.comparator);
+            if page.fragmentation_ratio() > 0.0 {
+                page.compact();
+                self.write_page(leaf, &data)?;
+                compacted += 1;
+            }
+        }
+        Ok(compacted)
+    }
+
+    ///
+    /// Checks that the pages reachable from the root form a valid tree, e.g. after opening a
+    /// data file that wasn't closed cleanly. Every page is read, so this takes as long as a
+    /// full scan.
+    ///
+    /// Every page must be reachable from the root, so the pages of any other tree stored in
+    /// the same data file are reported as orphaned.
+    ///
+    /// # Returns
+    /// * `Ok(())` if no problem was found.
+    /// * `Err(errors)`: Every problem found. Pages below a page that couldn't be read, or
+    ///   whose layout is invalid, aren't checked.
+    ///
+    pub fn check_integrity(&mut self) -> Result<(), Vec<IntegrityError>> {
+        let mut errors = Vec::new();
+        let mut referenced = HashSet::new();
+        self.check_subtree(self.root, b"", None, &mut referenced, &mut errors);
+        errors.extend(
+            self.buffer_manager
+                .allocated_pages()
+                .into_iter()
+                .filter(|page_id| !referenced.contains(page_id))
+                .map(IntegrityError::OrphanedPage),
+        );
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    ///
+    /// Checks a page and the pages below it, including overflow pages, recording the Page ID
+    /// of every page visited. The keys of the page must fall within `[lower, upper)`, the
+    /// range its parent's separators assign to it.
+    ///
+    fn check_subtree(
+        &mut self,
+        page_id: PageId,
+        lower: &[u8],
+        upper: Option<&[u8]>,
+        referenced: &mut HashSet<PageId>,
+        errors: &mut Vec<IntegrityError>,
+    ) {
+        // A page seen before has been checked already, or is an ancestor of this one.
+        if !referenced.insert(page_id) {
+            errors.push(IntegrityError::ReferencedTwice(page_id));
+            return;
+        }
+        let mut data = match self.read_node(page_id) {
+            Ok(data) => data,
+            Err(error) => {
+                errors.push(IntegrityError::Unreadable(page_id, error.into()));
+                return;
+            }
+        };
+        if !BTreePage::is_well_formed(&data) {
+            errors.push(IntegrityError::MalformedPage(page_id));
+            return;
+        }
+        let page = BTreePage::with_comparator(&mut data, self.comparator);
+        let page_type = page.get_page_type();
+        let entries = Self::get_entries(&page);
+        let ordered =
+            entries.windows(2).all(
+                |pair| match self.comparator.compare(&pair[0].0, &pair[1].0) {
+                    Ordering::Less => true,
+                    Ordering::Equal => page.allows_duplicate_keys(),
+                    Ordering::Greater => false,
+                },
+            );
+        if !ordered {
+            errors.push(IntegrityError::KeysOutOfOrder(page_id));
+        }
+        let in_bounds = |key: &[u8]| {
+            self.comparator.compare(key, lower) != Ordering::Less
+                && upper.is_none_or(|upper| self.comparator.compare(key, upper) == Ordering::Less)
+        };
+
+        match page_type {
+            PageType::Leaf => {
+                if !entries.iter().all(|(key, _)| in_bounds(key)) {
+                    errors.push(IntegrityError::KeyOutOfBounds(page_id));
+                }
+                for (_, value) in &entries {
+                    if let Some((first, length)) = Self::get_overflow(value) {
+                        self.check_overflow(first, length, referenced, errors);
+                    }
+                }
+            }
+            PageType::Internal => {
+                // The first separator is empty, and stands for the lower bound.
+                let well_formed = entries.first().is_some_and(|(key, _)| key.is_empty())
+                    && entries
+                        .iter()
+                        .all(|(_, child)| child.len() == CHILD_POINTER_SIZE);
+                if !well_formed {
+                    errors.push(IntegrityError::MalformedPage(page_id));
+                    return;
+                }
+                if !entries[1..].iter().all(|(key, _)| in_bounds(key)) {
+                    errors.push(IntegrityError::KeyOutOfBounds(page_id));
+                }
+                for (index, (separator, child)) in entries.iter().enumerate() {
+                    let child_lower = if index == 0 { lower } else { separator };
+                    let child_upper = entries.get(index + 1).map(|(key, _)| key.as_slice());
+                    self.check_subtree(
+                        Self::decode_child(child),
+                        child_lower,
+                        child_upper.or(upper),
+                        referenced,
+                        errors,
+                    );
+                }
+            }
+        }
+    }
+
+    ///
+    /// Checks that a chain of overflow pages is long enough to hold a value, recording the
+    /// Page ID of every page in it.
+    ///
+    fn check_overflow(
+        &mut self,
+        first: PageId,
+        length: usize,
+        referenced: &mut HashSet<PageId>,
+        errors: &mut Vec<IntegrityError>,
+    ) {
+        let mut page_id = first;
+        for _ in 0..length.div_ceil(OVERFLOW_DATA_SIZE) {
+            if !referenced.insert(page_id) {
+                errors.push(IntegrityError::ReferencedTwice(page_id));
+                return;
+            }
+            match self.read_node(page_id) {
+                Ok(data) => page_id = Self::get_overflow_next(&data),
+                Err(error) => {
+                    errors.push(IntegrityError::Unreadable(page_id, error.into()));
+                    return;
+                }
+            }
+        }
+    }
+
+    ///
+    /// Appends the rows of a subtree whose keys start with a prefix, in key order.
+    ///
+    fn scan_prefix_subtree(
+        &mut self,
+        page_id: PageId,
+        prefix: &[u8],
+        rows: &mut Entries,
+    ) -> Result<(), Error> {
+        let mut data = self.read_node(page_id)?;
+        let page = BTreePage::with_comparator(&mut data, self.comparator);
+        match page.get_page_type() {
+            // Every key of a leaf starts with its prefix, so if that starts with the prefix
+            // scanned, every row matches.
+            PageType::Leaf if page.get_prefix().starts_with(prefix) => {
+                for row in page.iter() {
+                    let value = self.decode_value(row.get_value())?;
+                    rows.push((row.get_key().into_owned(), value));
+                }
+            }
+            PageType::Leaf => {
+                let mut cursor = page.cursor();
+                cursor.seek(prefix);
+                while let (Some(key), Some(value)) = (cursor.key(), cursor.value()) {
+                    if !key.starts_with(prefix) {
+                        break;
+                    }
+                    rows.push((key.into_owned(), self.decode_value(value)?));
+                    cursor.next();
+                }
+            }
+            PageType::Internal => {
+                // Separators after the child holding the prefix are greater than it. Once one
+                // doesn't start with the prefix, neither does any key in the children after.
+                let entries = Self::get_entries(&page);
+                let start = self.get_child_index(&entries, prefix);
+                for (index, (separator, child)) in entries.iter().enumerate().skip(start) {
+                    if index > start && !separator.starts_with(prefix) {
+                        break;
+                    }
+                    self.scan_prefix_subtree(Self::decode_child(child), prefix, rows)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Returns the Page IDs of the leaves, in key order. Only internal pages are read, and
+    /// the first page of the leaf level.
+    ///
+    fn get_leaves(&mut self) -> Result<Vec<PageId>, Error> {
+        let (root, comparator) = (self.root, self.comparator);
+        Self::get_leaves_with(root, comparator, |page_id| self.read_node(page_id))
+    }
+
+    ///
+    /// Returns the Page IDs of the leaves below `root`, in key order. Pages are read with
+    /// `read_page`.
+    ///
+    fn get_leaves_with(
+        root: PageId,
+        comparator: &dyn Comparator,
+        mut read_page: impl FnMut(PageId) -> Result<[u8; PAGE_SIZE], Error>,
+    ) -> Result<Vec<PageId>, Error> {
+        let mut level = vec![root];
+        loop {
+            let mut children = Vec::new();
+            for page_id in &level {
+                let mut data = read_page(*page_id)?;
+                let page = BTreePage::with_comparator(&mut data, comparator);
+                // Every leaf is at the same depth, so the whole level is.
+                if page.get_page_type() == PageType::Leaf {
+                    return Ok(level);
+                }
+                children.extend(
+                    Self::get_entries(&page)
+                        .iter()
+                        .map(|(_, child)| Self::decode_child(child)),
+                );
+            }
+            level = children;
+        }
+    }
+
+    ///
+    /// Collects the rows of a subtree whose key falls within `[start, end)`, in key order.
+    ///
+    fn range_subtree(
+        &mut self,
+        page_id: PageId,
+        start: &[u8],
+        end: &[u8],
+        rows: &mut Entries,
+    ) -> Result<(), Error> {
+        let mut data = self.read_node(page_id)?;
+        let page = BTreePage::with_comparator(&mut data, self.comparator);
+        match page.get_page_type() {
+            PageType::Leaf => {
+                for row in page.range(start, end) {
+                    let value = self.decode_value(row.get_verified_value()?)?;
+                    rows.push((row.get_key().into_owned(), value));
+                }
+            }
+            PageType::Internal => {
+                // Once a separator is at or past the end, so is every key in its child and
+                // the children after it.
+                let entries = Self::get_entries(&page);
+                let first = self.get_child_index(&entries, start);
+                for (index, (separator, child)) in entries.iter().enumerate().skip(first) {
+                    if index > first && self.comparator.compare(separator, end) != Ordering::Less {
+                        break;
+                    }
+                    self.range_subtree(Self::decode_child(child), start, end, rows)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Copies the rows of a subtree whose key falls within `[start, end)`, in key order,
+    /// without decoding their values.
+    ///
+    fn collect_range_subtree(
+        &mut self,
+        page_id: PageId,
+        start: &[u8],
+        end: &[u8],
+        rows: &mut Entries,
+    ) -> Result<(), Error> {
+        let mut data = self.read_node(page_id)?;
+        let page = BTreePage::with_comparator(&mut data, self.comparator);
+        if page.get_page_type() == PageType::Leaf {
+            rows.append(&mut page.collect_range(start, end));
+            return Ok(());
+        }
+        let entries = Self::get_entries(&page);
+        let first = self.get_child_index(&entries, start);
+        for (index, (separator, child)) in entries.iter().enumerate().skip(first) {
+            if index > first && self.comparator.compare(separator, end) != Ordering::Less {
+                break;
+            }
+            self.collect_range_subtree(Self::decode_child(child), start, end, rows)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Counts the rows of a subtree whose key falls within `[start, end)`.
+    ///
+    fn count_range_subtree(
+        &mut self,
+        page_id: PageId,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<usize, Error> {
+        let mut data = self.read_node(page_id)?;
+        let page = BTreePage::with_comparator(&mut data, self.comparator);
+        if page.get_page_type() == PageType::Leaf {
+            return Ok(page.count_range(start, end));
+        }
+        let entries = Self::get_entries(&page);
+        let first = self.get_child_index(&entries, start);
+        let mut count = 0;
+        for (index, (separator, child)) in entries.iter().enumerate().skip(first) {
+            if index > first && self.comparator.compare(separator, end) != Ordering::Less {
+                break;
+            }
+            count += self.count_range_subtree(Self::decode_child(child), start, end)?;
+        }
+        Ok(count)
+    }
+
+    ///
+    /// Collects the rows of a subtree whose key falls within `[start, end)`, in descending
+    /// key order.
+    ///
+    fn range_rev_subtree(
+        &mut self,
+        page_id: PageId,
+        start: &[u8],
+        end: &[u8],
+        rows: &mut Entries,
+    ) -> Result<(), Error> {
+        let mut data = self.read_node(page_id)?;
+        let page = BTreePage::with_comparator(&mut data, self.comparator);
+        match page.get_page_type() {
+            PageType::Leaf => {
+                for row in page.range_rev(start, end) {
+                    let value = self.decode_value(row.get_verified_value()?)?;
+                    rows.push((row.get_key().into_owned(), value));
+                }
+            }
+            PageType::Internal => {
+                // The children from the one holding the start up to the last one whose
+                // separator is before the end, visited from the last.
+                let entries = Self::get_entries(&page);
+                let first = self.get_child_index(&entries, start);
+                let last = first
+                    + entries[first + 1..]
+                        .iter()
+                        .take_while(|(separator, _)| {
+                            self.comparator.compare(separator, end) == Ordering::Less
+                        })
+                        .count();
+                for (_, child) in entries[first..=last].iter().rev() {
+                    self.range_rev_subtree(Self::decode_child(child), start, end, rows)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Deallocates every page below a page, and the overflow pages of its rows. The page
+    /// itself is kept.
+    ///
+    fn free_subtree(&mut self, page_id: PageId) -> Result<(), Error> {
+        let mut data = self.read_node(page_id)?;
+        let page = BTreePage::with_comparator(&mut data, self.comparator);
+        let page_type = page.get_page_type();
+        for (_, value) in Self::get_entries(&page) {
+            match page_type {
+                PageType::Leaf => {
+                    if let Some((first, _)) = Self::get_overflow(&value) {
+                        self.free_overflow(first)?;
+                    }
+                }
+                PageType::Internal => {
+                    let child = Self::decode_child(&value);
+                    self.free_subtree(child)?;
+                    self.buffer_manager.deallocate_page(child)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Deletes a key from the subtree rooted at a page.
+    ///
+    /// # Returns
+    /// * `Ok(Rebalance)`: Whether the parent page needs to rebalance the page.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched, written or deallocated.
+    ///
+    fn remove(&mut self, page_id: PageId, key: &[u8]) -> Result<Rebalance, Error> {
+        let mut data = self.read_node(page_id)?;
+        let mut page = BTreePage::with_comparator(&mut data, self.comparator);
+        if page.get_page_type() == PageType::Leaf {
+            if !page.contains_key(key) {
+                return Ok(Rebalance::Balanced);
+            }
+            let overflows = Self::get_overflows(&page, key);
+            page.set_uses_tombstones(self.tombstone_deletes);
+            page.delete(key).map_err(Error::other)?;
+            let used_space = page.get_used_space();
+            self.write_page(page_id, &data)?;
+            for first in overflows {
+                self.free_overflow(first)?;
+            }
+            return Ok(self.get_rebalance(used_space));
+        }
+
+        let mut entries = Self::get_entries(&page);
+        let child_index = self.get_child_index(&entries, key);
+        match self.remove(Self::decode_child(&entries[child_index].1), key)? {
+            Rebalance::Balanced => return Ok(Rebalance::Balanced),
+            Rebalance::Underflow => self.rebalance_children(&mut entries, child_index)?,
+            Rebalance::Split(separator, right) => {
+                entries.insert(child_index + 1, (separator, Self::encode_child(right)))
+            }
+        }
+
+        if Self::get_size(&entries) > BTreePage::get_capacity() {
+            let (separator, right) = self.split(page_id, PageType::Internal, entries)?;
+            return Ok(Rebalance::Split(separator, right));
+        }
+        self.write_node(page_id, PageType::Internal, &entries)?;
+        Ok(self.get_rebalance(Self::get_size(&entries)))
+    }
+
+    ///
+    /// Deletes keys from the subtree rooted at a page, rebalancing the children of each
+    /// internal page once, after every key below it was deleted.
+    ///
+    /// # Arguments
+    /// * `page_id`: Page ID of the root of the subtree.
+    /// * `keys`: Keys to be deleted, sorted and without duplicates.
+    ///
+    /// # Returns
+    /// * `Ok((count, Rebalance))`: Number of keys deleted, and whether the parent page needs
+    ///   to rebalance the page.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched, written or deallocated.
+    ///
+    fn remove_batch(
+        &mut self,
+        page_id: PageId,
+        keys: &[&[u8]],
+    ) -> Result<(usize, Rebalance), Error> {
+        let mut data = self.read_node(page_id)?;
+        let mut page = BTreePage::with_comparator(&mut data, self.comparator);
+        if page.get_page_type() == PageType::Leaf {
+            page.set_uses_tombstones(self.tombstone_deletes);
+            let mut deleted = 0;
+            let mut overflows = Vec::new();
+            for key in keys {
+                if !page.contains_key(key) {
+                    continue;
+                }
+                overflows.append(&mut Self::get_overflows(&page, key));
+                page.delete(key).map_err(Error::other)?;
+                deleted += 1;
+            }
+            if deleted == 0 {
+                return Ok((0, Rebalance::Balanced));
+            }
+            let used_space = page.get_used_space();
+            self.write_page(page_id, &data)?;
+            for first in overflows {
+                self.free_overflow(first)?;
+            }
+            return Ok((deleted, self.get_rebalance(used_space)));
+        }
+
+        // The keys are sorted, so the keys of each child are consecutive.
+        let mut entries = Self::get_entries(&page);
+        let mut deleted = 0;
+        let mut results = Vec::new();
+        let mut start = 0;
+        while start < keys.len() {
+            let child_index = self.get_child_index(&entries, keys[start]);
+            let end = start
+                + keys[start..]
+                    .iter()
+                    .take_while(|key| self.get_child_index(&entries, key) == child_index)
+                    .count();
+            let child = Self::decode_child(&entries[child_index].1);
+            let (count, rebalance) = self.remove_batch(child, &keys[start..end])?;
+            deleted += count;
+            results.push((child_index, rebalance));
+            start = end;
+        }
+        if results
+            .iter()
+            .all(|(_, rebalance)| matches!(rebalance, Rebalance::Balanced))
+        {
+            return Ok((deleted, Rebalance::Balanced));
+        }
+
+        // New siblings are added first, from the last, so that the indices of the children
+        // before them hold. Merges remove children, so underflowing children are then looked
+        // up by Page ID, and skipped if they were already merged into their left sibling.
+        let mut underflows = Vec::new();
+        for (child_index, rebalance) in results.into_iter().rev() {
+            match rebalance {
+                Rebalance::Balanced => {}
+                Rebalance::Underflow => {
+                    underflows.push(Self::decode_child(&entries[child_index].1))
+                }
+                Rebalance::Split(separator, right) => {
+                    entries.insert(child_index + 1, (separator, Self::encode_child(right)))
+                }
+            }
+        }
+        for child in underflows.into_iter().rev() {
+            if let Some(child_index) = entries
+                .iter()
+                .position(|(_, value)| Self::decode_child(value) == child)
+            {
+                self.rebalance_children(&mut entries, child_index)?;
+            }
+        }
+
+        if Self::get_size(&entries) > BTreePage::get_capacity() {
+            let (separator, right) = self.split(page_id, PageType::Internal, entries)?;
+            return Ok((deleted, Rebalance::Split(separator, right)));
+        }
+        self.write_node(page_id, PageType::Internal, &entries)?;
+        Ok((deleted, self.get_rebalance(Self::get_size(&entries))))
+    }
+
+    ///
+    /// Rebalances a child, of an internal page, that is less than half full with one of its
+    /// siblings. If the rows of both fit in a single page, the right one is merged into the
+    /// left one and deallocated. Otherwise the rows are redistributed evenly between them.
+    ///
+    /// # Arguments
+    /// * `entries`: Rows of the internal page. The separators are updated in place.
+    /// * `child_index`: Index of the row pointing to the child.
+    ///
+    fn rebalance_children(
+        &mut self,
+        entries: &mut Entries,
+        child_index: usize,
+    ) -> Result<(), Error> {
+        if entries.len() < 2 {
+            // The child has no siblings. This only happens under a root that is about to
+            // be shrunk.
+            return Ok(());
+        }
+        let right_index = child_index.max(1);
+        let left = Self::decode_child(&entries[right_index - 1].1);
+        let right = Self::decode_child(&entries[right_index].1);
+
+        let mut left_data = self.read_node(left)?;
+        let left_page = BTreePage::with_comparator(&mut left_data, self.comparator);
+        let page_type = left_page.get_page_type();
+        let mut combined = Self::get_entries(&left_page);
+
+        let mut right_data = self.read_node(right)?;
+        let mut right_entries = Self::get_entries(&BTreePage::with_comparator(
+            &mut right_data,
+            self.comparator,
+        ));
+        if page_type == PageType::Internal {
+            // The first child of an internal page has an empty key. Pull the separator down
+            // from the parent to keep the combined rows ordered.
+            right_entries[0].0 = entries[right_index].0.clone();
+        }
+        combined.append(&mut right_entries);
+
+        if Self::get_size(&combined) <= BTreePage::get_capacity() {
+            self.write_node(left, page_type, &combined)?;
+            entries.remove(right_index);
+            self.buffer_manager.deallocate_page(right)?;
+        } else {
+            // The rows were split between the two pages before, so they can be again.
+            let (separator, right_entries) = Self::split_entries(
+                page_type,
+                &mut combined,
+                DEFAULT_FILL_FACTOR,
+                self.comparator,
+            )
+            .ok_or(RustyKVError::InsufficientSpace)?;
+            self.write_node(left, page_type, &combined)?;
+            self.write_node(right, page_type, &right_entries)?;
+            entries[right_index].0 = separator;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Adds a level to the tree, with a new root pointing to the old root and its new
+    /// sibling.
+    ///
+    fn grow_root(&mut self, separator: Vec<u8>, right: PageId) -> Result<(), Error> {
+        let new_root = self.buffer_manager.allocate_page()?;
+        self.write_node(
+            new_root,
+            PageType::Internal,
+            &[
+                (Vec::new(), Self::encode_child(self.root)),
+                (separator, Self::encode_child(right)),
+            ],
+        )?;
+        self.set_root(new_root)
+    }
+
+    ///
+    /// Removes levels from the tree while the root is an internal page with a single child.
+    ///
+    fn shrink_root(&mut self) -> Result<(), Error> {
+        loop {
+            let mut data = self.read_node(self.root)?;
+            let page = BTreePage::with_comparator(&mut data, self.comparator);
+            if page.get_page_type() == PageType::Leaf || page.iter().nth(1).is_some() {
+                return Ok(());
+            }
+            let old_root = self.root;
+            self.set_root(Self::get_child(&page, b""))?;
+            self.buffer_manager.deallocate_page(old_root)?;
+        }
+    }
+
+    ///
+    /// Replaces the root page, moving the pin on it if the root is pinned.
+    ///
+    fn set_root(&mut self, root: PageId) -> Result<(), Error> {
+        if self.pin_root {
+            self.buffer_manager.pin(root)?;
+            self.buffer_manager.unpin(self.root);
+        }
+        self.root = root;
+        Ok(())
+    }
+
+    ///
+    /// Saves a key value in the subtree rooted at a page.
+    ///
+    /// # Returns
+    /// * `Ok((outcome, None))` if the row was saved without splitting the page. `outcome`
+    ///   tells if the key was inserted in its leaf or updated.
+    /// * `Ok((outcome, Some((separator, right))))` if the page was split. `right` holds
+    ///   every key greater than or equal to `separator`, and needs to be added to the
+    ///   parent page.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched or written.
+    ///
+    fn insert(
+        &mut self,
+        page_id: PageId,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(SaveOutcome, Option<Split>), Error> {
+        let mut data = self.read_node(page_id)?;
+        let mut page = BTreePage::with_comparator(&mut data, self.comparator);
+        let page_type = page.get_page_type();
+
+        // Leaf pages store the row itself. Internal pages only store a pointer to the new
+        // page if the child was split, and pass on whether the leaf inserted the key.
+        let (key, value, child_outcome) = match page_type {
+            PageType::Leaf => {
+                page.set_allows_in_place_shrink(self.in_place_shrink);
+                page.set_allows_duplicate_keys(self.duplicate_keys);
+                (key.to_vec(), value.to_vec(), None)
+            }
+            PageType::Internal => {
+                let child = Self::get_child(&page, key);
+                match self.insert(child, key, value)? {
+                    (outcome, None) => return Ok((outcome, None)),
+                    (outcome, Some((separator, right))) => {
+                        (separator, Self::encode_child(right), Some(outcome))
+                    }
+                }
+            }
+        };
+
+        // The overflow pages of the value being replaced are freed once the row is saved.
+        // With duplicate keys, no value is replaced.
+        let previous_overflow = match page_type {
+            PageType::Leaf if !self.duplicate_keys => page
+                .get(&key)
+                .and_then(|row| Self::get_overflow(row.get_value())),
+            PageType::Leaf | PageType::Internal => None,
+        };
+
+        let page_allows_duplicate_keys = page.allows_duplicate_keys();
+        let (outcome, split) = match page.save(&key, &value) {
+            Ok(outcome) => {
+                self.write_page(page_id, &data)?;
+                (outcome, None)
+            }
+            Err(RustyKVError::InsufficientSpace) => {
+                let mut entries =
+                    Self::get_entries(&BTreePage::with_comparator(&mut data, self.comparator));
+                let outcome = match entries
+                    .binary_search_by(|(entry_key, _)| self.comparator.compare(entry_key, &key))
+                {
+                    Ok(index) if !page_allows_duplicate_keys => {
+                        entries[index].1 = value;
+                        SaveOutcome::Updated
+                    }
+                    _ => {
+                        // Duplicate keys go after the existing ones, as in the page.
+                        let index = entries.partition_point(|(entry_key, _)| {
+                            self.comparator.compare(entry_key, &key) != Ordering::Greater
+                        });
+                        entries.insert(index, (key, value));
+                        SaveOutcome::Inserted
+                    }
+                };
+                (outcome, Some(self.split(page_id, page_type, entries)?))
+            }
+            Err(error) => return Err(Error::other(error)),
+        };
+
+        if let Some((first, _)) = previous_overflow {
+            self.free_overflow(first)?;
+        }
+        Ok((child_outcome.unwrap_or(outcome), split))
+    }
+
+    ///
+    /// Splits the rows of a page, that no longer fit in it, across the page and a newly
+    /// allocated sibling. The rows are divided according to the fill factor.
+    ///
+    /// # Returns
+    /// * `Ok((separator, right))`: The first key of the new sibling, and its Page ID.
+    /// * `Err(std::io::Error)` wrapping `RustyKVError::InsufficientSpace` if the rows of a
+    ///   duplicate key don't fit in a page. Nothing is written.
+    /// * `Err(std::io::Error)` if a page couldn't be allocated or written.
+    ///
+    fn split(
+        &mut self,
+        page_id: PageId,
+        page_type: PageType,
+        mut entries: Entries,
+    ) -> Result<Split, Error> {
+        let (separator, right_entries) =
+            Self::split_entries(page_type, &mut entries, self.fill_factor, self.comparator)
+                .ok_or(RustyKVError::InsufficientSpace)?;
+        let right = self.buffer_manager.allocate_page()?;
+        self.write_node(page_id, page_type, &entries)?;
+        self.write_node(right, page_type, &right_entries)?;
+        Ok((separator, right))
+    }
+
+    ///
+    /// Divides rows in two, so that the left half holds about `fill_factor` of their bytes.
+    /// Rows with the same key are kept in the same half, so that they stay in one leaf.
+    ///
+    /// # Arguments
+    /// * `page_type`: Type of the page the rows belong to.
+    /// * `entries`: Rows to be divided. Only the left half is left in it.
+    /// * `fill_factor`: Fraction of the bytes to leave in the left half. It's adjusted as
+    ///   needed for both halves to fit in a page.
+    /// * `comparator`: Order of the keys.
+    ///
+    /// # Returns
+    /// * `Some((Vec<u8>, Entries))`: The first key of the right half, and the right half.
+    /// * `None` if the rows can't be divided into two halves that fit in a page without
+    ///   dividing the rows of a key. `entries` is left as it was.
+    ///
+    fn split_entries(
+        page_type: PageType,
+        entries: &mut Entries,
+        fill_factor: f32,
+        comparator: &dyn Comparator,
+    ) -> Option<(Vec<u8>, Entries)> {
+        let capacity = BTreePage::get_capacity();
+        let total_size = Self::get_size(entries);
+        let target_size = (total_size as f32 * fill_factor) as usize;
+        let mut left_size = 0;
+        // Last index the rows can be divided at, with both halves fitting in a page.
+        let mut split_index = None;
+        // `get_size` overestimates the rows of a leaf with a key prefix, so the exact size is
+        // only worked out if it doesn't fit.
+        let fits = |half: &[(Vec<u8>, Vec<u8>)], size: usize| {
+            size <= capacity || Self::get_layout_size(page_type, half, comparator) <= capacity
+        };
+        for index in 1..entries.len() {
+            let (key, value) = &entries[index - 1];
+            left_size += BTreePage::get_entry_size(key, value);
+            if comparator.compare(key, &entries[index].0) == Ordering::Equal {
+                continue;
+            }
+            if !fits(&entries[..index], left_size) {
+                break;
+            }
+            if !fits(&entries[index..], total_size - left_size) {
+                continue;
+            }
+            split_index = Some(index);
+            if left_size >= target_size {
+                break;
+            }
+        }
+
+        let mut right_entries = entries.split_off(split_index?);
+        let separator = right_entries[0].0.clone();
+        if page_type == PageType::Internal {
+            // The separator moves up to the parent, so the first child of the right half
+            // holds every key below its second separator.
+            right_entries[0].0.clear();
+        }
+        Some((separator, right_entries))
+    }
+
+    ///
+    /// Returns the number of bytes rows take up once laid out in a page by `layout_node`.
+    /// Leaf pages of lexicographic trees store the prefix shared by their keys once, so
+    /// their rows may take up less than `get_size`.
+    ///
+    fn get_layout_size(
+        page_type: PageType,
+        entries: &[(Vec<u8>, Vec<u8>)],
+        comparator: &dyn Comparator,
+    ) -> usize {
+        let prefix_size = match (
+            page_type,
+            comparator.is_lexicographic(),
+            entries.first(),
+            entries.last(),
+        ) {
+            (PageType::Leaf, true, Some((first, _)), Some((last, _))) => {
+                common_prefix_len(first, last)
+            }
+            _ => 0,
+        };
+        let rows_size: usize = entries
+            .iter()
+            .map(|(key, value)| BTreePage::get_entry_size(&key[prefix_size..], value))
+            .sum();
+        prefix_size + rows_size
+    }
+
+    ///
+    /// Encodes a value to be stored in a leaf page, moving it to overflow pages if it
+    /// doesn't fit in a row.
+    ///
+    fn encode_value(&mut self, key: &[u8], value: &[u8]) -> Result<Vec<u8>, Error> {
+        Self::encode_value_with(key, value, |data| {
+            let page_id = self.buffer_manager.allocate_page()?;
+            self.write_page(page_id, data)?;
+            Ok(page_id)
+        })
+    }
+
+    ///
+    /// Encodes a value to be stored in a leaf page. Overflow pages are written with
+    /// `new_page`, which allocates a page holding the given data.
+    ///
+    fn encode_value_with(
+        key: &[u8],
+        value: &[u8],
+        new_page: impl FnMut(&[u8; PAGE_SIZE]) -> Result<PageId, Error>,
+    ) -> Result<Vec<u8>, Error> {
+        if BTreePage::get_entry_size(key, value) + VALUE_TAG_SIZE <= MAX_ENTRY_SIZE {
+            let mut encoded = Vec::with_capacity(VALUE_TAG_SIZE + value.len());
+            encoded.push(VALUE_INLINE);
+            encoded.extend_from_slice(value);
+            return Ok(encoded);
+        }
+
+        let first = Self::write_overflow(value, new_page)?;
+        let mut encoded = Vec::with_capacity(OVERFLOW_POINTER_SIZE);
+        encoded.push(VALUE_OVERFLOW);
+        encoded.extend_from_slice(&first.value().to_le_bytes());
+        encoded.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        Ok(encoded)
+    }
+
+    ///
+    /// Decodes a value stored in a leaf page, reading it from overflow pages if needed.
+    ///
+    fn decode_value(&mut self, encoded: &[u8]) -> Result<Vec<u8>, Error> {
+        Self::decode_value_with(encoded, |page_id| self.read_node(page_id))
+    }
+
+    ///
+    /// Decodes a value stored in a leaf page. Overflow pages are read with `read_page`.
+    ///
+    fn decode_value_with(
+        encoded: &[u8],
+        read_page: impl FnMut(PageId) -> Result<[u8; PAGE_SIZE], Error>,
+    ) -> Result<Vec<u8>, Error> {
+        match Self::get_overflow(encoded) {
+            None => Ok(encoded[VALUE_TAG_SIZE..].to_vec()),
+            Some((first, length)) => Self::read_overflow(first, length, read_page),
+        }
+    }
+
+    ///
+    /// Returns whether a value read from a possibly corrupt leaf can be decoded: it has a
+    /// known tag, and if it was moved to overflow pages, a length of at most `max_length`.
+    ///
+    fn is_valid_value(encoded: &[u8], max_length: usize) -> bool {
+        match encoded.first() {
+            Some(&VALUE_INLINE) => true,
+            Some(&VALUE_OVERFLOW) => {
+                encoded.len() == OVERFLOW_POINTER_SIZE
+                    && Self::get_overflow(encoded).is_some_and(|(_, length)| length <= max_length)
+            }
+            _ => false,
+        }
+    }
+
+    ///
+    /// Returns the Page ID of the first overflow page and the length of an encoded value,
+    /// if it was moved to overflow pages.
+    ///
+    fn get_overflow(encoded: &[u8]) -> Option<(PageId, usize)> {
+        if encoded[0] != VALUE_OVERFLOW {
+            return None;
+        }
+        let (page_id, length) = encoded[VALUE_TAG_SIZE..].split_at(OVERFLOW_PAGE_ID_SIZE);
+        Some((
+            PageId::new(u64::from_le_bytes(page_id.try_into().unwrap())),
+            u64::from_le_bytes(length.try_into().unwrap()) as usize,
+        ))
+    }
+
+    ///
+    /// Returns the Page ID of the first overflow page of every row, of a leaf, with a key.
+    ///
+    fn get_overflows(page: &BTreePage, key: &[u8]) -> Vec<PageId> {
+        page.get_all(key)
+            .filter_map(|row| Self::get_overflow(row.get_value()))
+            .map(|(first, _)| first)
+            .collect()
+    }
+
+    ///
+    /// Writes a value to a newly allocated chain of overflow pages.
+    ///
+    /// # Arguments
+    /// * `value`: Value to be written.
+    /// * `new_page`: Allocates a page holding the given data.
+    ///
+    /// # Returns
+    /// * `Ok(PageId)`: Page ID of the first page in the chain.
+    /// * `Err(std::io::Error)` if a page couldn't be allocated or written.
+    ///
+    fn write_overflow(
+        value: &[u8],
+        mut new_page: impl FnMut(&[u8; PAGE_SIZE]) -> Result<PageId, Error>,
+    ) -> Result<PageId, Error> {
+        // The chain is written back to front, so that each page can point to the next one.
+        let mut next = PageId::INVALID;
+        for chunk in value.chunks(OVERFLOW_DATA_SIZE).rev() {
+            let mut data = [0u8; PAGE_SIZE];
+            BTreePage::mark_as_other_page(&mut data);
+            data[OVERFLOW_NEXT_OFFSET..OVERFLOW_DATA_OFFSET]
+                .copy_from_slice(&next.value().to_le_bytes());
+            data[OVERFLOW_DATA_OFFSET..OVERFLOW_DATA_OFFSET + chunk.len()].copy_from_slice(chunk);
+            next = new_page(&data)?;
+        }
+        Ok(next)
+    }
+
+    ///
+    /// Reads a value from a chain of overflow pages with `read_page`.
+    ///
+    fn read_overflow(
+        first: PageId,
+        length: usize,
+        mut read_page: impl FnMut(PageId) -> Result<[u8; PAGE_SIZE], Error>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut value = Vec::with_capacity(length);
+        let mut page_id = first;
+        while value.len() < length {
+            let data = read_page(page_id)?;
+            let chunk_size = (length - value.len()).min(OVERFLOW_DATA_SIZE);
+            value.extend_from_slice(&data[OVERFLOW_DATA_OFFSET..OVERFLOW_DATA_OFFSET + chunk_size]);
+            page_id = Self::get_overflow_next(&data);
+        }
+        Ok(value)
+    }
+
+    ///
+    /// Deallocates every page in a chain of overflow pages.
+    ///
+    fn free_overflow(&mut self, first: PageId) -> Result<(), Error> {
+        let mut page_id = first;
+        while page_id != PageId::INVALID {
+            let data = self.read_node(page_id)?;
+            self.buffer_manager.deallocate_page(page_id)?;
+            page_id = Self::get_overflow_next(&data);
+        }
+        Ok(())
+    }
+
+    fn get_overflow_next(data: &[u8; PAGE_SIZE]) -> PageId {
+        PageId::new(u64::from_le_bytes(
+            data[OVERFLOW_NEXT_OFFSET..OVERFLOW_DATA_OFFSET]
+                .try_into()
+                .unwrap(),
+        ))
+    }
+
+    ///
+    /// Walks down the tree to the leaf page that holds a key.
+    ///
+    fn find_leaf(&mut self, key: &[u8]) -> Result<PageId, Error> {
+        let mut page_id = self.root;
+        loop {
+            let mut data = self.read_node(page_id)?;
+            let page = BTreePage::with_comparator(&mut data, self.comparator);
+            match page.get_page_type() {
+                PageType::Leaf => return Ok(page_id),
+                PageType::Internal => page_id = Self::get_child(&page, key),
+            }
+        }
+    }
+
+    ///
+    /// Returns the child of an internal page whose subtree holds a key.
+    ///
+    fn get_child(page: &BTreePage, key: &[u8]) -> PageId {
+        // The first separator is empty, so every key has a floor.
+        Self::decode_child(page.get_floor(key).unwrap().get_value())
+    }
+
+    ///
+    /// Returns the index of the row, of an internal page, whose child holds a key.
+    ///
+    fn get_child_index(&self, entries: &Entries, key: &[u8]) -> usize {
+        match entries.binary_search_by(|(entry_key, _)| self.comparator.compare(entry_key, key)) {
+            Ok(index) => index,
+            // The first separator is empty, so the key is never inserted before it.
+            Err(index) => index - 1,
+        }
+    }
+
+    fn decode_child(value: &[u8]) -> PageId {
+        PageId::new(u64::from_le_bytes(value.try_into().unwrap()))
+    }
+
+    fn encode_child(page_id: PageId) -> Vec<u8> {
+        let bytes: [u8; CHILD_POINTER_SIZE] = page_id.value().to_le_bytes();
+        bytes.to_vec()
+    }
+
+    ///
+    /// Returns whether a page, with the given used space, needs to be rebalanced.
+    ///
+    fn get_rebalance(&self, used_space: usize) -> Rebalance {
+        if (used_space as f32) < BTreePage::get_capacity() as f32 * self.min_occupancy {
+            Rebalance::Underflow
+        } else {
+            Rebalance::Balanced
+        }
+    }
+
+    ///
+    /// Returns the number of bytes the rows take up in a page.
+    ///
+    fn get_size(entries: &Entries) -> usize {
+        entries
+            .iter()
+            .map(|(key, value)| BTreePage::get_entry_size(key, value))
+            .sum()
+    }
+
+    fn get_entries(page: &BTreePage) -> Entries {
+        page.iter()
+            .map(|row| (row.get_key().into_owned(), row.get_value().to_vec()))
+            .collect()
+    }
+
+    ///
+    /// Copies a page out of the buffer pool.
+    ///
+    fn read_node(&mut self, page_id: PageId) -> Result<[u8; PAGE_SIZE], Error> {
+        Ok(*self.buffer_manager.get(page_id)?.page())
+    }
+
+    ///
+    /// Overwrites a page with a freshly laid out page holding the given rows. Leaf pages
+    /// store the prefix shared by all their keys once.
+    ///
+    fn write_node(
+        &mut self,
+        page_id: PageId,
+        page_type: PageType,
+        entries: &[(Vec<u8>, Vec<u8>)],
+    ) -> Result<(), Error> {
+        let data = Self::layout_node(page_type, entries, self.comparator)?;
+        self.write_page(page_id, &data)
+    }
+
+    ///
+    /// Lays out a page holding the given rows. Leaf pages of lexicographic trees store the
+    /// prefix shared by all their keys once.
+    ///
+    fn layout_node(
+        page_type: PageType,
+        entries: &[(Vec<u8>, Vec<u8>)],
+        comparator: &dyn Comparator,
+    ) -> Result<[u8; PAGE_SIZE], RustyKVError> {
+        let mut data = [0u8; PAGE_SIZE];
+        let mut page = BTreePage::with_comparator(&mut data, comparator);
+        page.set_page_type(page_type);
+        // Rows with the same key are only kept if the page allows duplicate keys.
+        page.set_allows_duplicate_keys(
+            entries
+                .windows(2)
+                .any(|pair| comparator.compare(&pair[0].0, &pair[1].0) == Ordering::Equal),
+        );
+        if let (PageType::Leaf, true, Some((first, _)), Some((last, _))) = (
+            page_type,
+            comparator.is_lexicographic(),
+            entries.first(),
+            entries.last(),
+        ) {
+            // The rows are sorted, so the first and last keys share the shortest prefix.
+            page.set_prefix(&first[..common_prefix_len(first, last)])?;
+        }
+        for (key, value) in entries {
+            page.save(key, value)?;
+        }
+        Ok(data)
+    }
+
+    ///
+    /// Copies a page into the buffer pool, marking it dirty.
+    ///
+    fn write_page(&mut self, page_id: PageId, data: &[u8; PAGE_SIZE]) -> Result<(), Error> {
+        self.buffer_manager
+            .get(page_id)?
+            .with_page_mut(|page| page.copy_from_slice(data));
+        Ok(())
+    }
+}
+
+impl BTree {
+    ///
+    /// Builds a BTree from rows sorted by key, bottom-up. Leaf pages are filled to capacity
+    /// in key order, then each level of internal pages is built from the one below it, so
+    /// no page is ever split. Pages are written directly to the data file, bypassing the
+    /// buffer pool.
+    ///
+    /// # Arguments
+    /// * `items`: Rows to load, in strictly increasing key order.
+    /// * `path`: Path to the data file. The tree's pages are appended to it.
+    ///
+    /// # Returns
+    /// * `Ok(PageId)`: Page ID of the root page, to open the tree with `open`.
+    /// * `Err(RustyKVError::KeysOutOfOrder)` if a key isn't greater than the one before it.
+    /// * `Err(RustyKVError::ComparatorMismatch)` if the data file is ordered by a
+    ///   comparator other than `Lexicographic`.
+    /// * `Err(RustyKVError::InsufficientSpace)` if a key is too large to be stored.
+    /// * `Err(RustyKVError::Io)` if the data file couldn't be written.
+    ///
+    /// On error, the pages written so far are left in the file, but aren't reachable.
+    ///
+    pub fn bulk_load(
+        items: impl Iterator<Item = (Vec<u8>, Vec<u8>)>,
+        path: &Path,
+    ) -> Result<PageId, RustyKVError> {
+        let mut disk_manager = DiskManager::new(path, SyncMode::Deferred)?;
+        match disk_manager.get_comparator_name() {
+            Some(name) if name != Lexicographic.name() => {
+                return Err(RustyKVError::ComparatorMismatch);
+            }
+            Some(_) => {}
+            None => disk_manager.set_comparator_name(Lexicographic.name())?,
+        }
+        let mut new_page = |data: &[u8; PAGE_SIZE]| -> Result<PageId, Error> {
+            let page_id = disk_manager.allocate_page()?;
+            disk_manager.write_page(&page_id, data)?;
+            Ok(page_id)
+        };
+
+        let mut leaves = LevelBuilder::new(PageType::Leaf, &Lexicographic);
+        let mut previous_key: Option<Vec<u8>> = None;
+        for (key, value) in items {
+            if previous_key
+                .as_ref()
+                .is_some_and(|previous_key| cmp_lex_bytes(previous_key, &key) != Ordering::Less)
+            {
+                return Err(RustyKVError::KeysOutOfOrder);
+            }
+            if BTreePage::get_entry_size(&key, &[0u8; OVERFLOW_POINTER_SIZE]) > MAX_ENTRY_SIZE {
+                return Err(RustyKVError::InsufficientSpace);
+            }
+            let value = Self::encode_value_with(&key, &value, &mut new_page)?;
+            previous_key = Some(key.clone());
+            leaves.push(key, value, &mut new_page)?;
+        }
+
+        let mut pages = leaves.finish(&mut new_page)?;
+        while pages.len() > 1 {
+            let mut internal_pages = LevelBuilder::new(PageType::Internal, &Lexicographic);
+            for (key, child) in pages {
+                internal_pages.push(key, Self::encode_child(child), &mut new_page)?;
+            }
+            pages = internal_pages.finish(&mut new_page)?;
+        }
+        let root = match pages.pop() {
+            Some((_, root)) => root,
+            None => new_page(&Self::layout_node(PageType::Leaf, &[], &Lexicographic)?)?,
+        };
+        disk_manager.sync()?;
+        Ok(root)
+    }
+
+    ///
+    /// Restores a snapshot written by `export_snapshot` to `dest`, verifying the checksum of
+    /// every page. The tree can then be opened with a buffer pool over `dest`.
+    ///
+    /// # Arguments
+    /// * `path`: Path of the snapshot.
+    /// * `dest`: Path the data file is restored to. It must not be open.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the snapshot was restored.
+    /// * `Err(std::io::Error)` if an error occurred while copying the file, or a page is
+    ///   corrupt.
+    ///
+    pub fn import_snapshot(path: &Path, dest: &Path) -> Result<(), Error> {
+        DiskManager::import_snapshot(path, dest)
+    }
+
+    ///
+    /// Recovers what can be recovered of a corrupt data file into a new one. Every page of
+    /// `src` is checked with `DiskManager::verify`, and the rows of every leaf that passes
+    /// are saved into a new lexicographic tree at `dst`. Internal pages aren't needed, since
+    /// the tree is rebuilt from its leaves, so only corrupt leaves and overflow pages lose
+    /// rows. The Page ID of every page that couldn't be recovered is listed in the report.
+    ///
+    /// # Arguments
+    /// * `src`: Path of the corrupt data file. It is only read.
+    /// * `dst`: Path of the data file to create. It must not exist.
+    ///
+    /// # Returns
+    /// * `Ok(SalvageReport)`: The root of the new tree, and what couldn't be recovered.
+    /// * `Err(std::io::Error)` of kind `AlreadyExists` if `dst` exists.
+    /// * `Err(std::io::Error)` of kind `InvalidInput` wrapping
+    ///   `RustyKVError::ComparatorMismatch` if `src` is ordered by a comparator other than
+    ///   `Lexicographic`.
+    /// * `Err(std::io::Error)` if `src` couldn't be read, or `dst` written.
+    ///
+    /// # Impl Note
+    /// Free pages are skipped, since they may hold the stale rows of a deallocated leaf. If
+    /// the free list is lost with the metadata page, they are salvaged too, so deleted keys
+    /// or old values may come back.
+    ///
+    pub fn salvage(src: &Path, dst: &Path) -> Result<SalvageReport, Error> {
+        if dst.exists() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                "salvage destination already exists",
+            ));
+        }
+        let verify_report = DiskManager::verify(src)?;
+        let mut source = DiskManager::open_for_salvage(src)?;
+        if source
+            .get_comparator_name()
+            .is_some_and(|name| name != Lexicographic.name())
+        {
+            return Err(RustyKVError::ComparatorMismatch.into());
+        }
+        let skipped: HashSet<PageId> = verify_report
+            .corrupt_pages
+            .iter()
+            .chain(source.free_pages())
+            .copied()
+            .collect();
+        let max_length = verify_report.num_pages * OVERFLOW_DATA_SIZE;
+        let mut read_page = |page_id: PageId| {
+            let mut data = [0u8; PAGE_SIZE];
+            source.read_page(&page_id, &mut data)?;
+            Ok(data)
+        };
+
+        let mut btree = Self::new(BufferManager::new_with_path(SALVAGE_POOL_SIZE, dst)?)?;
+        let mut report = SalvageReport {
+            root: btree.root,
+            rows: 0,
+            lost_rows: 0,
+            lost_pages: verify_report.corrupt_pages.clone(),
+        };
+        for index in 1..verify_report.num_pages {
+            let page_id = PageId::new(index as u64);
+            if skipped.contains(&page_id) {
+                continue;
+            }
+            let mut data = match read_page(page_id) {
+                Ok(data) => data,
+                Err(_) => {
+                    report.lost_pages.push(page_id);
+                    continue;
+                }
+            };
+            // Overflow pages are read with the rows pointing to them.
+            if !BTreePage::is_btree_page(&data) {
+                continue;
+            }
+            let page = BTreePage::from(&mut data);
+            if page.get_page_type() != PageType::Leaf {
+                continue;
+            }
+            for row in page.iter() {
+                let value = row
+                    .get_verified_value()
+                    .ok()
+                    .filter(|encoded| Self::is_valid_value(encoded, max_length))
+                    .and_then(|encoded| Self::decode_value_with(encoded, &mut read_page).ok());
+                match value {
+                    Some(value) => {
+                        btree.save(&row.get_key(), &value)?;
+                        report.rows += 1;
+                    }
+                    None => report.lost_rows += 1,
+                }
+            }
+        }
+        btree.buffer_manager.flush_all()?;
+
+        report.lost_pages.sort_by_key(PageId::value);
+        report.root = btree.root;
+        Ok(report)
+    }
+
+    ///
+    /// Calls `f` with every row of the tree, scanning it on `n_shards` threads at once. The
+    /// leaves are split into `n_shards` runs of consecutive pages of about the same length,
+    /// going by the slot maps of the internal pages, and each thread reads its run through
+    /// its own read-only handle on the data file, so that the reads overlap.
+    ///
+    /// Pages in the buffer pool are read from their frames instead, under their shared
+    /// latches, so that changes that haven't been flushed are seen. Pages are neither
+    /// fetched into the buffer pool nor evicted from it by the scan.
+    ///
+    /// # Arguments
+    /// * `n_shards`: Number of threads to scan with. Must be greater than 0.
+    /// * `f`: Called with the key and value of every row, from several threads at once.
+    ///
+    /// # Returns
+    /// * `Ok(results)`: What `f` returned for every row, in key order.
+    /// * `Err(std::io::Error)` of kind `InvalidInput` if `n_shards` is 0.
+    /// * `Err(std::io::Error)` if a page couldn't be read.
+    ///
+    pub fn parallel_scan<R: Send>(
+        &self,
+        n_shards: usize,
+        f: impl Fn(&[u8], &[u8]) -> R + Sync,
+    ) -> Result<Vec<R>, Error> {
+        if n_shards == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "a scan needs at least one shard",
+            ));
+        }
+        let path = self.buffer_manager.path();
+        let comparator = self.comparator;
+        let frames = &self.buffer_manager.resident_frames();
+        let mut disk_manager = DiskManager::open_readonly(path)?;
+        let leaves = Self::get_leaves_with(self.root, comparator, |page_id| {
+            Self::read_page_from(frames, &mut disk_manager, page_id)
+        })?;
+        let f = &f;
+
+        let shards = thread::scope(|scope| {
+            let handles: Vec<_> = (0..n_shards)
+                .map(|shard| {
+                    let shard = &leaves
+                        [shard * leaves.len() / n_shards..(shard + 1) * leaves.len() / n_shards];
+                    scope.spawn(move || Self::scan_leaves(path, frames, shard, comparator, f))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|panic| panic::resume_unwind(panic))
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        })?;
+        Ok(shards.into_iter().flatten().collect())
+    }
+
+    ///
+    /// Calls `f` with every row of a run of leaves, reading them from `frames`, or the data
+    /// file at `path` if they aren't in the buffer pool, and returns the results in key
+    /// order.
+    ///
+    fn scan_leaves<R>(
+        path: &Path,
+        frames: &HashMap<PageId, &Frame>,
+        leaves: &[PageId],
+        comparator: &dyn Comparator,
+        f: &impl Fn(&[u8], &[u8]) -> R,
+    ) -> Result<Vec<R>, Error> {
+        let mut disk_manager = DiskManager::open_readonly(path)?;
+        let mut read_page =
+            |page_id: PageId| Self::read_page_from(frames, &mut disk_manager, page_id);
+        let mut results = Vec::new();
+        for leaf in leaves {
+            let mut data = read_page(*leaf)?;
+            let page = BTreePage::with_comparator(&mut data, comparator);
+            for row in page.iter() {
+                let value = Self::decode_value_with(row.get_verified_value()?, &mut read_page)?;
+                results.push(f(&row.get_key(), &value));
+            }
+        }
+        Ok(results)
+    }
+
+    ///
+    /// Copies a page out of its frame in `frames` under its shared latch, or reads it from
+    /// `disk_manager` if it isn't in the buffer pool.
+    ///
+    fn read_page_from(
+        frames: &HashMap<PageId, &Frame>,
+        disk_manager: &mut DiskManager,
+        page_id: PageId,
+    ) -> Result<[u8; PAGE_SIZE], Error> {
+        if let Some(frame) = frames.get(&page_id) {
+            return Ok(*frame.data.read().unwrap());
+        }
+        let mut data = [0u8; PAGE_SIZE];
+        disk_manager.read_page(&page_id, &mut data)?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::btree_kv::cache_policy_engine::EvictionPolicy;
+    use crate::store::btree_kv::memory_disk_manager::MemoryDiskManager;
+    use std::env;
+    use std::fs;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::sync::{Arc, Mutex};
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key{:05}", index).into_bytes()
+    }
+
+    fn value(index: usize) -> Vec<u8> {
+        format!("value{:05}", index).repeat(10).into_bytes()
+    }
+
+    fn height<B: StorageBackend>(btree: &mut BTree<B>) -> usize {
+        let mut height = 1;
+        let mut page_id = btree.root;
+        loop {
+            let mut data = btree.read_node(page_id).unwrap();
+            let page = BTreePage::from(&mut data);
+            if page.get_page_type() == PageType::Leaf {
+                return height;
+            }
+            page_id = <BTree>::get_child(&page, b"");
+            height += 1;
+        }
+    }
+
+    ///
+    /// Collects every key in the tree with an in-order traversal, checking that each key is
+    /// within the bounds of the separators above it.
+    ///
+    fn collect_keys(
+        btree: &mut BTree,
+        page_id: PageId,
+        lower: &[u8],
+        upper: Option<&[u8]>,
+        keys: &mut Vec<Vec<u8>>,
+    ) {
+        let mut data = btree.read_node(page_id).unwrap();
+        let page = BTreePage::from(&mut data);
+        let entries = <BTree>::get_entries(&page);
+        match page.get_page_type() {
+            PageType::Leaf => {
+                for (key, _) in entries {
+                    assert!(key.as_slice() >= lower);
+                    assert!(upper.is_none_or(|upper| key.as_slice() < upper));
+                    keys.push(key);
+                }
+            }
+            PageType::Internal => {
+                for (index, (separator, child)) in entries.iter().enumerate() {
+                    let child_lower = if index == 0 { lower } else { separator };
+                    let child_upper = entries.get(index + 1).map(|(key, _)| key.as_slice());
+                    collect_keys(
+                        btree,
+                        <BTree>::decode_child(child),
+                        child_lower,
+                        child_upper.or(upper),
+                        keys,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_empty");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        assert_eq!(btree.get(b"key").unwrap(), None);
+        assert!(!btree.contains_key(b"key").unwrap());
+
+        btree.save(b"key", b"value").unwrap();
+        assert_eq!(btree.get(b"key").unwrap(), Some(b"value".to_vec()));
+        assert!(btree.contains_key(b"key").unwrap());
+        assert_eq!(height(&mut btree), 1);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_split_keeps_every_key_retrievable() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_split");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        // A small pool, so that pages are evicted and read back while the tree grows.
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in 0..5000 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        assert!(height(&mut btree) >= 2);
+
+        for index in 0..5000 {
+            assert_eq!(btree.get(&key(index)).unwrap(), Some(value(index)));
+        }
+        assert_eq!(btree.get(&key(5000)).unwrap(), None);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_check_integrity_reports_corruption() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_integrity");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in 0..2000 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        btree.save(&key(1000), &vec![7u8; 3 * PAGE_SIZE]).unwrap();
+        assert_eq!(height(&mut btree), 2);
+        assert_eq!(btree.check_integrity(), Ok(()));
+
+        // The last slot of a page is stored in its last two bytes. Pointing the slot before
+        // it at the same row repeats the last key.
+        let first_leaf = btree.find_leaf(&key(0)).unwrap();
+        btree
+            .buffer_manager
+            .get(first_leaf)
+            .unwrap()
+            .with_page_mut(|data| data.copy_within(PAGE_SIZE - 2.., PAGE_SIZE - 4));
+        let last_leaf = btree.find_leaf(&key(1999)).unwrap();
+        btree
+            .buffer_manager
+            .get(last_leaf)
+            .unwrap()
+            .with_page_mut(|data| data[PAGE_SIZE - 2..].copy_from_slice(&[0xff, 0xff]));
+        let orphan = btree.buffer_manager.allocate_page().unwrap();
+        assert_eq!(
+            btree.check_integrity(),
+            Err(vec![
+                IntegrityError::KeysOutOfOrder(first_leaf),
+                IntegrityError::MalformedPage(last_leaf),
+                IntegrityError::OrphanedPage(orphan),
+            ])
+        );
+
+        // Point the root's second child at its first child instead.
+        let mut data = btree.read_node(btree.root).unwrap();
+        let mut entries = <BTree>::get_entries(&BTreePage::from(&mut data));
+        let second_child = <BTree>::decode_child(&entries[1].1);
+        entries[1].1 = entries[0].1.clone();
+        btree
+            .write_node(btree.root, PageType::Internal, &entries)
+            .unwrap();
+        let errors = btree.check_integrity().unwrap_err();
+        assert!(
+            errors.contains(&IntegrityError::ReferencedTwice(<BTree>::decode_child(
+                &entries[0].1
+            )))
+        );
+        assert!(errors.contains(&IntegrityError::OrphanedPage(second_child)));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_fill_factor_reduces_pages_for_ascending_inserts() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_fill_factor");
+        fs::create_dir_all(&temp_dir).unwrap();
+
+        let mut page_counts = Vec::new();
+        for fill_factor in [0.5, 0.9] {
+            let test_file = temp_dir.join(format!("test_{}.db", fill_factor));
+            let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+            let mut btree = BTree::new(bpm).unwrap();
+            btree.set_fill_factor(fill_factor);
+            for index in 0..5000 {
+                btree.save(&key(index), &value(index)).unwrap();
+            }
+            for index in 0..5000 {
+                assert_eq!(btree.get(&key(index)).unwrap(), Some(value(index)));
+            }
+            assert_eq!(btree.check_integrity(), Ok(()));
+            page_counts.push(btree.buffer_manager.allocated_pages().len());
+        }
+        // Even splits leave every page but the last half full.
+        assert!(page_counts[1] * 10 < page_counts[0] * 7);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_split_with_unordered_inserts_and_updates() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_unordered");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(8 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        // Visit every index exactly once, in a scattered order.
+        let indices: Vec<usize> = (0..2000).map(|index| index * 7919 % 2000).collect();
+        for index in &indices {
+            assert_eq!(
+                btree.save(&key(*index), &value(*index)).unwrap(),
+                SaveOutcome::Inserted
+            );
+        }
+        assert!(height(&mut btree) >= 2);
+
+        // Growing values forces splits on update as well.
+        for index in indices.iter().filter(|index| *index % 3 == 0) {
+            assert_eq!(
+                btree.save(&key(*index), &value(*index).repeat(2)).unwrap(),
+                SaveOutcome::Updated
+            );
+        }
+        for index in 0..2000 {
+            let expected = if index % 3 == 0 {
+                value(index).repeat(2)
+            } else {
+                value(index)
+            };
+            assert_eq!(btree.get(&key(index)).unwrap(), Some(expected));
+        }
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_get_many() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_get_many");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in (0..1000).step_by(2) {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+
+        let indices = [998, 1, 0, 501, 500, 998];
+        let keys: Vec<Vec<u8>> = indices.iter().map(|index| key(*index)).collect();
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_slice()).collect();
+        let values = btree.get_many(&keys).unwrap();
+        let expected: Vec<Option<Vec<u8>>> = indices
+            .iter()
+            .map(|index| (index % 2 == 0).then(|| value(*index)))
+            .collect();
+        assert_eq!(values, expected);
+        assert!(btree.get_many(&[]).unwrap().is_empty());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_get_or_and_get_or_default() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_get_or");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        btree.save(b"key1", b"value1").unwrap();
+        // Empty values are stored, and returned rather than the default.
+        btree.save(b"key2", b"").unwrap();
+
+        assert_eq!(btree.get_or(b"key1", b"default").unwrap(), b"value1");
+        assert_eq!(btree.get_or(b"key2", b"default").unwrap(), b"");
+        assert_eq!(btree.get_or(b"missing", b"default").unwrap(), b"default");
+
+        assert_eq!(btree.get_or_default(b"key1").unwrap(), b"value1");
+        assert_eq!(btree.get_or_default(b"missing").unwrap(), b"");
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_save_batch_matches_individual_saves() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_save_batch");
+        fs::create_dir_all(&temp_dir).unwrap();
+
+        let items: Vec<(Vec<u8>, Vec<u8>)> = (0..3000)
+            .map(|index| (key(index * 7919 % 2500), value(index)))
+            .collect();
+
+        let bpm = BufferManager::new_with_path(8 * PAGE_SIZE, &temp_dir.join("a.db")).unwrap();
+        let mut individual = BTree::new(bpm).unwrap();
+        for (key, value) in &items {
+            individual.save(key, value).unwrap();
+        }
+        let bpm = BufferManager::new_with_path(8 * PAGE_SIZE, &temp_dir.join("b.db")).unwrap();
+        let mut batched = BTree::new(bpm).unwrap();
+        batched.save_batch(items).unwrap();
+
+        let (mut individual_keys, mut batched_keys) = (Vec::new(), Vec::new());
+        let root = individual.root;
+        collect_keys(&mut individual, root, b"", None, &mut individual_keys);
+        let root = batched.root;
+        collect_keys(&mut batched, root, b"", None, &mut batched_keys);
+        assert_eq!(batched_keys, individual_keys);
+        for key in &individual_keys {
+            assert_eq!(batched.get(key).unwrap(), individual.get(key).unwrap());
+        }
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_delete_rebalances_and_keeps_tree_ordered() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_delete");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in 0..3000 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        assert!(height(&mut btree) >= 2);
+
+        // Deleting most of the keys leaves pages under half full, forcing both borrowing
+        // and merging.
+        for index in (0..3000).filter(|index| index % 5 != 0) {
+            btree.delete(&key(index)).unwrap();
+        }
+        // Deleting missing keys is a no-op.
+        btree.delete(&key(1)).unwrap();
+        btree.delete(b"missing").unwrap();
+
+        let mut keys = Vec::new();
+        let root = btree.root;
+        collect_keys(&mut btree, root, b"", None, &mut keys);
+        let expected: Vec<Vec<u8>> = (0..3000).step_by(5).map(key).collect();
+        assert_eq!(keys, expected);
+        for index in 0..3000 {
+            let expected = (index % 5 == 0).then(|| value(index));
+            assert_eq!(btree.get(&key(index)).unwrap(), expected);
+        }
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_tombstone_deletes() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_tombstones");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        btree.set_tombstone_deletes(true);
+        for index in 0..500 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        for index in (0..500).step_by(2) {
+            btree.delete(&key(index)).unwrap();
+        }
+
+        for index in 0..500 {
+            let expected = (index % 2 == 1).then(|| value(index));
+            assert_eq!(btree.get(&key(index)).unwrap(), expected);
+        }
+        let keys: Vec<_> = btree
+            .range(b"", b"\xff")
+            .unwrap()
+            .map(|(key, _)| key)
+            .collect();
+        let expected: Vec<_> = (1..500).step_by(2).map(key).collect();
+        assert_eq!(keys, expected);
+        assert_eq!(btree.check_integrity(), Ok(()));
+
+        // Saving a deleted key brings it back.
+        assert_eq!(
+            btree.save(&key(0), &value(0)).unwrap(),
+            SaveOutcome::Inserted
+        );
+        assert_eq!(btree.get(&key(0)).unwrap(), Some(value(0)));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_duplicate_keys");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        btree.set_allow_duplicate_keys(true);
+        // Every key is saved again after the leaves were split, so that splits fall between
+        // rows of the same key.
+        for round in 0..3 {
+            for index in 0..300 {
+                assert_eq!(
+                    btree.save(&key(index), &value(index + round)).unwrap(),
+                    SaveOutcome::Inserted
+                );
+            }
+        }
+        let large_value = vec![7u8; 20 * 1024];
+        btree.save(&key(0), &large_value).unwrap();
+
+        for index in 0..300 {
+            let mut expected: Vec<_> = (0..3).map(|round| value(index + round)).collect();
+            if index == 0 {
+                expected.push(large_value.clone());
+            }
+            assert_eq!(btree.get_all(&key(index)).unwrap(), expected);
+            assert_eq!(btree.get(&key(index)).unwrap(), Some(value(index)));
+        }
+        assert!(btree.get_all(b"absent").unwrap().is_empty());
+        assert!(height(&mut btree) > 1);
+        assert_eq!(btree.check_integrity(), Ok(()));
+
+        // Deleting a key deletes every value, freeing their overflow pages, or they would
+        // be reported as orphaned.
+        btree.delete(&key(0)).unwrap();
+        assert_eq!(btree.delete_batch(&[&key(1), &key(2)]).unwrap(), 2);
+        for index in 0..3 {
+            assert!(btree.get_all(&key(index)).unwrap().is_empty());
+        }
+        assert_eq!(btree.get_all(&key(3)).unwrap().len(), 3);
+        assert_eq!(btree.check_integrity(), Ok(()));
+
+        // The rows of a key must fit in a leaf. Saves that don't fit fail, without leaking
+        // the overflow pages of their value.
+        let mut count = 0;
+        while btree.save(b"crowded", &[1u8; 1000]).is_ok() {
+            count += 1;
+        }
+        let error = loop {
+            match btree.save(b"crowded", &large_value) {
+                Ok(_) => count += 1,
+                Err(error) => break error,
+            }
+        };
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<RustyKVError>(),
+            Some(&RustyKVError::InsufficientSpace)
+        );
+        assert_eq!(btree.get_all(b"crowded").unwrap().len(), count);
+        assert_eq!(btree.check_integrity(), Ok(()));
+
+        // The rows of the key only fit in their leaf thanks to its key prefix, and the leaf
+        // can still be split from the rows next to it.
+        btree.delete(&key(3)).unwrap();
+        btree.save(&key(3), &value(3)).unwrap();
+        assert_eq!(btree.get_all(&key(3)).unwrap(), vec![value(3)]);
+        assert_eq!(btree.get_all(b"crowded").unwrap().len(), count);
+        assert_eq!(btree.check_integrity(), Ok(()));
+
+        // A failed batch restores every value of the keys it changed.
+        let mut batch = WriteBatch::new();
+        batch
+            .put(key(4), value(0))
+            .delete(key(5))
+            .put(vec![b'k'; PAGE_SIZE], value(0));
+        assert!(btree.apply_batch(batch).is_err());
+        for index in 4..6 {
+            let expected: Vec<_> = (0..3).map(|round| value(index + round)).collect();
+            assert_eq!(btree.get_all(&key(index)).unwrap(), expected);
+        }
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_compact() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_compact");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        btree.set_tombstone_deletes(true);
+        for index in 0..500 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        for index in (0..500).step_by(5) {
+            btree.delete(&key(index)).unwrap();
+        }
+
+        let leaves = btree.get_leaves().unwrap();
+        let mut fragmented = 0;
+        for &leaf in &leaves {
+            if btree.page_stats(leaf).unwrap().dead_bytes > 0 {
+                fragmented += 1;
+            }
+        }
+        assert!(fragmented > 0);
+        assert_eq!(btree.compact().unwrap(), fragmented);
+        for leaf in leaves {
+            assert_eq!(btree.page_stats(leaf).unwrap().dead_bytes, 0);
+        }
+        assert_eq!(btree.compact().unwrap(), 0);
+        for index in 0..500 {
+            let expected = (index % 5 != 0).then(|| value(index));
+            assert_eq!(btree.get(&key(index)).unwrap(), expected);
+        }
+        assert_eq!(btree.check_integrity(), Ok(()));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_min_occupancy() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_min_occupancy");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        btree.set_min_occupancy(0.2);
+        let mut count = 0;
+        while height(&mut btree) == 1 {
+            btree.save(&key(count), &value(count)).unwrap();
+            count += 1;
+        }
+        let left = btree.get_leaves().unwrap()[0];
+        let min_used = BTreePage::get_capacity() as f32 * 0.2;
+
+        // Deleting from the left leaf only merges it once it's below the threshold, long
+        // after it's less than half full.
+        let mut below_half = false;
+        for index in 0..count {
+            btree.delete(&key(index)).unwrap();
+            if btree.get_leaves().unwrap().len() == 1 {
+                break;
+            }
+            let used = btree.page_stats(left).unwrap().used_bytes;
+            assert!(used as f32 >= min_used);
+            below_half |= used < BTreePage::get_capacity() / 2;
+        }
+        assert!(below_half);
+        assert_eq!(btree.get_leaves().unwrap().len(), 1);
+        assert_eq!(height(&mut btree), 1);
+        assert_eq!(btree.check_integrity(), Ok(()));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_delete_everything_shrinks_tree() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_shrink");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in 0..2000 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        assert!(height(&mut btree) >= 2);
+        btree.buffer_manager.flush_all().unwrap();
+        let file_length = fs::metadata(&test_file).unwrap().len();
+
+        for index in (0..2000).rev() {
+            btree.delete(&key(index)).unwrap();
+        }
+        assert_eq!(height(&mut btree), 1);
+        assert_eq!(btree.get(&key(0)).unwrap(), None);
+
+        // Freed pages are reused, so growing the tree again doesn't extend the file.
+        for index in 0..2000 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        btree.buffer_manager.flush_all().unwrap();
+        assert_eq!(fs::metadata(&test_file).unwrap().len(), file_length);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_delete_batch() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_delete_batch");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in 0..4000 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        let large_value = vec![7u8; 3 * PAGE_SIZE];
+        btree.save(&key(10), &large_value).unwrap();
+        assert!(height(&mut btree) >= 2);
+        let pages = btree.buffer_manager.allocated_pages().len();
+
+        // Every even key, in reverse, with an absent key and a repeated one.
+        let mut keys: Vec<Vec<u8>> = (0..4000).step_by(2).rev().map(key).collect();
+        keys.push(key(5000));
+        keys.push(key(0));
+        let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+        assert_eq!(btree.delete_batch(&keys).unwrap(), 2000);
+        assert_eq!(btree.delete_batch(&keys).unwrap(), 0);
+        assert_eq!(btree.delete_batch(&[]).unwrap(), 0);
+
+        assert_eq!(btree.check_integrity(), Ok(()));
+        for index in 0..4000 {
+            let expected = (index % 2 == 1).then(|| value(index));
+            assert_eq!(btree.get(&key(index)).unwrap(), expected);
+        }
+        // Emptied leaves were merged, and the overflow pages of the large value freed.
+        assert!(btree.buffer_manager.allocated_pages().len() < pages * 2 / 3);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_pinned_root_survives_eviction_pressure() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_pin_root");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let mut bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let listener = Arc::clone(&evicted);
+        bpm.on_evict(move |page_id| listener.lock().unwrap().push(page_id));
+        let mut btree = BTree::new(bpm).unwrap();
+        btree.set_pin_root(true).unwrap();
+
+        // The pin moves to every new root as the tree grows.
+        for index in 0..2000 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        assert!(height(&mut btree) >= 2);
+        assert_eq!(btree.buffer_manager.pinned_page_count(), 1);
+
+        // A full scan reads the root once, then cycles through the other frames many times
+        // over.
+        evicted.lock().unwrap().clear();
+        assert_eq!(btree.keys().unwrap().len(), 2000);
+        assert!(evicted.lock().unwrap().len() > 4);
+        assert!(!evicted.lock().unwrap().contains(&btree.root()));
+
+        // Without the pin, the same reads evict the root.
+        btree.set_pin_root(false).unwrap();
+        assert_eq!(btree.buffer_manager.pinned_page_count(), 0);
+        evicted.lock().unwrap().clear();
+        assert_eq!(btree.keys().unwrap().len(), 2000);
+        assert!(evicted.lock().unwrap().contains(&btree.root()));
+
+        // Shrinking the tree moves the pin down, and frees the old roots.
+        btree.set_pin_root(true).unwrap();
+        for index in 0..2000 {
+            btree.delete(&key(index)).unwrap();
+        }
+        assert_eq!(height(&mut btree), 1);
+        assert_eq!(btree.buffer_manager.pinned_page_count(), 1);
+        btree.set_pin_root(false).unwrap();
+        assert_eq!(btree.buffer_manager.pinned_page_count(), 0);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    ///
+    /// Stores pages in memory, counting the full page reads.
+    ///
+    struct CountingBackend {
+        backend: MemoryDiskManager,
+        reads: Arc<Mutex<usize>>,
+    }
+
+    impl StorageBackend for CountingBackend {
+        fn read_page(&mut self, id: &PageId, buffer: &mut [u8; PAGE_SIZE]) -> Result<(), Error> {
+            *self.reads.lock().unwrap() += 1;
+            self.backend.read_page(id, buffer)
+        }
+
+        fn read_page_head(&mut self, id: &PageId, buffer: &mut [u8]) -> Result<(), Error> {
+            self.backend.read_page_head(id, buffer)
+        }
+
+        fn write_page(&mut self, id: &PageId, buffer: &[u8; PAGE_SIZE]) -> Result<(), Error> {
+            self.backend.write_page(id, buffer)
+        }
+
+        fn allocate_page(&mut self) -> Result<PageId, Error> {
+            self.backend.allocate_page()
+        }
+
+        fn deallocate_page(&mut self, id: PageId) -> Result<(), Error> {
+            self.backend.deallocate_page(id)
+        }
+
+        fn sync(&mut self) -> Result<(), Error> {
+            self.backend.sync()
+        }
+
+        fn export_snapshot(&mut self, path: &Path) -> Result<(), Error> {
+            self.backend.export_snapshot(path)
+        }
+
+        fn num_pages(&self) -> usize {
+            self.backend.num_pages()
+        }
+
+        fn free_pages(&self) -> &[PageId] {
+            self.backend.free_pages()
+        }
+    }
+
+    #[test]
+    fn test_contains_key_rules_out_absent_keys_from_the_header() {
+        let reads = Arc::new(Mutex::new(0));
+        let backend = CountingBackend {
+            backend: MemoryDiskManager::new(),
+            reads: Arc::clone(&reads),
+        };
+        let bpm = BufferManager::with_backend(4 * PAGE_SIZE, backend, EvictionPolicy::LRU).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in (0..4000).step_by(2) {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        assert_eq!(height(&mut btree), 2);
+        // With the root pinned, the leaf is the only page a lookup can read.
+        btree.set_pin_root(true).unwrap();
+
+        // Keys are looked up out of order, so that their leaves have been evicted.
+        let scattered =
+            |offset: usize| (0..2000).map(move |index| (index * 37 % 2000) * 2 + offset);
+        let mut definite_misses = 0;
+        for index in scattered(1) {
+            let leaf = btree.find_leaf(&key(index)).unwrap();
+            let mut head = vec![0u8; BTreePage::get_head_size(&key(index))];
+            btree
+                .buffer_manager
+                .read_page_head(leaf, &mut head)
+                .unwrap();
+            let definite_miss = !BTreePage::head_may_contain(&head, &key(index));
+
+            let reads_before = *reads.lock().unwrap();
+            assert!(!btree.contains_key(&key(index)).unwrap());
+            if definite_miss {
+                assert_eq!(*reads.lock().unwrap(), reads_before);
+                definite_misses += 1;
+            }
+        }
+        assert!(
+            definite_misses > 1900,
+            "{} definite misses",
+            definite_misses
+        );
+
+        // Present keys are always found, reading their leaves.
+        let reads_before = *reads.lock().unwrap();
+        for index in scattered(0) {
+            assert!(btree.contains_key(&key(index)).unwrap());
+        }
+        assert!(*reads.lock().unwrap() > reads_before + 1000);
+    }
+
+    #[test]
+    fn test_large_values_use_overflow_pages() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_overflow");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        let large_value: Vec<u8> = (0..50 * 1024).map(|index| (index % 251) as u8).collect();
+        btree.save(b"large", &large_value).unwrap();
+        // A value right at the limit of a row, and small values around the large one.
+        let limit_value = vec![7u8; MAX_ENTRY_SIZE - BTreePage::get_entry_size(b"limit", b"") - 1];
+        btree.save(b"limit", &limit_value).unwrap();
+        for index in 0..100 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+
+        assert_eq!(btree.get(b"large").unwrap(), Some(large_value.clone()));
+        assert_eq!(btree.get(b"limit").unwrap(), Some(limit_value));
+        for index in 0..100 {
+            assert_eq!(btree.get(&key(index)).unwrap(), Some(value(index)));
+        }
+
+        // Deleting a large value frees its overflow pages for reuse.
+        let small_value = &large_value[..20 * 1024];
+        btree.buffer_manager.flush_all().unwrap();
+        let file_length = fs::metadata(&test_file).unwrap().len();
+        btree.delete(b"large").unwrap();
+        btree.save(b"other", small_value).unwrap();
+        btree.save(b"large", small_value).unwrap();
+        btree.buffer_manager.flush_all().unwrap();
+        assert_eq!(fs::metadata(&test_file).unwrap().len(), file_length);
+
+        // Replacing a large value frees its old overflow pages once the new ones are
+        // written, so the file grows by at most one value.
+        for _ in 0..5 {
+            btree.save(b"large", small_value).unwrap();
+        }
+        btree.buffer_manager.flush_all().unwrap();
+        assert!(
+            fs::metadata(&test_file).unwrap().len()
+                <= file_length
+                    + (small_value.len() / OVERFLOW_DATA_SIZE + 1) as u64 * PAGE_SIZE as u64
+        );
+        assert_eq!(btree.get(b"large").unwrap(), Some(small_value.to_vec()));
+        assert_eq!(btree.get(b"other").unwrap(), Some(small_value.to_vec()));
+        // Overflow pages aren't mistaken for malformed BTree pages.
+        assert!(DiskManager::verify(&test_file).unwrap().is_ok());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_salvage_recovers_every_readable_leaf() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_salvage");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+        let salvaged_file = temp_dir.join("salvaged.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in 0..2000 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        let large_value = vec![7u8; 3 * PAGE_SIZE];
+        btree.save(b"large", &large_value).unwrap();
+        // Merged leaves are freed, but still hold their rows in the file.
+        for index in 1500..2000 {
+            btree.delete(&key(index)).unwrap();
+        }
+        let corrupt_leaf = btree.find_leaf(&key(700)).unwrap();
+        let mut data = btree.read_node(corrupt_leaf).unwrap();
+        let lost_keys: HashSet<Vec<u8>> = BTreePage::from(&mut data).keys().into_iter().collect();
+        btree.buffer_manager.flush_all().unwrap();
+        drop(btree);
+
+        let mut file = fs::OpenOptions::new().write(true).open(&test_file).unwrap();
+        file.seek(SeekFrom::Start(
+            corrupt_leaf.value() * PAGE_SIZE as u64 + PAGE_SIZE as u64 / 2,
+        ))
+        .unwrap();
+        file.write_all(&[0xff]).unwrap();
+        drop(file);
+
+        let report = BTree::salvage(&test_file, &salvaged_file).unwrap();
+        assert_eq!(report.lost_pages, vec![corrupt_leaf]);
+        assert_eq!(report.lost_rows, 0);
+        assert_eq!(report.rows, 1500 - lost_keys.len() + 1);
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &salvaged_file).unwrap();
+        let mut salvaged = BTree::open(bpm, report.root).unwrap();
+        assert_eq!(salvaged.check_integrity(), Ok(()));
+        for index in 0..2000 {
+            let expected = (index < 1500 && !lost_keys.contains(&key(index))).then(|| value(index));
+            assert_eq!(salvaged.get(&key(index)).unwrap(), expected);
+        }
+        assert_eq!(salvaged.get(b"large").unwrap(), Some(large_value));
+
+        // The destination isn't overwritten.
+        let error = BTree::salvage(&test_file, &salvaged_file).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_clear_frees_every_page() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_clear");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        let fill = |btree: &mut BTree| {
+            for index in 0..2000 {
+                btree.save(&key(index), &value(index)).unwrap();
+            }
+            btree.save(b"large", &[1u8; 3 * PAGE_SIZE]).unwrap();
+            btree.buffer_manager.flush_all().unwrap();
+        };
+        fill(&mut btree);
+        let file_length = fs::metadata(&test_file).unwrap().len();
+
+        btree.clear().unwrap();
+        assert_eq!(height(&mut btree), 1);
+        assert_eq!(btree.get(&key(0)).unwrap(), None);
+        assert_eq!(btree.get(b"large").unwrap(), None);
+        let mut keys = Vec::new();
+        let root = btree.root;
+        collect_keys(&mut btree, root, b"", None, &mut keys);
+        assert!(keys.is_empty());
+
+        // Every page was freed, so filling the tree again reuses them.
+        fill(&mut btree);
+        assert_eq!(fs::metadata(&test_file).unwrap().len(), file_length);
+        assert_eq!(btree.get(&key(0)).unwrap(), Some(value(0)));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_save_rejects_oversized_keys() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_oversized");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        let key = [1u8; MAX_ENTRY_SIZE];
+        let error = btree.save(&key, b"value").err().unwrap();
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<RustyKVError>(),
+            Some(&RustyKVError::InsufficientSpace)
+        );
+        assert_eq!(btree.get(&key).unwrap(), None);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_snapshot_export_and_import() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_snapshot");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+        let snapshot_file = temp_dir.join("snapshot.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in 0..2000 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        let large_value = vec![7u8; 3 * PAGE_SIZE];
+        btree.save(b"large", &large_value).unwrap();
+
+        // Dirty pages still in the buffer pool are part of the snapshot.
+        btree.export_snapshot(&snapshot_file).unwrap();
+        let root = btree.root();
+        drop(btree);
+        fs::remove_file(&test_file).unwrap();
+
+        BTree::import_snapshot(&snapshot_file, &test_file).unwrap();
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::open(bpm, root).unwrap();
+        for index in 0..2000 {
+            assert_eq!(btree.get(&key(index)).unwrap(), Some(value(index)));
+        }
+        assert_eq!(btree.get(b"large").unwrap(), Some(large_value));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_bulk_load() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_bulk_load");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let large_value = vec![7u8; 3 * PAGE_SIZE];
+        let items = (0..10_000).map(|index| {
+            if index == 5000 {
+                (key(index), large_value.clone())
+            } else {
+                (key(index), value(index))
+            }
+        });
+        let root = BTree::bulk_load(items, &test_file).unwrap();
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::open(bpm, root).unwrap();
+        assert!(height(&mut btree) > 1);
+        let mut keys = Vec::new();
+        collect_keys(&mut btree, root, b"", None, &mut keys);
+        assert_eq!(keys, (0..10_000).map(key).collect::<Vec<_>>());
+
+        // Visit keys in a scattered order, so consecutive lookups hit different leaves.
+        for step in 0..10_000 {
+            let index = (step * 7919) % 10_000;
+            let expected = if index == 5000 {
+                large_value.clone()
+            } else {
+                value(index)
+            };
+            assert_eq!(btree.get(&key(index)).unwrap(), Some(expected));
+        }
+        assert_eq!(btree.get(b"missing").unwrap(), None);
+
+        // The loaded tree can be modified like any other.
+        btree.save(&key(10_000), &value(10_000)).unwrap();
+        btree.delete(&key(0)).unwrap();
+        assert_eq!(btree.get(&key(10_000)).unwrap(), Some(value(10_000)));
+        assert_eq!(btree.get(&key(0)).unwrap(), None);
+        drop(btree);
+
+        // Long keys fill internal pages quickly, so more than one level of them is built.
+        fs::remove_file(&test_file).unwrap();
+        let long_key = |index: usize| [key(index), vec![b'_'; 300]].concat();
+        let root = BTree::bulk_load(
+            (0..2000).map(|index| (long_key(index), value(index))),
+            &test_file,
+        )
+        .unwrap();
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::open(bpm, root).unwrap();
+        assert!(height(&mut btree) > 2);
+        let mut keys = Vec::new();
+        collect_keys(&mut btree, root, b"", None, &mut keys);
+        assert_eq!(keys, (0..2000).map(long_key).collect::<Vec<_>>());
+        for index in 0..2000 {
+            assert_eq!(btree.get(&long_key(index)).unwrap(), Some(value(index)));
+        }
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_bulk_load_rejects_unsorted_keys() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_bulk_load_unsorted");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let items = [(key(1), value(1)), (key(0), value(0))];
+        assert_eq!(
+            BTree::bulk_load(items.into_iter(), &test_file),
+            Err(RustyKVError::KeysOutOfOrder)
+        );
+        let items = [(key(0), value(0)), (key(0), value(1))];
+        assert_eq!(
+            BTree::bulk_load(items.into_iter(), &test_file),
+            Err(RustyKVError::KeysOutOfOrder)
+        );
+
+        // An empty input builds an empty tree.
+        let root = BTree::bulk_load(std::iter::empty(), &test_file).unwrap();
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::open(bpm, root).unwrap();
+        assert_eq!(btree.get(&key(0)).unwrap(), None);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_apply_batch() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_apply_batch");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in 0..500 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+
+        let mut batch = WriteBatch::new();
+        for index in 0..100 {
+            batch.delete(key(index));
+            batch.put(key(index + 1000), value(index + 1000));
+        }
+        batch.put(key(200), b"updated".to_vec());
+        btree.apply_batch(batch).unwrap();
+        for index in 0..100 {
+            assert_eq!(btree.get(&key(index)).unwrap(), None);
+            assert_eq!(
+                btree.get(&key(index + 1000)).unwrap(),
+                Some(value(index + 1000))
+            );
+        }
+        assert_eq!(btree.get(&key(200)).unwrap(), Some(b"updated".to_vec()));
+
+        // The oversized key fails the batch after every other operation has been applied.
+        let root = btree.root;
+        let mut keys = Vec::new();
+        collect_keys(&mut btree, root, b"", None, &mut keys);
+        let mut batch = WriteBatch::new();
+        for index in 100..300 {
+            batch.delete(key(index));
+        }
+        batch
+            .put(key(300), vec![1u8; 3 * PAGE_SIZE])
+            .put(key(5000), value(5000))
+            .put(vec![b'k'; PAGE_SIZE], value(0));
+        let error = btree.apply_batch(batch).err().unwrap();
+        assert_eq!(RustyKVError::from(error), RustyKVError::InsufficientSpace);
+
+        let mut keys_after = Vec::new();
+        let root = btree.root;
+        collect_keys(&mut btree, root, b"", None, &mut keys_after);
+        assert_eq!(keys_after, keys);
+        for index in 201..500 {
+            assert_eq!(btree.get(&key(index)).unwrap(), Some(value(index)));
+        }
+        assert_eq!(btree.get(&key(200)).unwrap(), Some(b"updated".to_vec()));
+        assert_eq!(btree.get(&key(5000)).unwrap(), None);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_apply_batch_rollback_failure() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_apply_batch_rollback");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in 0..500 {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        // Empty the first leaf but for its first key, without rebalancing it.
+        btree.set_min_occupancy(0.0);
+        let leaves = btree.get_leaves().unwrap();
+        let mut data = btree.read_node(leaves[0]).unwrap();
+        for key in BTreePage::from(&mut data).keys().into_iter().skip(1) {
+            btree.delete(&key).unwrap();
+        }
+        let root = btree.root;
+        btree.buffer_manager.flush_all().unwrap();
+        drop(btree);
+
+        // Corrupt the sibling of the first leaf, which only a rebalance of the first leaf
+        // reads.
+        let mut file = fs::OpenOptions::new().write(true).open(&test_file).unwrap();
+        file.seek(SeekFrom::Start(
+            leaves[1].value() * PAGE_SIZE as u64 + PAGE_SIZE as u64 / 2,
+        ))
+        .unwrap();
+        file.write_all(&[0xff]).unwrap();
+        drop(file);
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::open(bpm, root).unwrap();
+        // Undoing the put into the first leaf leaves it underflowing, so it's rebalanced.
+        btree.set_min_occupancy(0.5);
+        let mut large_key = key(0);
+        large_key.extend_from_slice(&[b'a'; PAGE_SIZE]);
+        let mut batch = WriteBatch::new();
+        batch
+            .put([key(0), b"a".to_vec()].concat(), value(0))
+            .put(large_key, value(0));
+        let error = btree.apply_batch(batch).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Other);
+        match error.get_ref().unwrap().downcast_ref::<RustyKVError>() {
+            Some(RustyKVError::RollbackFailed(error, rollback_error)) => {
+                assert_eq!(
+                    error.get_ref().unwrap().downcast_ref::<RustyKVError>(),
+                    Some(&RustyKVError::InsufficientSpace)
+                );
+                assert_eq!(
+                    rollback_error
+                        .get_ref()
+                        .unwrap()
+                        .downcast_ref::<RustyKVError>(),
+                    Some(&RustyKVError::ChecksumMismatch)
+                );
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_range() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_range");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in (0..2000).step_by(2) {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        assert!(height(&mut btree) > 1);
+
+        // Bounds that aren't in the tree, spanning several leaves.
+        let rows: Vec<_> = btree.range(&key(301), &key(1501)).unwrap().collect();
+        let expected: Vec<_> = (302..1501)
+            .step_by(2)
+            .map(|index| (key(index), value(index)))
+            .collect();
+        assert_eq!(rows, expected);
+
+        // The start is inclusive and the end exclusive.
+        let keys: Vec<_> = btree
+            .range(&key(10), &key(16))
+            .unwrap()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![key(10), key(12), key(14)]);
+
+        assert_eq!(btree.range(b"", b"\xff").unwrap().count(), 1000);
+        assert_eq!(btree.range(&key(16), &key(10)).unwrap().count(), 0);
+        assert_eq!(btree.range(&key(10), &key(10)).unwrap().count(), 0);
+
+        // Tombstoned rows are left out.
+        btree.set_tombstone_deletes(true);
+        btree.delete(&key(12)).unwrap();
+        let keys: Vec<_> = btree
+            .range(&key(10), &key(16))
+            .unwrap()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![key(10), key(14)]);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_delete_range() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_delete_range");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        assert_eq!(btree.delete_range(b"", b"\xff").unwrap(), 0);
+        for index in (0..2000).step_by(2) {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        let large_value = vec![7u8; 20 * 1024];
+        btree.save(&key(501), &large_value).unwrap();
+        assert!(height(&mut btree) > 1);
+
+        // Bounds that aren't in the tree, spanning several leaves, and a row with overflow
+        // pages, which must be freed.
+        assert_eq!(btree.delete_range(&key(301), &key(1501)).unwrap(), 601);
+        assert_eq!(btree.delete_range(&key(16), &key(10)).unwrap(), 0);
+        assert_eq!(btree.delete_range(&key(301), &key(1501)).unwrap(), 0);
+        let expected: Vec<_> = (0..2000)
+            .step_by(2)
+            .filter(|index| !(301..1501).contains(index))
+            .map(key)
+            .collect();
+        assert_eq!(btree.keys().unwrap(), expected);
+        assert_eq!(btree.check_integrity(), Ok(()));
+
+        assert_eq!(btree.delete_range(b"", b"\xff").unwrap(), 400);
+        assert!(btree.keys().unwrap().is_empty());
+        assert_eq!(btree.check_integrity(), Ok(()));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_count_range() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_count_range");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        assert_eq!(btree.count_range(b"", b"\xff").unwrap(), 0);
+        for index in (0..2000).step_by(2) {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        assert!(height(&mut btree) > 1);
+
+        // Bounds that aren't in the tree, spanning several leaves.
+        assert_eq!(btree.count_range(&key(301), &key(1501)).unwrap(), 600);
+        assert_eq!(btree.count_range(&key(10), &key(16)).unwrap(), 3);
+        assert_eq!(btree.count_range(b"", b"\xff").unwrap(), 1000);
+        assert_eq!(btree.count_range(&key(16), &key(10)).unwrap(), 0);
+        assert_eq!(btree.count_range(&key(11), &key(12)).unwrap(), 0);
+        for (start, end) in [(0, 2000), (301, 1501), (777, 779), (1990, 5000)] {
+            assert_eq!(
+                btree.count_range(&key(start), &key(end)).unwrap(),
+                btree.range(&key(start), &key(end)).unwrap().count()
+            );
+        }
+
+        // Tombstoned rows aren't counted.
+        btree.set_tombstone_deletes(true);
+        btree.delete(&key(12)).unwrap();
+        assert_eq!(btree.count_range(&key(10), &key(16)).unwrap(), 2);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_range_rev_and_last() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_range_rev");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        assert_eq!(btree.last(10).unwrap().count(), 0);
+        for index in (0..2000).step_by(2) {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        assert!(height(&mut btree) > 1);
+
+        // Bounds that aren't in the tree, spanning several leaves.
+        let rows: Vec<_> = btree.range_rev(&key(301), &key(1501)).unwrap().collect();
+        let expected: Vec<_> = (302..1501)
+            .step_by(2)
+            .rev()
+            .map(|index| (key(index), value(index)))
+            .collect();
+        assert_eq!(rows, expected);
+
+        let keys: Vec<_> = btree
+            .range_rev(&key(10), &key(16))
+            .unwrap()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![key(14), key(12), key(10)]);
+        assert_eq!(btree.range_rev(b"", b"\xff").unwrap().count(), 1000);
+        assert_eq!(btree.range_rev(&key(16), &key(10)).unwrap().count(), 0);
+
+        // The latest rows may span several leaves, and there may be fewer than asked for.
+        let expected: Vec<_> = (0..2000)
+            .step_by(2)
+            .rev()
+            .take(300)
+            .map(|index| (key(index), value(index)))
+            .collect();
+        assert_eq!(btree.last(300).unwrap().collect::<Vec<_>>(), expected);
+        assert_eq!(
+            btree.last(1).unwrap().collect::<Vec<_>>(),
+            vec![(key(1998), value(1998))]
+        );
+        assert_eq!(btree.last(5000).unwrap().count(), 1000);
+        assert_eq!(btree.last(0).unwrap().count(), 0);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_keys() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_keys");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        assert!(btree.keys().unwrap().is_empty());
+        // INSERTS and 7 are coprime, so this saves every index once, out of order.
+        const INSERTS: usize = 1000;
+        for index in (0..INSERTS).map(|index| index * 7 % INSERTS) {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        assert!(height(&mut btree) > 1);
+
+        let expected: Vec<Vec<u8>> = (0..INSERTS).map(key).collect();
+        assert_eq!(btree.keys().unwrap(), expected);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_rank() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_rank");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        assert_eq!(btree.rank(&key(0)).unwrap(), 0);
+        btree.set_tombstone_deletes(true);
+        // Only the even indices are saved, and the multiples of 10 are deleted again, leaving
+        // tombstones that mustn't be counted.
+        for index in (0..2000).step_by(2) {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        for index in (0..2000).step_by(10) {
+            btree.delete(&key(index)).unwrap();
+        }
+        assert!(height(&mut btree) > 1);
+
+        let present = |index: usize| index % 2 == 0 && index % 10 != 0;
+        for index in 0..2001 {
+            let expected = (0..index).filter(|&smaller| present(smaller)).count();
+            assert_eq!(btree.rank(&key(index)).unwrap(), expected);
+        }
+        assert_eq!(btree.rank(b"").unwrap(), 0);
+        assert_eq!(btree.rank(b"zzz").unwrap(), 800);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_scan_prefix() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_scan_prefix");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        // Interleave the prefixes, so that matching keys are spread across leaves.
+        let prefixes: [&[u8]; 4] = [b"use", b"user:", b"users", b"user"];
+        let mut expected = std::collections::BTreeMap::new();
+        for index in 0..2000 {
+            let mut prefixed_key = prefixes[index % prefixes.len()].to_vec();
+            prefixed_key.extend_from_slice(&key(index));
+            btree.save(&prefixed_key, &value(index)).unwrap();
+            expected.insert(prefixed_key, value(index));
+        }
+        let large_value = vec![7u8; 2 * PAGE_SIZE];
+        btree.save(b"user:large", &large_value).unwrap();
+        expected.insert(b"user:large".to_vec(), large_value);
+        assert!(height(&mut btree) > 1);
+
+        for prefix in [
+            &b"user:"[..],
+            b"users",
+            b"user",
+            b"use",
+            b"user:key0001",
+            b"",
+        ] {
+            let rows: Vec<(Vec<u8>, Vec<u8>)> = btree.scan_prefix(prefix).unwrap().collect();
+            let expected_rows: Vec<(Vec<u8>, Vec<u8>)> = expected
+                .iter()
+                .filter(|(key, _)| key.starts_with(prefix))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            assert!(!rows.is_empty());
+            assert_eq!(rows, expected_rows);
+        }
+        assert_eq!(btree.scan_prefix(b"user;").unwrap().count(), 0);
+        assert_eq!(btree.scan_prefix(b"a").unwrap().count(), 0);
+        assert_eq!(btree.scan_prefix(b"z").unwrap().count(), 0);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    struct CaseInsensitive;
+
+    impl Comparator for CaseInsensitive {
+        fn name(&self) -> &str {
+            "case-insensitive"
+        }
+
+        fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+            a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase())
+        }
+    }
+
+    #[test]
+    fn test_case_insensitive_comparator() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_comparator");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::with_comparator(bpm, &CaseInsensitive).unwrap();
+        // Alternate the case, so that lexicographic order would interleave the keys.
+        let mixed_key = |index: usize| {
+            let key = key(index);
+            if index.is_multiple_of(2) {
+                key.to_ascii_uppercase()
+            } else {
+                key
+            }
+        };
+        for index in 0..2000 {
+            btree.save(&mixed_key(index), &value(index)).unwrap();
+        }
+        assert!(height(&mut btree) > 1);
+        assert_eq!(btree.check_integrity(), Ok(()));
+
+        for index in 0..2000 {
+            let lower = key(index).to_ascii_lowercase();
+            let upper = key(index).to_ascii_uppercase();
+            assert_eq!(btree.get(&lower).unwrap(), Some(value(index)));
+            assert_eq!(btree.get(&upper).unwrap(), Some(value(index)));
+        }
+        let keys: Vec<Vec<u8>> = btree
+            .range(b"KEY00010", b"key00014")
+            .unwrap()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, (10..14).map(mixed_key).collect::<Vec<_>>());
+
+        assert_eq!(
+            btree.save(b"KEY00001", b"updated").unwrap(),
+            SaveOutcome::Updated
+        );
+        assert_eq!(btree.get(b"key00001").unwrap(), Some(b"updated".to_vec()));
+        assert_eq!(
+            btree.scan_prefix(b"key").err().unwrap().kind(),
+            ErrorKind::Unsupported
+        );
+
+        let root = btree.root();
+        btree.buffer_manager.flush_all().unwrap();
+        drop(btree);
+
+        // The comparator is recorded in the data file.
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let error = BTree::open(bpm, root).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(
+            error.to_string(),
+            RustyKVError::ComparatorMismatch.to_string()
+        );
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::open_with_comparator(bpm, root, &CaseInsensitive).unwrap();
+        assert_eq!(btree.get(b"Key00002").unwrap(), Some(value(2)));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_parallel_scan() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_btree_parallel_scan");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        for index in (0..2000).rev() {
+            btree.save(&key(index), &value(index)).unwrap();
+        }
+        let large_value = vec![7u8; 3 * PAGE_SIZE];
+        btree.save(b"large", &large_value).unwrap();
+        assert!(height(&mut btree) > 1);
+        btree.flush().unwrap();
+
+        let serial: Vec<(Vec<u8>, Vec<u8>)> = btree.range(b"", b"zzz").unwrap().collect();
+        let threads = std::sync::Mutex::new(HashSet::new());
+        let parallel = btree
+            .parallel_scan(4, |key, value| {
+                threads.lock().unwrap().insert(thread::current().id());
+                (key.to_vec(), value.to_vec())
+            })
+            .unwrap();
+        assert_eq!(parallel.len(), 2001);
+        assert_eq!(parallel, serial);
+        assert_eq!(threads.lock().unwrap().len(), 4);
+
+        // Changes that haven't been flushed are seen, whether their pages are still in the
+        // buffer pool or were evicted and written back.
+        for index in 0..1500 {
+            btree.delete(&key(index)).unwrap();
+        }
+        for index in 0..20 {
+            btree.save(&key(index), &large_value).unwrap();
+        }
+        assert!(btree.buffer_manager.dirty_page_count() > 0);
+        let serial: Vec<(Vec<u8>, Vec<u8>)> = btree.range(b"", b"zzz").unwrap().collect();
+        let parallel = btree
+            .parallel_scan(4, |key, value| (key.to_vec(), value.to_vec()))
+            .unwrap();
+        assert_eq!(parallel.len(), 521);
+        assert_eq!(parallel, serial);
+
+        let error = btree.parallel_scan(0, |_, _| ()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        // More shards than leaves leaves some shards empty.
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &temp_dir.join("small.db")).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        btree.save(b"key", b"value").unwrap();
+        assert_eq!(
+            btree.parallel_scan(3, |key, _| key.to_vec()).unwrap(),
+            vec![b"key".to_vec()]
+        );
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+}
@@ -1,18 +1,70 @@
-use crate::store::btree_kv::frame::{Frame, FrameHandler, FrameMetadata};
+use crate::store::btree_kv::frame::{Frame, FrameHandler, FrameMetadata, PageLatch};
 use crate::store::btree_kv::{
     cache_policy_engine::{CachePolicyEngineFactory, EvictionPolicy, ICachePolicyEngine},
     commons::{PAGE_SIZE, PageId},
-    disk_manager::DiskManager,
+    disk_manager::{DiskManager, SyncMode},
+    error::RustyKVError,
+    storage_backend::StorageBackend,
 };
-use std::io::Error;
-use std::ops::Deref;
-use std::{collections::HashMap, path::Path, sync::Arc};
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::Ordering;
+use std::{collections::HashMap, path::Path};
 
-pub struct BufferManager {
+///
+/// Configuration for the background writer. Once the ratio of dirty frames in
+/// the pool crosses `high_water_mark`, the writer flushes dirty frames until
+/// the ratio drops to `low_water_mark`.
+///
+#[derive(Clone, Copy)]
+pub struct BackgroundWriterConfig {
+    pub high_water_mark: f32,
+    pub low_water_mark: f32,
+}
+
+///
+/// Counters for the page accesses served by the buffer pool, to help size it.
+///
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct BufferPoolStats {
+    // Number of `get` calls served from the buffer pool.
+    pub hits: u64,
+    // Number of `get` calls that read the page from disk.
+    pub misses: u64,
+    // Number of pages evicted to make room for another page.
+    pub evictions: u64,
+}
+
+impl BufferPoolStats {
+    ///
+    /// Returns the fraction of page accesses served from the buffer pool, or 0 if no page
+    /// has been accessed.
+    ///
+    pub fn hit_ratio(&self) -> f64 {
+        let accesses = self.hits + self.misses;
+        if accesses == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / accesses as f64
+    }
+}
+
+///
+/// Caches pages of the data file in a fixed number of frames. Pages are stored in a
+/// `DiskManager` by default, or in any other `StorageBackend` passed to `with_backend`.
+///
+/// # Latching
+/// Each frame has a read/write latch guarding its data. `FrameHandler`s take it for the
+/// duration of each access, and `latch` hands out `PageLatch`es that can be used from other
+/// threads while the buffer pool is used elsewhere. To avoid deadlocks:
+/// * The buffer pool is always acquired before a page latch. Release any page guards before
+///   calling into the buffer pool, since writing a page back waits for its latch.
+/// * When guarding several pages at once, take their guards in ascending Page ID order.
+///
+pub struct BufferManager<B: StorageBackend = DiskManager> {
     // Capacity of the buffer pool. In bytes.
     capacity: usize,
     // Handles disk operations for the Buffer Pool Manager.
-    disk_manager: DiskManager,
+    disk_manager: B,
     // Buffer Pool. This contains Frames of data.
     pool: Vec<Frame>,
     // Metadata for the buffer pool frames.
@@ -23,6 +75,18 @@ pub struct BufferManager {
     cache_policy_engine: Box<dyn ICachePolicyEngine<PageId>>,
     // Indicates the slots in buffer pool that are vacant.
     vacant_slots: Vec<usize>,
+    // Proactively flushes dirty frames on tick(), if enabled.
+    background_writer: Option<BackgroundWriterConfig>,
+    // Hit, miss and eviction counters.
+    stats: BufferPoolStats,
+    // Called with the Page ID of every evicted page, if set.
+    eviction_listener: Option<Box<dyn Fn(PageId) + Send>>,
+    // Flushes every dirty frame when the buffer pool is dropped, if set.
+    flush_on_drop: bool,
+    // Evicts clean frames before dirty ones, if set.
+    prefer_clean_eviction: bool,
+    // Number of frames freed at once when a page is read into a full buffer pool.
+    eviction_batch_size: usize,
 }
 
 impl BufferManager {
@@ -30,43 +94,190 @@ impl BufferManager {
         Self::new_with_path(size, Path::new("data.db"))
     }
 
+    ///
+    /// Creates a buffer pool backed by the data file at `path`, that evicts the least
+    /// recently used page when full. See `new_with_policy`.
+    ///
+    /// # Arguments
+    /// * `size`: Capacity of the buffer pool, in bytes. Must be a non-zero multiple of
+    ///   PAGE_SIZE.
+    /// * `path`: Path to the data file.
+    ///
+    /// # Returns
+    /// * `Ok(BufferManager)` if the buffer pool was created.
+    /// * `Err(std::io::Error)` if the data file couldn't be opened. If `size` isn't a
+    ///   non-zero multiple of PAGE_SIZE, the error is of kind `InvalidInput` and wraps
+    ///   `RustyKVError::InvalidBufferPoolSize`.
+    ///
     pub fn new_with_path(size: usize, path: &Path) -> Result<Self, Error> {
+        Self::new_with_policy(size, path, EvictionPolicy::LRU)
+    }
+
+    ///
+    /// Creates a buffer pool backed by the data file at `path`.
+    ///
+    /// # Arguments
+    /// * `size`: Capacity of the buffer pool, in bytes. Must be a non-zero multiple of
+    ///   PAGE_SIZE.
+    /// * `path`: Path to the data file.
+    /// * `policy`: Policy used to pick the page to evict when the buffer pool is full.
+    ///
+    /// # Returns
+    /// * `Ok(BufferManager)` if the buffer pool was created.
+    /// * `Err(std::io::Error)` if the data file couldn't be opened. If `size` isn't a
+    ///   non-zero multiple of PAGE_SIZE, the error is of kind `InvalidInput` and wraps
+    ///   `RustyKVError::InvalidBufferPoolSize`.
+    ///
+    pub fn new_with_policy(
+        size: usize,
+        path: &Path,
+        policy: EvictionPolicy,
+    ) -> Result<Self, Error> {
+        // Checked before the data file is created.
+        Self::check_size(size)?;
+        let disk_manager = DiskManager::new(path, SyncMode::Deferred)?;
+        Self::with_backend(size, disk_manager, policy)
+    }
+
+    ///
+    /// Creates a buffer pool that reads pages from an existing data file opened with
+    /// `DiskManager::open_readonly`, and evicts the least recently used page when full.
+    ///
+    /// Pages can still be modified in the buffer pool, but writing them back fails, so a
+    /// dirty page can't be flushed or evicted. Allocating and deallocating pages fails too.
+    /// These errors are of kind `PermissionDenied` and wrap `RustyKVError::ReadOnly`.
+    ///
+    /// # Arguments
+    /// * `size`: Capacity of the buffer pool, in bytes. Must be a non-zero multiple of
+    ///   PAGE_SIZE.
+    /// * `path`: Path to the data file.
+    ///
+    /// # Returns
+    /// * `Ok(BufferManager)` if the buffer pool was created.
+    /// * `Err(std::io::Error)` if the data file couldn't be opened. If `size` isn't a
+    ///   non-zero multiple of PAGE_SIZE, the error is of kind `InvalidInput` and wraps
+    ///   `RustyKVError::InvalidBufferPoolSize`.
+    ///
+    pub fn open_readonly(size: usize, path: &Path) -> Result<Self, Error> {
+        Self::check_size(size)?;
+        let disk_manager = DiskManager::open_readonly(path)?;
+        Self::with_backend(size, disk_manager, EvictionPolicy::LRU)
+    }
+
+    ///
+    /// Returns the path to the data file.
+    ///
+    pub(crate) fn path(&self) -> &Path {
+        self.disk_manager.path()
+    }
+}
+
+impl<B: StorageBackend> BufferManager<B> {
+    ///
+    /// Creates a buffer pool that stores its pages in `backend`.
+    ///
+    /// # Arguments
+    /// * `size`: Capacity of the buffer pool, in bytes. Must be a non-zero multiple of
+    ///   PAGE_SIZE.
+    /// * `backend`: Stores the pages, e.g. a `MemoryDiskManager` to keep them in memory.
+    /// * `policy`: Policy used to pick the page to evict when the buffer pool is full.
+    ///
+    /// # Returns
+    /// * `Ok(BufferManager)` if the buffer pool was created.
+    /// * `Err(std::io::Error)` of kind `InvalidInput` wrapping
+    ///   `RustyKVError::InvalidBufferPoolSize`, if `size` isn't a non-zero multiple of
+    ///   PAGE_SIZE.
+    ///
+    pub fn with_backend(size: usize, backend: B, policy: EvictionPolicy) -> Result<Self, Error> {
+        Self::check_size(size)?;
         let pool_slots = size / PAGE_SIZE;
-        match DiskManager::new(path) {
-            Ok(disk_manager) => Ok(BufferManager {
-                capacity: size,
-                disk_manager,
-                pool: vec![Frame::default(); pool_slots],
-                pool_metadata: vec![FrameMetadata::default(); pool_slots],
-                pool_lookup: HashMap::new(),
-                cache_policy_engine: CachePolicyEngineFactory::get_engine(
-                    EvictionPolicy::LRU,
-                    pool_slots,
-                ),
-                vacant_slots: (0..pool_slots).collect(),
-            }),
-            Err(error) => Err(error),
-        }
-    }
-
-    pub fn get(&mut self, page_id: PageId) -> Result<FrameHandler, Error> {
+        Ok(BufferManager {
+            capacity: size,
+            disk_manager: backend,
+            pool: (0..pool_slots).map(|_| Frame::default()).collect(),
+            pool_metadata: vec![FrameMetadata::default(); pool_slots],
+            pool_lookup: HashMap::new(),
+            cache_policy_engine: CachePolicyEngineFactory::get_engine(policy, pool_slots),
+            vacant_slots: (0..pool_slots).collect(),
+            background_writer: None,
+            stats: BufferPoolStats::default(),
+            eviction_listener: None,
+            flush_on_drop: false,
+            prefer_clean_eviction: false,
+            eviction_batch_size: 1,
+        })
+    }
+
+    ///
+    /// Returns the name of the comparator recorded by the backend, see
+    /// `StorageBackend::get_comparator_name`.
+    ///
+    pub(crate) fn get_comparator_name(&self) -> Option<&str> {
+        self.disk_manager.get_comparator_name()
+    }
+
+    ///
+    /// Records the name of the comparator in the backend, see
+    /// `StorageBackend::set_comparator_name`.
+    ///
+    pub(crate) fn set_comparator_name(&mut self, name: &str) -> Result<(), Error> {
+        self.disk_manager.set_comparator_name(name)
+    }
+
+    ///
+    /// Checks that a buffer pool size is a non-zero multiple of PAGE_SIZE.
+    ///
+    fn check_size(size: usize) -> Result<(), Error> {
+        if size < PAGE_SIZE || !size.is_multiple_of(PAGE_SIZE) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                RustyKVError::InvalidBufferPoolSize,
+            ));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Fetches a page, reading it from disk into the buffer pool if it isn't present. If
+    /// the buffer pool is full, a page is evicted to make room for it.
+    ///
+    /// # Arguments
+    /// * `page_id`: Page ID of the page to be fetched.
+    ///
+    /// # Returns
+    /// * `Ok(FrameHandler)`: Handler through which the page can be read or written.
+    /// * `Err(RustyKVError::BufferPoolExhausted)` if every frame is pinned or latched.
+    /// * `Err(RustyKVError::ChecksumMismatch)` if the page on disk is corrupt.
+    /// * `Err(RustyKVError::ItemNotFound)` if the page hasn't been allocated.
+    /// * `Err(RustyKVError::Io)` if the page couldn't be read, or the evicted page couldn't
+    ///   be written back.
+    ///
+    pub fn get(&mut self, page_id: PageId) -> Result<FrameHandler<'_>, RustyKVError> {
         let frame_index;
         if self.pool_lookup.contains_key(&page_id) {
             // Page already present in Buffer Pool.
             frame_index = *self.pool_lookup.get(&page_id).unwrap();
+            self.stats.hits += 1;
 
-            // Update cache to indicate that this page has been accessed.
-            self.cache_policy_engine.touch(&page_id);
+            // Update cache to indicate that this page has been accessed. Pinned pages
+            // aren't tracked by the cache.
+            if self.pool_metadata[frame_index].pin_count == 0 {
+                self.cache_policy_engine.touch(&page_id);
+            }
         } else {
             // Page not present in Buffer Pool.
             // 1. Fetch page from Disk.
             let mut data: [u8; PAGE_SIZE] = [0u8; PAGE_SIZE];
             self.disk_manager.read_page(&page_id, &mut data)?;
+            self.stats.misses += 1;
 
             // 2. Find a vacant slot.
+            if self.vacant_slots.is_empty() && self.eviction_batch_size > 1 {
+                self.evict_batch(self.eviction_batch_size);
+            }
             match self.vacant_slots.pop() {
                 None => {
-                    frame_index = self.evict_slot();
+                    frame_index = self.evict_slot()?;
                 }
                 Some(index) => {
                     frame_index = index;
@@ -74,7 +285,7 @@ impl BufferManager {
             };
 
             // 3. Update buffer pool.
-            self.pool[frame_index].data = Arc::new(data);
+            self.pool[frame_index].reset(data);
             self.pool_metadata[frame_index].page_id = Some(page_id);
             self.pool_metadata[frame_index].is_dirty = false;
             self.pool_lookup.insert(page_id, frame_index);
@@ -89,38 +300,566 @@ impl BufferManager {
         ))
     }
 
-    // TODO: Add reference counting to prevent eviction of active pages
-    fn evict_slot(&mut self) -> usize {
-        let evicted_index = self.cache_policy_engine.evict();
+    ///
+    /// Fetches the first `buffer.len()` bytes of a page, without reading it into the buffer
+    /// pool. A page in the buffer pool is copied from its frame; otherwise only those bytes
+    /// are read from storage, without verifying the page checksum. Neither counts as an
+    /// access of the page, for the stats or the cache policy.
+    ///
+    /// # Arguments
+    /// * `page_id`: Page ID of the page to read from.
+    /// * `buffer`: Buffer that needs to be populated. At most PAGE_SIZE bytes long.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the bytes were read.
+    /// * `Err(std::io::Error)` if the page couldn't be read.
+    ///
+    pub fn read_page_head(&mut self, page_id: PageId, buffer: &mut [u8]) -> Result<(), Error> {
+        match self.pool_lookup.get(&page_id) {
+            Some(frame_index) => {
+                let page = self.pool[*frame_index].data.read().unwrap();
+                buffer.copy_from_slice(&page[..buffer.len()]);
+                Ok(())
+            }
+            None => self.disk_manager.read_page_head(&page_id, buffer),
+        }
+    }
+
+    ///
+    /// Pins a page so that it can't be evicted, fetching it into the buffer pool first if
+    /// needed. Pins are counted; the page can be evicted again once it has been unpinned
+    /// as many times as it was pinned.
+    ///
+    /// # Arguments
+    /// * `page_id`: Page ID of the page to be pinned.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the page was pinned.
+    /// * `Err(std::io::Error)` if the page couldn't be fetched. If every frame is pinned, the
+    ///   error wraps `RustyKVError::BufferPoolExhausted`.
+    ///
+    pub fn pin(&mut self, page_id: PageId) -> Result<(), Error> {
+        self.get(page_id)?;
+        let frame_index = *self.pool_lookup.get(&page_id).unwrap();
+        if self.pool_metadata[frame_index].pin_count == 0 {
+            self.cache_policy_engine.remove(&page_id);
+        }
+        self.pool_metadata[frame_index].pin_count += 1;
+        Ok(())
+    }
+
+    ///
+    /// Releases a pin on a page.
+    ///
+    /// # Arguments
+    /// * `page_id`: Page ID of the page to be unpinned.
+    ///
+    /// # Returns
+    /// * `true` if a pin was released, `false` if the page isn't pinned.
+    ///
+    pub fn unpin(&mut self, page_id: PageId) -> bool {
+        let frame_index = match self.pool_lookup.get(&page_id) {
+            None => return false,
+            Some(frame_index) => *frame_index,
+        };
+        let metadata = &mut self.pool_metadata[frame_index];
+        if metadata.pin_count == 0 {
+            return false;
+        }
+        metadata.pin_count -= 1;
+        if metadata.pin_count == 0 {
+            self.cache_policy_engine.touch(&page_id);
+        }
+        true
+    }
+
+    ///
+    /// Allocates a new page on disk. The page isn't fetched into the buffer pool until it
+    /// is accessed through `get`.
+    ///
+    /// # Returns
+    /// * `Ok(PageId)`: Page ID of the allocated page.
+    /// * `Err(std::io::Error)` if an error occurred while updating the disk.
+    ///
+    pub fn allocate_page(&mut self) -> Result<PageId, Error> {
+        self.disk_manager.allocate_page()
+    }
 
-        // 1. Fetch evicted frame data and metadata.
-        let evicted_frame_index = *self.pool_lookup.get(&evicted_index).unwrap();
-        let evicted_frame = &mut self.pool[evicted_frame_index];
-        let evicted_frame_metadata = &mut self.pool_metadata[evicted_frame_index];
+    ///
+    /// Deallocates a page on disk so that it can be reused. If the page is in the buffer
+    /// pool, its frame is discarded without being written back.
+    ///
+    /// # Arguments
+    /// * `page_id`: Page ID of the page to be deallocated. The page must not be pinned.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the page was deallocated.
+    /// * `Err(std::io::Error)` if an error occurred while updating the disk.
+    ///
+    pub fn deallocate_page(&mut self, page_id: PageId) -> Result<(), Error> {
+        if let Some(frame_index) = self.pool_lookup.remove(&page_id) {
+            assert_eq!(
+                self.pool_metadata[frame_index].pin_count, 0,
+                "Pinned pages can't be deallocated"
+            );
+            assert!(
+                !self.pool[frame_index].is_latched(),
+                "Latched pages can't be deallocated"
+            );
+            self.cache_policy_engine.remove(&page_id);
+            self.pool_metadata[frame_index] = FrameMetadata::default();
+            self.vacant_slots.push(frame_index);
+        }
+        self.disk_manager.deallocate_page(page_id)
+    }
 
-        // 2. Fetch Page ID for the evicted slot.
-        let evicted_page_id = evicted_frame_metadata.page_id.unwrap();
+    ///
+    /// Enables the background writer. Dirty frames are only flushed when
+    /// `tick` is called.
+    ///
+    /// # Arguments
+    /// * `config`: High and low water marks for the dirty frame ratio.
+    ///
+    pub fn set_background_writer(&mut self, config: BackgroundWriterConfig) {
+        assert!(config.low_water_mark <= config.high_water_mark);
+        self.background_writer = Some(config);
+    }
+
+    ///
+    /// Makes dropping the buffer pool flush every dirty page to disk, as `flush_all` does, so
+    /// that changes that were never evicted aren't lost if it isn't called. Disabled by
+    /// default.
+    ///
+    /// Errors can't be returned from a drop, so they're printed to stderr instead. Call
+    /// `flush_all` before dropping the buffer pool to handle them.
+    ///
+    /// # Arguments
+    /// * `flush_on_drop`: Whether to flush dirty pages on drop.
+    ///
+    pub fn set_flush_on_drop(&mut self, flush_on_drop: bool) {
+        self.flush_on_drop = flush_on_drop;
+    }
+
+    ///
+    /// Makes eviction pick the first clean frame in cache policy order, rather than the
+    /// first frame, so that a page doesn't have to be written to disk before another can
+    /// be read. Dirty frames are only evicted when every unpinned frame is dirty. Disabled
+    /// by default.
+    ///
+    /// Dirty frames passed over are touched again, which counts as an access under the
+    /// cache policy, so they're kept longer than they would be otherwise.
+    ///
+    /// # Arguments
+    /// * `prefer_clean_eviction`: Whether to evict clean frames first.
+    ///
+    pub fn set_prefer_clean_eviction(&mut self, prefer_clean_eviction: bool) {
+        self.prefer_clean_eviction = prefer_clean_eviction;
+    }
+
+    ///
+    /// Sets the number of frames freed at once when a page is read into a full buffer
+    /// pool, see `evict_batch`. Under heavy miss rates, batches write dirty pages back
+    /// together and spare the following misses an eviction each, at the cost of evicting
+    /// pages that could still have been hit. Defaults to 1, a single eviction per miss.
+    ///
+    /// # Arguments
+    /// * `eviction_batch_size`: Number of frames to free at once. Values below 1 are
+    ///   treated as 1.
+    ///
+    pub fn set_eviction_batch_size(&mut self, eviction_batch_size: usize) {
+        self.eviction_batch_size = eviction_batch_size.max(1);
+    }
+
+    ///
+    /// Drives the background writer. If the dirty frame ratio is above the high
+    /// water mark, dirty frames are written to disk until the ratio is at or below
+    /// the low water mark. This smooths out the write storms caused by evicting
+    /// many dirty frames at once.
+    ///
+    /// # Returns
+    /// * `Ok(usize)`: Number of frames flushed.
+    /// * `Err(std::io::Error)` if an error occurred while writing to the disk.
+    ///
+    /// # Impl Note
+    /// A `FrameHandler` holds a mutable borrow of the pool, so a tick can never
+    /// flush a frame while it is being mutated.
+    ///
+    pub fn tick(&mut self) -> Result<usize, Error> {
+        let config = match self.background_writer {
+            None => return Ok(0),
+            Some(config) => config,
+        };
+
+        let pool_slots = self.pool.len() as f32;
+        let mut dirty_count = self
+            .pool_metadata
+            .iter()
+            .filter(|metadata| metadata.is_dirty)
+            .count();
+        if dirty_count as f32 / pool_slots <= config.high_water_mark {
+            return Ok(0);
+        }
+
+        let mut flushed = 0;
+        for frame_index in 0..self.pool.len() {
+            if dirty_count as f32 / pool_slots <= config.low_water_mark {
+                break;
+            }
+            if self.write_back(frame_index)? {
+                dirty_count -= 1;
+                flushed += 1;
+            }
+        }
+        Ok(flushed)
+    }
+
+    ///
+    /// Writes a page to disk if it is dirty, without evicting it.
+    ///
+    /// # Arguments
+    /// * `page_id`: Page ID of the page to be flushed.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the page was flushed, or wasn't dirty or present in the buffer pool.
+    /// * `Err(std::io::Error)` if an error occurred while writing to the disk.
+    ///
+    pub fn flush_page(&mut self, page_id: PageId) -> Result<(), Error> {
+        if let Some(frame_index) = self.pool_lookup.get(&page_id) {
+            self.write_back(*frame_index)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Writes every dirty page to disk, without evicting them, and syncs the file.
+    ///
+    /// # Returns
+    /// * `Ok(())` if all dirty pages were flushed.
+    /// * `Err(std::io::Error)` if an error occurred while writing to the disk.
+    ///
+    pub fn flush_all(&mut self) -> Result<(), Error> {
+        for frame_index in 0..self.pool.len() {
+            self.write_back(frame_index)?;
+        }
+        self.disk_manager.sync()
+    }
+
+    ///
+    /// Writes every dirty page to disk and copies the data file to `path`, so that the
+    /// snapshot holds every write made through the buffer pool.
+    ///
+    /// # Arguments
+    /// * `path`: Path the snapshot is written to. An existing file is replaced.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the snapshot was written.
+    /// * `Err(std::io::Error)` if an error occurred while flushing or copying the file.
+    ///
+    pub fn export_snapshot(&mut self, path: &Path) -> Result<(), Error> {
+        self.flush_all()?;
+        self.disk_manager.export_snapshot(path)
+    }
+
+    ///
+    /// Latches a page, fetching it into the buffer pool first if needed. The page isn't
+    /// evicted until every latch on it has been dropped.
+    ///
+    /// # Arguments
+    /// * `page_id`: Page ID of the page to be latched.
+    ///
+    /// # Returns
+    /// * `Ok(PageLatch)`: Latch through which the page can be read or written.
+    /// * `Err(std::io::Error)` if the page couldn't be fetched.
+    ///
+    pub fn latch(&mut self, page_id: PageId) -> Result<PageLatch, Error> {
+        self.get(page_id)?;
+        let frame_index = *self.pool_lookup.get(&page_id).unwrap();
+        Ok(PageLatch::new(page_id, &self.pool[frame_index]))
+    }
+
+    ///
+    /// Registers a callback that is called with the Page ID of every page evicted from the
+    /// buffer pool, after it has been written back. Replaces any previous callback.
+    ///
+    /// # Arguments
+    /// * `listener`: Called on every eviction. It must not block, as it runs inside `get`.
+    ///
+    pub fn on_evict(&mut self, listener: impl Fn(PageId) + Send + 'static) {
+        self.eviction_listener = Some(Box::new(listener));
+    }
+
+    ///
+    /// Returns the hit, miss and eviction counters since the buffer pool was created.
+    ///
+    pub fn stats(&self) -> BufferPoolStats {
+        self.stats
+    }
+
+    ///
+    /// Returns the number of pages in the buffer pool with changes that haven't been
+    /// written to disk, including pages written through a `PageLatch`.
+    ///
+    pub fn dirty_page_count(&self) -> usize {
+        self.iter_dirty_pages().count()
+    }
+
+    ///
+    /// Returns the Page IDs of the pages in the buffer pool with changes that haven't been
+    /// written to disk, including pages written through a `PageLatch`. They can be flushed
+    /// with `flush_page`, e.g. by a custom flushing strategy.
+    ///
+    /// # Returns
+    /// * `Vec<PageId>`: Page IDs of the dirty pages, in ascending order, so that flushing
+    ///   them in turn writes the data file sequentially.
+    ///
+    pub fn dirty_pages(&self) -> Vec<PageId> {
+        let mut page_ids: Vec<PageId> = self.iter_dirty_pages().collect();
+        page_ids.sort_by_key(PageId::value);
+        page_ids
+    }
+
+    ///
+    /// Returns the frame of every page in the buffer pool, by Page ID, so that they can be
+    /// read from other threads under their latches while the buffer pool is borrowed. Pages
+    /// that aren't in the buffer pool are up to date in the backend, since dirty pages are
+    /// written back when they're evicted.
+    ///
+    pub(crate) fn resident_frames(&self) -> HashMap<PageId, &Frame> {
+        self.pool_lookup
+            .iter()
+            .map(|(page_id, frame_index)| (*page_id, &self.pool[*frame_index]))
+            .collect()
+    }
+
+    ///
+    /// Iterates over the Page IDs of the dirty pages in the buffer pool, in frame order.
+    ///
+    fn iter_dirty_pages(&self) -> impl Iterator<Item = PageId> + '_ {
+        (0..self.pool.len())
+            .filter(|frame_index| self.is_frame_dirty(*frame_index))
+            .filter_map(|frame_index| self.pool_metadata[frame_index].page_id)
+    }
+
+    ///
+    /// Returns `true` if a frame has changes that haven't been written to disk, through a
+    /// FrameHandler or a PageLatch.
+    ///
+    fn is_frame_dirty(&self, frame_index: usize) -> bool {
+        self.pool_metadata[frame_index].is_dirty
+            || self.pool[frame_index].latch_dirty.load(Ordering::Acquire)
+    }
+
+    ///
+    /// Returns the Page IDs of every page allocated in the backend and not deallocated since,
+    /// whether or not it's in the buffer pool. Page 0 is reserved by the backend, and isn't
+    /// included.
+    ///
+    /// # Returns
+    /// * `Vec<PageId>`: Page IDs of the allocated pages, in ascending order.
+    ///
+    pub fn allocated_pages(&self) -> Vec<PageId> {
+        let free_pages = self.disk_manager.free_pages();
+        (1..self.disk_manager.num_pages() as u64)
+            .map(PageId::new)
+            .filter(|page_id| !free_pages.contains(page_id))
+            .collect()
+    }
+
+    ///
+    /// Returns the number of pages in the buffer pool with at least one pin.
+    ///
+    pub fn pinned_page_count(&self) -> usize {
+        self.pool_metadata
+            .iter()
+            .filter(|metadata| metadata.pin_count > 0)
+            .count()
+    }
+
+    ///
+    /// Writes the frame to disk if it is dirty and clears the dirty bit.
+    ///
+    /// # Returns
+    /// * `Ok(true)` if the frame was written, `Ok(false)` if it wasn't dirty.
+    /// * `Err(std::io::Error)` if an error occurred while writing to the disk.
+    ///
+    fn write_back(&mut self, frame_index: usize) -> Result<bool, Error> {
+        let metadata = &mut self.pool_metadata[frame_index];
+        let frame = &self.pool[frame_index];
+        let page_id = match metadata.page_id {
+            None => return Ok(false),
+            Some(page_id) => page_id,
+        };
+        // The flag is cleared before the page is read, so that a write through a PageLatch
+        // that races with the write back marks the frame dirty again.
+        let latch_dirty = frame.take_latch_dirty();
+        if !metadata.is_dirty && !latch_dirty {
+            return Ok(false);
+        }
+
+        let page = frame.data.read().unwrap();
+        if let Err(error) = self.disk_manager.write_page(&page_id, &page) {
+            if latch_dirty {
+                frame.latch_dirty.store(true, Ordering::Release);
+            }
+            return Err(error);
+        }
+        metadata.is_dirty = false;
+        Ok(true)
+    }
+
+    ///
+    /// Evicts a page from the buffer pool, writing it to disk if it's dirty.
+    ///
+    /// # Returns
+    /// * `Ok(usize)`: Index of the frame that was freed.
+    /// * `Err(RustyKVError::BufferPoolExhausted)` if every frame is pinned or latched.
+    /// * `Err(RustyKVError::Io)` if the evicted page couldn't be written to disk.
+    ///
+    fn evict_slot(&mut self) -> Result<usize, RustyKVError> {
+        match self.select_victims(1).pop() {
+            None => Err(RustyKVError::BufferPoolExhausted),
+            Some(page_id) => self.release_frame(page_id),
+        }
+    }
+
+    ///
+    /// Evicts up to `n` pages in one pass over the cache policy, freeing their frames for
+    /// the pages read next. Dirty victims are written back in Page ID order, so that the
+    /// writes are as sequential as the data file allows.
+    ///
+    /// Fewer than `n` frames are freed if the rest are pinned or latched, or if a page
+    /// couldn't be written back. The page is then kept, along with the victims not yet
+    /// written, and the error is reported by the next eviction that picks it.
+    ///
+    /// # Arguments
+    /// * `n`: Maximum number of frames to free.
+    ///
+    /// # Returns
+    /// * `Vec<usize>`: Indices of the frames that were freed.
+    ///
+    pub fn evict_batch(&mut self, n: usize) -> Vec<usize> {
+        let mut victims = self.select_victims(n);
+        victims.sort_by_key(PageId::value);
+        let mut freed = Vec::with_capacity(victims.len());
+        for (index, page_id) in victims.iter().enumerate() {
+            match self.release_frame(*page_id) {
+                Ok(frame_index) => {
+                    self.vacant_slots.push(frame_index);
+                    freed.push(frame_index);
+                }
+                Err(_) => {
+                    for page_id in &victims[index + 1..] {
+                        self.cache_policy_engine.touch(page_id);
+                    }
+                    break;
+                }
+            }
+        }
+        freed
+    }
+
+    ///
+    /// Removes up to `n` victims from the cache policy, in eviction order. Latched frames,
+    /// and dirty ones if clean frames are preferred, are skipped, and touched again once
+    /// the victims have been selected. Dirty frames skipped are only selected if too few
+    /// clean ones are found.
+    ///
+    /// Pinned pages aren't tracked by the cache policy engine, so fewer than `n` victims
+    /// are returned only if the remaining frames are pinned or latched.
+    ///
+    fn select_victims(&mut self, n: usize) -> Vec<PageId> {
+        let mut victims = Vec::new();
+        // Frames passed over, and whether they were passed over for being dirty.
+        let mut skipped = Vec::new();
+        while victims.len() < n && self.cache_policy_engine.get_size() > 0 {
+            let page_id = self.cache_policy_engine.evict();
+            let frame_index = *self.pool_lookup.get(&page_id).unwrap();
+            if self.pool[frame_index].is_latched() {
+                skipped.push((page_id, false));
+            } else if self.prefer_clean_eviction && self.is_frame_dirty(frame_index) {
+                skipped.push((page_id, true));
+            } else {
+                victims.push(page_id);
+            }
+        }
+        for (page_id, is_dirty) in skipped {
+            if is_dirty && victims.len() < n {
+                victims.push(page_id);
+            } else {
+                self.cache_policy_engine.touch(&page_id);
+            }
+        }
+        victims
+    }
+
+    ///
+    /// Frees the frame of a page removed from the cache policy, writing the page to disk
+    /// first if it's dirty.
+    ///
+    /// # Returns
+    /// * `Ok(usize)`: Index of the frame that was freed.
+    /// * `Err(RustyKVError::Io)` if the page couldn't be written to disk. The page is
+    ///   touched again, so it stays in the buffer pool.
+    ///
+    fn release_frame(&mut self, evicted_page_id: PageId) -> Result<usize, RustyKVError> {
+        // 1. Fetch evicted frame index.
+        let evicted_frame_index = *self.pool_lookup.get(&evicted_page_id).unwrap();
+
+        // 2. Write entry to disk if the frame was dirty.
+        // TODO: Make dirty check and write atomic to prevent race conditions
+        if let Err(error) = self.write_back(evicted_frame_index) {
+            // Keep the page in the buffer pool so the changes aren't lost.
+            self.cache_policy_engine.touch(&evicted_page_id);
+            return Err(error.into());
+        }
 
         // 3. Delete entry for that Page ID from buffer_pool_lookup.
         self.pool_lookup.remove(&evicted_page_id);
+        self.pool_metadata[evicted_frame_index].page_id = None;
+        self.stats.evictions += 1;
+        if let Some(listener) = &self.eviction_listener {
+            listener(evicted_page_id);
+        }
+        Ok(evicted_frame_index)
+    }
+}
 
-        // 4. Write entry to disk if the frame was dirty.
-        // TODO: Make dirty check and write atomic to prevent race conditions
-        if evicted_frame_metadata.is_dirty {
-            self.disk_manager
-                .write_page(&evicted_page_id, evicted_frame.data.deref())
-                .expect("Failed to write to disk.");
-            evicted_frame_metadata.is_dirty = false;
+impl<B: StorageBackend> Drop for BufferManager<B> {
+    ///
+    /// Flushes every dirty page if `set_flush_on_drop` enabled it. Skipped while panicking,
+    /// since a frame's latch may have been poisoned.
+    ///
+    fn drop(&mut self) {
+        if !self.flush_on_drop || std::thread::panicking() {
+            return;
+        }
+        if let Err(error) = self.flush_all() {
+            eprintln!("Failed to flush the buffer pool on drop: {}", error);
         }
-        evicted_frame_index
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::store::btree_kv::commons::PAGE_CHECKSUM_SIZE;
+    use crate::store::btree_kv::memory_disk_manager::MemoryDiskManager;
     use std::env;
     use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    ///
+    /// Creates a buffer pool of `slots` frames, backed by memory.
+    ///
+    fn memory_pool(slots: usize) -> BufferManager<MemoryDiskManager> {
+        BufferManager::with_backend(
+            slots * PAGE_SIZE,
+            MemoryDiskManager::new(),
+            EvictionPolicy::LRU,
+        )
+        .unwrap()
+    }
 
     #[test]
     fn test_buffer_pool_creation() {
@@ -136,6 +875,142 @@ mod tests {
         let _ = fs::remove_dir_all(&temp_dir);
     }
 
+    #[test]
+    fn test_buffer_pool_rejects_invalid_sizes() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_invalid_size");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        for size in [0, PAGE_SIZE - 1, PAGE_SIZE + PAGE_SIZE / 2] {
+            let error = BufferManager::new_with_path(size, &test_file)
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+            assert_eq!(
+                error.get_ref().unwrap().downcast_ref::<RustyKVError>(),
+                Some(&RustyKVError::InvalidBufferPoolSize)
+            );
+        }
+        // The data file isn't created for an invalid size.
+        assert!(!test_file.exists());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_stats_count_hits_misses_and_evictions() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_stats");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let mut bpm = BufferManager::new_with_path(2 * PAGE_SIZE, &test_file).unwrap();
+        assert_eq!(bpm.stats(), BufferPoolStats::default());
+        assert_eq!(bpm.stats().hit_ratio(), 0.0);
+
+        let pages: Vec<PageId> = (0..3).map(|_| bpm.allocate_page().unwrap()).collect();
+        bpm.get(pages[0]).unwrap(); // Miss
+        bpm.get(pages[1]).unwrap(); // Miss
+        bpm.get(pages[0]).unwrap(); // Hit
+        bpm.get(pages[1]).unwrap(); // Hit
+        bpm.get(pages[2]).unwrap(); // Miss, evicts pages[0]
+        bpm.get(pages[0]).unwrap(); // Miss, evicts pages[1]
+        bpm.get(pages[0]).unwrap(); // Hit
+
+        let stats = bpm.stats();
+        assert_eq!(stats.hits, 3);
+        assert_eq!(stats.misses, 4);
+        assert_eq!(stats.evictions, 2);
+        assert_eq!(stats.hit_ratio(), 3.0 / 7.0);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_on_evict_reports_evicted_pages() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_on_evict");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let mut bpm = BufferManager::new_with_path(2 * PAGE_SIZE, &test_file).unwrap();
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let listener_evicted = Arc::clone(&evicted);
+        bpm.on_evict(move |page_id| listener_evicted.lock().unwrap().push(page_id));
+
+        let pages: Vec<PageId> = (0..4).map(|_| bpm.allocate_page().unwrap()).collect();
+        for page_id in &pages {
+            bpm.get(*page_id).unwrap();
+        }
+        assert_eq!(*evicted.lock().unwrap(), vec![pages[0], pages[1]]);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_latched_pages_are_shared_and_not_evicted() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_latch");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let mut bpm = BufferManager::new_with_path(2 * PAGE_SIZE, &test_file).unwrap();
+        let page_id = bpm.allocate_page().unwrap();
+        let latch = bpm.latch(page_id).unwrap();
+        let writes = 200u8;
+
+        // Readers share the page and must never see a partially written page.
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let latch = latch.clone();
+                thread::spawn(move || {
+                    for _ in 0..writes {
+                        let page = latch.read();
+                        let value = page[PAGE_CHECKSUM_SIZE];
+                        assert!(page[PAGE_CHECKSUM_SIZE..].iter().all(|byte| *byte == value));
+                    }
+                })
+            })
+            .collect();
+        let writer = {
+            let latch = latch.clone();
+            thread::spawn(move || {
+                for value in 1..=writes {
+                    latch.write()[PAGE_CHECKSUM_SIZE..].fill(value);
+                }
+            })
+        };
+
+        // Cycle other pages through the pool. The latched page takes up one of the two
+        // frames, so every other page evicts the one before it.
+        let others: Vec<PageId> = (0..20).map(|_| bpm.allocate_page().unwrap()).collect();
+        for other in &others {
+            bpm.get(*other).unwrap();
+            assert!(bpm.pool_lookup.contains_key(&page_id));
+        }
+
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        writer.join().unwrap();
+        assert_eq!(bpm.stats().evictions, others.len() as u64 - 1);
+
+        // Writes through the latch are flushed like any other write.
+        drop(latch);
+        bpm.flush_all().unwrap();
+        let mut data = [0u8; PAGE_SIZE];
+        bpm.disk_manager.read_page(&page_id, &mut data).unwrap();
+        assert!(
+            data[PAGE_CHECKSUM_SIZE..]
+                .iter()
+                .all(|byte| *byte == writes)
+        );
+
+        // Once the latch is dropped, the page can be evicted.
+        bpm.get(others[0]).unwrap();
+        bpm.get(others[1]).unwrap();
+        assert!(!bpm.pool_lookup.contains_key(&page_id));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
     #[test]
     fn test_get_page_cache_miss() {
         let temp_dir = env::temp_dir().join("rusty_kv_test_miss");
@@ -145,8 +1020,7 @@ mod tests {
         let mut bpm = BufferManager::new_with_path(8000, &test_file).unwrap();
         let page_id = PageId::new(0);
 
-        let result = bpm.get(page_id);
-        assert!(result.is_ok());
+        assert!(bpm.get(page_id).is_ok());
         assert!(bpm.pool_lookup.contains_key(&page_id));
         assert_eq!(bpm.vacant_slots.len(), 0);
 
@@ -163,10 +1037,10 @@ mod tests {
         let page_id = PageId::new(0);
 
         // First access - cache miss
-        let _frame1 = bpm.get(page_id).unwrap();
+        bpm.get(page_id).unwrap();
 
         // Second access - cache hit
-        let _frame2 = bpm.get(page_id).unwrap();
+        bpm.get(page_id).unwrap();
 
         assert!(bpm.pool_lookup.contains_key(&page_id));
         assert_eq!(bpm.vacant_slots.len(), 0);
@@ -180,19 +1054,655 @@ mod tests {
         fs::create_dir_all(&temp_dir).unwrap();
         let test_file = temp_dir.join("test.db");
 
-        let mut bpm = BufferManager::new_with_path(8000, &test_file).unwrap(); // Only 1 slot
+        check_eviction(BufferManager::new_with_path(8000, &test_file).unwrap()); // Only 1 slot
 
-        let page1 = PageId::new(0);
-        let page2 = PageId::new(1);
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_eviction_in_memory() {
+        check_eviction(memory_pool(1));
+    }
+
+    fn check_eviction<B: StorageBackend>(mut bpm: BufferManager<B>) {
+        let page1 = bpm.allocate_page().unwrap();
+        let page2 = bpm.allocate_page().unwrap();
 
         // Fill the buffer pool
-        let _frame1 = bpm.get(page1).unwrap();
+        bpm.get(page1).unwrap();
         assert!(bpm.pool_lookup.contains_key(&page1));
 
         // This should trigger eviction
-        let _frame2 = bpm.get(page2).unwrap();
+        bpm.get(page2).unwrap();
         assert!(bpm.pool_lookup.contains_key(&page2));
         assert!(!bpm.pool_lookup.contains_key(&page1));
+    }
+
+    #[test]
+    fn test_eviction_with_lfu_policy() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_eviction_lfu");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let mut bpm =
+            BufferManager::new_with_policy(2 * PAGE_SIZE, &test_file, EvictionPolicy::LFU).unwrap();
+        let pages: Vec<PageId> = (0..3).map(|_| bpm.allocate_page().unwrap()).collect();
+        bpm.get(pages[0]).unwrap();
+        bpm.get(pages[0]).unwrap();
+        bpm.get(pages[0]).unwrap();
+        bpm.get(pages[1]).unwrap();
+
+        // LRU would evict pages[0], but it has been accessed more often than pages[1].
+        bpm.get(pages[2]).unwrap();
+        assert!(bpm.pool_lookup.contains_key(&pages[0]));
+        assert!(!bpm.pool_lookup.contains_key(&pages[1]));
+        assert!(bpm.pool_lookup.contains_key(&pages[2]));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_pinned_pages_are_not_evicted() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_pinned");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        check_pinned_pages_are_not_evicted(
+            BufferManager::new_with_path(2 * PAGE_SIZE, &test_file).unwrap(),
+        );
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_pinned_pages_are_not_evicted_in_memory() {
+        check_pinned_pages_are_not_evicted(memory_pool(2));
+    }
+
+    ///
+    /// Counts the full page reads made through a MemoryDiskManager.
+    ///
+    struct CountingBackend {
+        backend: MemoryDiskManager,
+        reads: Arc<Mutex<usize>>,
+    }
+
+    impl StorageBackend for CountingBackend {
+        fn read_page(&mut self, id: &PageId, buffer: &mut [u8; PAGE_SIZE]) -> Result<(), Error> {
+            *self.reads.lock().unwrap() += 1;
+            self.backend.read_page(id, buffer)
+        }
+
+        fn read_page_head(&mut self, id: &PageId, buffer: &mut [u8]) -> Result<(), Error> {
+            let mut page = [0u8; PAGE_SIZE];
+            self.backend.read_page(id, &mut page)?;
+            buffer.copy_from_slice(&page[..buffer.len()]);
+            Ok(())
+        }
+
+        fn write_page(&mut self, id: &PageId, buffer: &[u8; PAGE_SIZE]) -> Result<(), Error> {
+            self.backend.write_page(id, buffer)
+        }
+
+        fn allocate_page(&mut self) -> Result<PageId, Error> {
+            self.backend.allocate_page()
+        }
+
+        fn deallocate_page(&mut self, id: PageId) -> Result<(), Error> {
+            self.backend.deallocate_page(id)
+        }
+
+        fn sync(&mut self) -> Result<(), Error> {
+            self.backend.sync()
+        }
+
+        fn export_snapshot(&mut self, path: &Path) -> Result<(), Error> {
+            self.backend.export_snapshot(path)
+        }
+
+        fn num_pages(&self) -> usize {
+            self.backend.num_pages()
+        }
+
+        fn free_pages(&self) -> &[PageId] {
+            self.backend.free_pages()
+        }
+    }
+
+    #[test]
+    fn test_read_page_head_skips_the_buffer_pool() {
+        let reads = Arc::new(Mutex::new(0));
+        let backend = CountingBackend {
+            backend: MemoryDiskManager::new(),
+            reads: Arc::clone(&reads),
+        };
+        let mut bpm = BufferManager::with_backend(PAGE_SIZE, backend, EvictionPolicy::LRU).unwrap();
+        let page1 = bpm.allocate_page().unwrap();
+        let page2 = bpm.allocate_page().unwrap();
+        bpm.get(page1)
+            .unwrap()
+            .with_page_mut(|data| data[PAGE_CHECKSUM_SIZE..].fill(1));
+        bpm.get(page2)
+            .unwrap()
+            .with_page_mut(|data| data[PAGE_CHECKSUM_SIZE..].fill(2));
+        let reads_before = *reads.lock().unwrap();
+        let stats = bpm.stats();
+
+        // page1 was evicted, and is read from storage without a full page read.
+        let mut head = [0u8; 16];
+        bpm.read_page_head(page1, &mut head).unwrap();
+        assert_eq!(head[PAGE_CHECKSUM_SIZE..], [1u8; 16 - PAGE_CHECKSUM_SIZE]);
+        // page2 is copied from its frame.
+        bpm.read_page_head(page2, &mut head).unwrap();
+        assert_eq!(head[PAGE_CHECKSUM_SIZE..], [2u8; 16 - PAGE_CHECKSUM_SIZE]);
+
+        assert_eq!(*reads.lock().unwrap(), reads_before);
+        assert_eq!(bpm.stats(), stats);
+        assert!(!bpm.pool_lookup.contains_key(&page1));
+    }
+
+    fn check_pinned_pages_are_not_evicted<B: StorageBackend>(mut bpm: BufferManager<B>) {
+        let page1 = bpm.allocate_page().unwrap();
+        let page2 = bpm.allocate_page().unwrap();
+        let page3 = bpm.allocate_page().unwrap();
+
+        bpm.pin(page1).unwrap();
+        let _ = bpm.get(page2).unwrap();
+
+        // page2 is the only candidate for eviction, even though page1 is older.
+        let _ = bpm.get(page3).unwrap();
+        assert!(bpm.pool_lookup.contains_key(&page1));
+        assert!(!bpm.pool_lookup.contains_key(&page2));
+
+        // Once unpinned, page1 can be evicted again. Unpinning counts as an access.
+        assert!(bpm.unpin(page1));
+        assert!(!bpm.unpin(page1));
+        let _ = bpm.get(page2).unwrap();
+        assert!(!bpm.pool_lookup.contains_key(&page3));
+        let _ = bpm.get(page3).unwrap();
+        assert!(!bpm.pool_lookup.contains_key(&page1));
+    }
+
+    #[test]
+    fn test_get_fails_when_every_frame_is_pinned() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_exhausted");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        check_get_fails_when_every_frame_is_pinned(
+            BufferManager::new_with_path(2 * PAGE_SIZE, &test_file).unwrap(),
+        );
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_get_fails_when_every_frame_is_pinned_in_memory() {
+        check_get_fails_when_every_frame_is_pinned(memory_pool(2));
+    }
+
+    fn check_get_fails_when_every_frame_is_pinned<B: StorageBackend>(mut bpm: BufferManager<B>) {
+        for _ in 1..4 {
+            bpm.allocate_page().unwrap();
+        }
+        bpm.pin(PageId::new(1)).unwrap();
+        bpm.pin(PageId::new(2)).unwrap();
+
+        assert_eq!(
+            bpm.get(PageId::new(3)).err(),
+            Some(RustyKVError::BufferPoolExhausted)
+        );
+
+        // Pinned pages can still be fetched.
+        assert!(bpm.get(PageId::new(1)).is_ok());
+
+        // Once a page is unpinned, its frame can be reused.
+        bpm.unpin(PageId::new(2));
+        assert!(bpm.get(PageId::new(3)).is_ok());
+    }
+
+    #[test]
+    fn test_evicted_pages_are_written_back_in_memory() {
+        let mut bpm = memory_pool(1);
+        let page1 = bpm.allocate_page().unwrap();
+        let page2 = bpm.allocate_page().unwrap();
+        bpm.get(page1)
+            .unwrap()
+            .with_page_mut(|data| data[PAGE_CHECKSUM_SIZE..].fill(7));
+
+        // This evicts page1, writing it back to the backend.
+        assert!(!bpm.get(page2).unwrap().is_dirty());
+        assert_eq!(bpm.stats().evictions, 1);
+
+        let frame = bpm.get(page1).unwrap();
+        assert!(!frame.is_dirty());
+        assert!(
+            frame.page()[PAGE_CHECKSUM_SIZE..]
+                .iter()
+                .all(|byte| *byte == 7)
+        );
+        drop(frame);
+        assert_eq!(bpm.disk_manager.num_pages(), 3);
+    }
+
+    #[test]
+    fn test_prefer_clean_eviction() {
+        let mut bpm = memory_pool(3);
+        bpm.set_prefer_clean_eviction(true);
+        let pages: Vec<PageId> = (0..5).map(|_| bpm.allocate_page().unwrap()).collect();
+        bpm.get(pages[0])
+            .unwrap()
+            .with_page_mut(|data| data[PAGE_SIZE - 1] = 1);
+        bpm.get(pages[1]).unwrap();
+        bpm.get(pages[2])
+            .unwrap()
+            .with_page_mut(|data| data[PAGE_SIZE - 1] = 1);
+
+        // pages[0] is the least recently used, but it's dirty.
+        bpm.get(pages[3]).unwrap();
+        assert!(!bpm.pool_lookup.contains_key(&pages[1]));
+        assert!(bpm.pool_lookup.contains_key(&pages[0]));
+        assert_eq!(bpm.dirty_page_count(), 2);
+
+        // With every frame dirty, the least recently used one is evicted. Passing over
+        // pages[0] touched it, so that's pages[2].
+        bpm.get(pages[3])
+            .unwrap()
+            .with_page_mut(|data| data[PAGE_SIZE - 1] = 1);
+        bpm.get(pages[4]).unwrap();
+        assert!(!bpm.pool_lookup.contains_key(&pages[2]));
+        assert!(bpm.pool_lookup.contains_key(&pages[0]));
+        assert!(bpm.pool_lookup.contains_key(&pages[3]));
+        assert_eq!(bpm.stats().evictions, 2);
+    }
+
+    #[test]
+    fn test_evict_batch() {
+        let mut bpm = memory_pool(4);
+        let pages: Vec<PageId> = (0..4).map(|_| bpm.allocate_page().unwrap()).collect();
+        for page_id in &pages {
+            bpm.get(*page_id)
+                .unwrap()
+                .with_page_mut(|data| data[PAGE_SIZE - 1] = 1);
+        }
+        bpm.pin(pages[1]).unwrap();
+
+        // Pinned frames aren't freed, dirty ones are written back first.
+        let pinned_frame = bpm.pool_lookup[&pages[1]];
+        let freed = bpm.evict_batch(8);
+        assert_eq!(freed.len(), 3);
+        assert!(!freed.contains(&pinned_frame));
+        assert_eq!(bpm.vacant_slots.len(), 3);
+        assert_eq!(bpm.dirty_pages(), vec![pages[1]]);
+        assert_eq!(bpm.stats().evictions, 3);
+        assert!(bpm.evict_batch(1).is_empty());
+    }
+
+    #[test]
+    fn test_eviction_batch_size() {
+        let mut bpm = memory_pool(8);
+        bpm.set_eviction_batch_size(4);
+        let pages: Vec<PageId> = (0..20).map(|_| bpm.allocate_page().unwrap()).collect();
+        for page_id in &pages[..8] {
+            bpm.get(*page_id).unwrap();
+        }
+        assert_eq!(bpm.stats().evictions, 0);
+
+        // The first miss into a full pool frees 4 frames, the next 3 use them.
+        bpm.get(pages[8]).unwrap();
+        assert_eq!(bpm.stats().evictions, 4);
+        assert_eq!(bpm.vacant_slots.len(), 3);
+        for page_id in &pages[9..12] {
+            bpm.get(*page_id).unwrap();
+        }
+        assert_eq!(bpm.stats().evictions, 4);
+        assert!(bpm.vacant_slots.is_empty());
+        for page_id in &pages[..4] {
+            assert!(!bpm.pool_lookup.contains_key(page_id));
+        }
+
+        // Every further miss is served in batches of 4.
+        for page_id in &pages[12..] {
+            bpm.get(*page_id).unwrap();
+        }
+        let stats = bpm.stats();
+        assert_eq!(stats.misses, 20);
+        assert_eq!(stats.evictions, 12);
+    }
+
+    #[test]
+    fn test_get_reports_corrupt_pages() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_get_corrupt");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let page_id = {
+            let mut bpm = BufferManager::new_with_path(PAGE_SIZE, &test_file).unwrap();
+            let page_id = bpm.allocate_page().unwrap();
+            bpm.get(page_id).unwrap().with_page_mut(|data| data.fill(1));
+            bpm.flush_all().unwrap();
+            page_id
+        };
+        let mut file_data = fs::read(&test_file).unwrap();
+        file_data[page_id.value() as usize * PAGE_SIZE + PAGE_CHECKSUM_SIZE] ^= 0xFF;
+        fs::write(&test_file, &file_data).unwrap();
+
+        let mut bpm = BufferManager::new_with_path(PAGE_SIZE, &test_file).unwrap();
+        assert_eq!(bpm.get(page_id).err(), Some(RustyKVError::ChecksumMismatch));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_open_readonly_rejects_writes() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_open_readonly");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let (page1, page2) = {
+            let mut bpm = BufferManager::new_with_path(PAGE_SIZE, &test_file).unwrap();
+            let page1 = bpm.allocate_page().unwrap();
+            let page2 = bpm.allocate_page().unwrap();
+            for page_id in [page1, page2] {
+                bpm.get(page_id)
+                    .unwrap()
+                    .with_page_mut(|data| data[PAGE_CHECKSUM_SIZE..].fill(page_id.value() as u8));
+            }
+            bpm.flush_all().unwrap();
+            (page1, page2)
+        };
+
+        let mut bpm = BufferManager::open_readonly(PAGE_SIZE, &test_file).unwrap();
+        for page_id in [page1, page2] {
+            assert!(
+                bpm.get(page_id).unwrap().page()[PAGE_CHECKSUM_SIZE..]
+                    .iter()
+                    .all(|byte| *byte == page_id.value() as u8)
+            );
+        }
+        let error = bpm.allocate_page().err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(RustyKVError::from(error), RustyKVError::ReadOnly);
+
+        // A modified page can't be written back, so it stays in the buffer pool.
+        bpm.get(page2)
+            .unwrap()
+            .with_page_mut(|data| data[PAGE_CHECKSUM_SIZE..].fill(9));
+        assert_eq!(bpm.get(page1).err(), Some(RustyKVError::ReadOnly));
+        let error = bpm.flush_all().err().unwrap();
+        assert_eq!(RustyKVError::from(error), RustyKVError::ReadOnly);
+        assert!(bpm.get(page2).unwrap().is_dirty());
+        drop(bpm);
+
+        let mut bpm = BufferManager::new_with_path(PAGE_SIZE, &test_file).unwrap();
+        assert!(
+            bpm.get(page2).unwrap().page()[PAGE_CHECKSUM_SIZE..]
+                .iter()
+                .all(|byte| *byte == 2)
+        );
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_write_through_handler_persists_after_eviction() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_write_through");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let page1;
+        let page2;
+        {
+            let mut bpm = BufferManager::new_with_path(PAGE_SIZE, &test_file).unwrap(); // Only 1 slot
+            page1 = bpm.allocate_page().unwrap();
+            page2 = bpm.allocate_page().unwrap();
+            let mut frame = bpm.get(page1).unwrap();
+            frame.with_page_mut(|data| {
+                data[PAGE_CHECKSUM_SIZE..PAGE_CHECKSUM_SIZE + 3].copy_from_slice(&[7, 8, 9])
+            });
+            assert!(frame.is_dirty());
+            drop(frame);
+
+            // This evicts page1, writing it back to disk.
+            let frame = bpm.get(page2).unwrap();
+            assert!(!frame.is_dirty());
+        }
+
+        let mut bpm = BufferManager::new_with_path(PAGE_SIZE, &test_file).unwrap();
+        let frame = bpm.get(page1).unwrap();
+        assert_eq!(
+            frame.page()[PAGE_CHECKSUM_SIZE..PAGE_CHECKSUM_SIZE + 3],
+            [7, 8, 9]
+        );
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_deallocate_page_discards_frame() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_deallocate");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let mut bpm = BufferManager::new_with_path(2 * PAGE_SIZE, &test_file).unwrap();
+        let page_id = bpm.allocate_page().unwrap();
+        bpm.get(page_id).unwrap().with_page_mut(|data| data.fill(1));
+
+        bpm.deallocate_page(page_id).unwrap();
+        assert!(!bpm.pool_lookup.contains_key(&page_id));
+        assert_eq!(bpm.vacant_slots.len(), 2);
+
+        // The page is reused, and the discarded changes were never written.
+        assert_eq!(bpm.allocate_page().unwrap(), page_id);
+        assert!(
+            bpm.get(page_id)
+                .unwrap()
+                .page()
+                .iter()
+                .all(|byte| *byte == 0)
+        );
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_flush_page_and_flush_all() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_flush");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let mut bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        for id in 1..4 {
+            assert_eq!(bpm.allocate_page().unwrap(), PageId::new(id));
+            bpm.get(PageId::new(id))
+                .unwrap()
+                .with_page_mut(|data| data.fill(id as u8));
+        }
+
+        let read_file_page = |id: u64| -> Vec<u8> {
+            let contents = fs::read(&test_file).unwrap();
+            let offset = id as usize * PAGE_SIZE;
+            contents
+                .get(offset + PAGE_CHECKSUM_SIZE..offset + PAGE_SIZE)
+                .unwrap_or(&[])
+                .to_vec()
+        };
+
+        bpm.flush_page(PageId::new(1)).unwrap();
+        assert_eq!(read_file_page(1), vec![1u8; PAGE_SIZE - PAGE_CHECKSUM_SIZE]);
+        assert!(!bpm.get(PageId::new(1)).unwrap().is_dirty());
+        assert!(bpm.get(PageId::new(2)).unwrap().is_dirty());
+
+        bpm.flush_all().unwrap();
+        for id in 1..4 {
+            assert_eq!(
+                read_file_page(id),
+                vec![id as u8; PAGE_SIZE - PAGE_CHECKSUM_SIZE]
+            );
+            assert!(!bpm.get(PageId::new(id)).unwrap().is_dirty());
+        }
+        // Flushing doesn't evict.
+        assert_eq!(bpm.pool_lookup.len(), 3);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_dirty_and_pinned_page_counts() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_page_counts");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let mut bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let pages: Vec<PageId> = (0..4).map(|_| bpm.allocate_page().unwrap()).collect();
+        assert_eq!(bpm.dirty_page_count(), 0);
+        assert_eq!(bpm.pinned_page_count(), 0);
+
+        bpm.get(pages[0])
+            .unwrap()
+            .with_page_mut(|data| data.fill(1));
+        bpm.get(pages[1])
+            .unwrap()
+            .with_page_mut(|data| data.fill(2));
+        bpm.get(pages[2]).unwrap();
+        bpm.latch(pages[3]).unwrap().write().fill(4);
+        assert_eq!(bpm.dirty_page_count(), 3);
+
+        bpm.pin(pages[0]).unwrap();
+        bpm.pin(pages[0]).unwrap();
+        bpm.pin(pages[2]).unwrap();
+        assert_eq!(bpm.pinned_page_count(), 2);
+        bpm.unpin(pages[0]);
+        assert_eq!(bpm.pinned_page_count(), 2);
+        bpm.unpin(pages[0]);
+        assert_eq!(bpm.pinned_page_count(), 1);
+
+        bpm.flush_page(pages[0]).unwrap();
+        assert_eq!(bpm.dirty_page_count(), 2);
+        bpm.flush_all().unwrap();
+        assert_eq!(bpm.dirty_page_count(), 0);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_flush_on_drop() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_flush_on_drop");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let (first, second) = {
+            let mut bpm = BufferManager::new_with_path(2 * PAGE_SIZE, &test_file).unwrap();
+            let pages = (bpm.allocate_page().unwrap(), bpm.allocate_page().unwrap());
+            for (page_id, byte) in [(pages.0, 1), (pages.1, 2)] {
+                bpm.get(page_id)
+                    .unwrap()
+                    .with_page_mut(|data| data[PAGE_CHECKSUM_SIZE..].fill(byte));
+            }
+            bpm.flush_all().unwrap();
+            bpm.get(pages.0)
+                .unwrap()
+                .with_page_mut(|data| data[PAGE_CHECKSUM_SIZE..].fill(3));
+            pages
+        };
+        {
+            let mut bpm = BufferManager::new_with_path(2 * PAGE_SIZE, &test_file).unwrap();
+            bpm.set_flush_on_drop(true);
+            bpm.get(second)
+                .unwrap()
+                .with_page_mut(|data| data[PAGE_CHECKSUM_SIZE..].fill(4));
+        }
+
+        // The change to the first page was dropped without flushing it, so it was lost.
+        let mut bpm = BufferManager::new_with_path(2 * PAGE_SIZE, &test_file).unwrap();
+        assert!(
+            bpm.get(first).unwrap().page()[PAGE_CHECKSUM_SIZE..]
+                .iter()
+                .all(|byte| *byte == 1)
+        );
+        assert!(
+            bpm.get(second).unwrap().page()[PAGE_CHECKSUM_SIZE..]
+                .iter()
+                .all(|byte| *byte == 4)
+        );
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_dirty_pages() {
+        let mut bpm = memory_pool(4);
+        let pages: Vec<PageId> = (0..4).map(|_| bpm.allocate_page().unwrap()).collect();
+        assert_eq!(bpm.dirty_pages(), vec![]);
+
+        // Dirtied out of order, and in frames out of order too.
+        bpm.get(pages[1]).unwrap();
+        bpm.latch(pages[3]).unwrap().write().fill(4);
+        bpm.get(pages[2])
+            .unwrap()
+            .with_page_mut(|data| data.fill(2));
+        bpm.get(pages[0])
+            .unwrap()
+            .with_page_mut(|data| data.fill(1));
+        assert_eq!(bpm.dirty_pages(), vec![pages[0], pages[2], pages[3]]);
+
+        for page_id in bpm.dirty_pages() {
+            bpm.flush_page(page_id).unwrap();
+        }
+        assert_eq!(bpm.dirty_pages(), vec![]);
+        assert_eq!(bpm.dirty_page_count(), 0);
+    }
+
+    #[test]
+    fn test_background_writer_flushes_above_high_water_mark() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_background_writer");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let mut bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        bpm.set_background_writer(BackgroundWriterConfig {
+            high_water_mark: 0.5,
+            low_water_mark: 0.25,
+        });
+
+        let dirty_ratio = |bpm: &BufferManager| {
+            bpm.pool_metadata.iter().filter(|m| m.is_dirty).count() as f32 / bpm.pool.len() as f32
+        };
+
+        // Dirty two of the four frames. This is at the high water mark, so
+        // nothing should be flushed.
+        for id in 1..5 {
+            assert_eq!(bpm.allocate_page().unwrap(), PageId::new(id));
+            let _ = bpm.get(PageId::new(id)).unwrap();
+        }
+        for frame_index in 0..2 {
+            bpm.pool[frame_index].reset([frame_index as u8 + 1; PAGE_SIZE]);
+            bpm.pool_metadata[frame_index].is_dirty = true;
+        }
+        assert_eq!(bpm.tick().unwrap(), 0);
+        assert_eq!(dirty_ratio(&bpm), 0.5);
+
+        // Crossing the high water mark flushes down to the low water mark.
+        for frame_index in 2..4 {
+            bpm.pool[frame_index].reset([frame_index as u8 + 1; PAGE_SIZE]);
+            bpm.pool_metadata[frame_index].is_dirty = true;
+        }
+        assert_eq!(bpm.tick().unwrap(), 3);
+        assert!(dirty_ratio(&bpm) <= 0.25);
+
+        // Flushed frames are on disk.
+        for frame_index in 0..4 {
+            if bpm.pool_metadata[frame_index].is_dirty {
+                continue;
+            }
+            let page_id = bpm.pool_metadata[frame_index].page_id.unwrap();
+            let mut data = [0u8; PAGE_SIZE];
+            bpm.disk_manager.read_page(&page_id, &mut data).unwrap();
+            assert_eq!(
+                data[PAGE_CHECKSUM_SIZE..],
+                [frame_index as u8 + 1; PAGE_SIZE - PAGE_CHECKSUM_SIZE]
+            );
+        }
 
         let _ = fs::remove_dir_all(&temp_dir);
     }
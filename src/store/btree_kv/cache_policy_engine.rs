@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, HashMap};
 use std::hash::Hash;
 
 use linked_hash_set::LinkedHashSet;
@@ -6,31 +7,51 @@ pub trait ICachePolicyEngine<T: Eq + Hash + Clone> {
     fn evict(&mut self) -> T;
     fn get_size(&self) -> usize;
     fn touch(&mut self, item: &T);
+    ///
+    /// Stops tracking an item, so it can't be evicted. A later touch tracks it again
+    /// as a new item. Removing an item that isn't tracked is a no-op.
+    ///
+    fn remove(&mut self, item: &T);
 }
 
+///
+/// Callback invoked with every item an engine evicts.
+///
+pub type EvictionCallback<T> = Box<dyn Fn(&T)>;
+
 struct LRUCachePolicyEngine<T: Eq + Hash + Clone> {
     cache: LinkedHashSet<T>,
     max_capacity: usize,
+    on_evict: Option<EvictionCallback<T>>,
 }
 
 impl<T: Eq + Hash + Clone> LRUCachePolicyEngine<T> {
-    pub fn new(capacity: usize) -> Self {
+    pub fn new(capacity: usize, on_evict: Option<EvictionCallback<T>>) -> Self {
         LRUCachePolicyEngine {
             cache: LinkedHashSet::with_capacity(capacity),
             max_capacity: capacity,
+            on_evict,
         }
     }
 }
 
 impl<T: Eq + Hash + Clone> ICachePolicyEngine<T> for LRUCachePolicyEngine<T> {
     fn evict(&mut self) -> T {
-        self.cache.pop_front().unwrap()
+        let item = self.cache.pop_front().unwrap();
+        if let Some(on_evict) = &self.on_evict {
+            on_evict(&item);
+        }
+        item
     }
 
     fn get_size(&self) -> usize {
         self.cache.len()
     }
 
+    fn remove(&mut self, item: &T) {
+        self.cache.remove(item);
+    }
+
     fn touch(&mut self, item: &T) {
         // Add item to the back of the queue. If the item is already present,
         // insert() ensures it is removed from its current position and added
@@ -40,9 +61,339 @@ impl<T: Eq + Hash + Clone> ICachePolicyEngine<T> for LRUCachePolicyEngine<T> {
     }
 }
 
+///
+/// Least Frequently Used. Items with the same frequency are evicted in LRU order.
+///
+struct LFUCachePolicyEngine<T: Eq + Hash + Clone> {
+    // Number of times each item has been touched.
+    frequencies: HashMap<T, usize>,
+    // Items grouped by frequency. Each group is ordered from least to most recently used.
+    frequency_buckets: BTreeMap<usize, LinkedHashSet<T>>,
+    max_capacity: usize,
+    on_evict: Option<EvictionCallback<T>>,
+}
+
+impl<T: Eq + Hash + Clone> LFUCachePolicyEngine<T> {
+    pub fn new(capacity: usize, on_evict: Option<EvictionCallback<T>>) -> Self {
+        LFUCachePolicyEngine {
+            frequencies: HashMap::with_capacity(capacity),
+            frequency_buckets: BTreeMap::new(),
+            max_capacity: capacity,
+            on_evict,
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> ICachePolicyEngine<T> for LFUCachePolicyEngine<T> {
+    fn evict(&mut self) -> T {
+        let mut bucket = self.frequency_buckets.first_entry().unwrap();
+        let item = bucket.get_mut().pop_front().unwrap();
+        if bucket.get().is_empty() {
+            bucket.remove();
+        }
+        self.frequencies.remove(&item);
+        if let Some(on_evict) = &self.on_evict {
+            on_evict(&item);
+        }
+        item
+    }
+
+    fn get_size(&self) -> usize {
+        self.frequencies.len()
+    }
+
+    fn remove(&mut self, item: &T) {
+        if let Some(frequency) = self.frequencies.remove(item) {
+            let bucket = self.frequency_buckets.get_mut(&frequency).unwrap();
+            bucket.remove(item);
+            if bucket.is_empty() {
+                self.frequency_buckets.remove(&frequency);
+            }
+        }
+    }
+
+    fn touch(&mut self, item: &T) {
+        let frequency = self.frequencies.entry(item.clone()).or_insert(0);
+
+        // Move the item from its current bucket to the next one.
+        if *frequency > 0 {
+            let bucket = self.frequency_buckets.get_mut(frequency).unwrap();
+            bucket.remove(item);
+            if bucket.is_empty() {
+                self.frequency_buckets.remove(frequency);
+            }
+        }
+        *frequency += 1;
+        self.frequency_buckets
+            .entry(*frequency)
+            .or_default()
+            .insert(item.clone());
+        assert!(self.frequencies.len() <= self.max_capacity);
+    }
+}
+
+///
+/// Segmented LRU. Items enter a probationary segment and are promoted to a
+/// protected segment on a second access. Eviction drains the probationary
+/// segment first, so pages touched once (e.g. by a scan) can't push out the
+/// working set.
+///
+struct SLRUCachePolicyEngine<T: Eq + Hash + Clone> {
+    probationary: LinkedHashSet<T>,
+    protected: LinkedHashSet<T>,
+    protected_capacity: usize,
+    max_capacity: usize,
+    on_evict: Option<EvictionCallback<T>>,
+}
+
+impl<T: Eq + Hash + Clone> SLRUCachePolicyEngine<T> {
+    ///
+    /// Creates an instance of SLRUCachePolicyEngine.
+    /// # Arguments:
+    /// * `capacity`: Maximum number of items tracked across both segments.
+    /// * `protected_ratio`: Fraction of the capacity reserved for the protected segment.
+    /// * `on_evict`: Callback invoked with each evicted item, if any.
+    ///
+    pub fn new(
+        capacity: usize,
+        protected_ratio: f32,
+        on_evict: Option<EvictionCallback<T>>,
+    ) -> Self {
+        assert!((0.0..=1.0).contains(&protected_ratio));
+        let protected_capacity = (capacity as f32 * protected_ratio) as usize;
+        SLRUCachePolicyEngine {
+            probationary: LinkedHashSet::with_capacity(capacity),
+            protected: LinkedHashSet::with_capacity(protected_capacity),
+            protected_capacity,
+            max_capacity: capacity,
+            on_evict,
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> ICachePolicyEngine<T> for SLRUCachePolicyEngine<T> {
+    fn evict(&mut self) -> T {
+        let item = match self.probationary.pop_front() {
+            Some(item) => item,
+            None => self.protected.pop_front().unwrap(),
+        };
+        if let Some(on_evict) = &self.on_evict {
+            on_evict(&item);
+        }
+        item
+    }
+
+    fn get_size(&self) -> usize {
+        self.probationary.len() + self.protected.len()
+    }
+
+    fn remove(&mut self, item: &T) {
+        if !self.probationary.remove(item) {
+            self.protected.remove(item);
+        }
+    }
+
+    fn touch(&mut self, item: &T) {
+        if self.protected.contains(item) {
+            // Hit in the protected segment. Move it to the back of the queue.
+            self.protected.insert(item.clone());
+        } else if self.probationary.remove(item) {
+            // Second hit. Promote to the protected segment, demoting the least
+            // recently used protected item back to probation if it's full.
+            self.protected.insert(item.clone());
+            if self.protected.len() > self.protected_capacity {
+                let demoted = self.protected.pop_front().unwrap();
+                self.probationary.insert(demoted);
+            }
+        } else {
+            self.probationary.insert(item.clone());
+        }
+        assert!(self.get_size() <= self.max_capacity);
+    }
+}
+
+///
+/// Clock (second chance). Items sit in a circular buffer with a reference bit
+/// that is set on every touch. The clock hand sweeps the buffer on eviction,
+/// clearing reference bits, and evicts the first item whose bit is already clear.
+///
+struct ClockCachePolicyEngine<T: Eq + Hash + Clone> {
+    // Circular buffer of items and their reference bits.
+    slots: Vec<Option<(T, bool)>>,
+    // A map of item against its slot index.
+    lookup: HashMap<T, usize>,
+    // Slots that are not holding an item.
+    vacant_slots: Vec<usize>,
+    // Position of the clock hand.
+    hand: usize,
+    on_evict: Option<EvictionCallback<T>>,
+}
+
+impl<T: Eq + Hash + Clone> ClockCachePolicyEngine<T> {
+    pub fn new(capacity: usize, on_evict: Option<EvictionCallback<T>>) -> Self {
+        ClockCachePolicyEngine {
+            slots: vec![None; capacity],
+            lookup: HashMap::with_capacity(capacity),
+            vacant_slots: (0..capacity).rev().collect(),
+            hand: 0,
+            on_evict,
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> ICachePolicyEngine<T> for ClockCachePolicyEngine<T> {
+    fn evict(&mut self) -> T {
+        assert!(!self.lookup.is_empty());
+        loop {
+            let index = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+            match &mut self.slots[index] {
+                None => {}
+                Some((_, referenced)) if *referenced => *referenced = false,
+                Some(_) => {
+                    let (item, _) = self.slots[index].take().unwrap();
+                    self.lookup.remove(&item);
+                    self.vacant_slots.push(index);
+                    if let Some(on_evict) = &self.on_evict {
+                        on_evict(&item);
+                    }
+                    return item;
+                }
+            }
+        }
+    }
+
+    fn get_size(&self) -> usize {
+        self.lookup.len()
+    }
+
+    fn remove(&mut self, item: &T) {
+        if let Some(index) = self.lookup.remove(item) {
+            self.slots[index] = None;
+            self.vacant_slots.push(index);
+        }
+    }
+
+    fn touch(&mut self, item: &T) {
+        match self.lookup.get(item) {
+            Some(index) => {
+                self.slots[*index].as_mut().unwrap().1 = true;
+            }
+            None => {
+                let index = self.vacant_slots.pop().unwrap();
+                self.slots[index] = Some((item.clone(), true));
+                self.lookup.insert(item.clone(), index);
+            }
+        }
+    }
+}
+
+///
+/// 2Q. Items touched for the first time enter the `a1in` FIFO queue, and are promoted to
+/// the `am` LRU queue when they are touched again. Items evicted from `a1in` are remembered
+/// in the `a1out` ghost queue, so that they go straight to `am` if they come back soon.
+/// Eviction drains `a1in` while it's above its share of the capacity, so items touched
+/// once (e.g. by a scan) can't push out the working set.
+///
+struct TwoQCachePolicyEngine<T: Eq + Hash + Clone> {
+    a1in: LinkedHashSet<T>,
+    am: LinkedHashSet<T>,
+    // Items recently evicted from a1in. They aren't tracked, so they can't be evicted.
+    a1out: LinkedHashSet<T>,
+    // a1in is evicted from first once it holds more than this many items.
+    a1in_capacity: usize,
+    a1out_capacity: usize,
+    max_capacity: usize,
+    on_evict: Option<EvictionCallback<T>>,
+}
+
+impl<T: Eq + Hash + Clone> TwoQCachePolicyEngine<T> {
+    ///
+    /// Creates an instance of TwoQCachePolicyEngine, sizing the queues as recommended by
+    /// the 2Q paper: a1in gets a quarter of the capacity, and a1out remembers half as many
+    /// items as the capacity.
+    /// # Arguments:
+    /// * `capacity`: Maximum number of items tracked across a1in and am.
+    /// * `on_evict`: Callback invoked with each evicted item, if any.
+    ///
+    pub fn new(capacity: usize, on_evict: Option<EvictionCallback<T>>) -> Self {
+        let a1out_capacity = capacity / 2;
+        TwoQCachePolicyEngine {
+            a1in: LinkedHashSet::with_capacity(capacity),
+            am: LinkedHashSet::with_capacity(capacity),
+            a1out: LinkedHashSet::with_capacity(a1out_capacity),
+            a1in_capacity: capacity / 4,
+            a1out_capacity,
+            max_capacity: capacity,
+            on_evict,
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> ICachePolicyEngine<T> for TwoQCachePolicyEngine<T> {
+    fn evict(&mut self) -> T {
+        let item = if self.a1in.len() > self.a1in_capacity || self.am.is_empty() {
+            let item = self.a1in.pop_front().unwrap();
+            if self.a1out_capacity > 0 {
+                if self.a1out.len() == self.a1out_capacity {
+                    self.a1out.pop_front();
+                }
+                self.a1out.insert(item.clone());
+            }
+            item
+        } else {
+            self.am.pop_front().unwrap()
+        };
+        if let Some(on_evict) = &self.on_evict {
+            on_evict(&item);
+        }
+        item
+    }
+
+    fn get_size(&self) -> usize {
+        self.a1in.len() + self.am.len()
+    }
+
+    fn remove(&mut self, item: &T) {
+        if !self.a1in.remove(item) {
+            self.am.remove(item);
+        }
+    }
+
+    fn touch(&mut self, item: &T) {
+        if self.am.contains(item) {
+            // Hit in am. Move it to the back of the queue.
+            self.am.insert(item.clone());
+        } else if self.a1in.remove(item) || self.a1out.remove(item) {
+            // Second access, while tracked or soon after being evicted.
+            self.am.insert(item.clone());
+        } else {
+            self.a1in.insert(item.clone());
+        }
+        assert!(self.get_size() <= self.max_capacity);
+    }
+}
+
 pub enum EvictionPolicy {
     LRU,
     LFU,
+    ///
+    /// Segmented LRU. `protected_ratio` is the fraction of the capacity
+    /// reserved for items that have been accessed more than once.
+    ///
+    SLRU {
+        protected_ratio: f32,
+    },
+    ///
+    /// Clock (second chance). An approximation of LRU that only needs a
+    /// reference bit per item.
+    ///
+    Clock,
+    ///
+    /// 2Q. Scan resistant: items accessed only once are evicted before items that have
+    /// been accessed repeatedly.
+    ///
+    TwoQ,
 }
 
 pub struct CachePolicyEngineFactory {}
@@ -51,10 +402,41 @@ impl CachePolicyEngineFactory {
     pub fn get_engine<T: 'static + Eq + Hash + Clone>(
         eviction_policy: EvictionPolicy,
         capacity: usize,
+    ) -> Box<dyn ICachePolicyEngine<T>> {
+        Self::new_engine(eviction_policy, capacity, None)
+    }
+
+    ///
+    /// Creates an engine that calls `on_evict` with every item it evicts, before `evict`
+    /// returns it.
+    /// # Arguments:
+    /// * `eviction_policy`: Policy of the engine.
+    /// * `capacity`: Maximum number of items tracked.
+    /// * `on_evict`: Callback invoked with each evicted item.
+    ///
+    pub fn get_engine_with_on_evict<T: 'static + Eq + Hash + Clone>(
+        eviction_policy: EvictionPolicy,
+        capacity: usize,
+        on_evict: EvictionCallback<T>,
+    ) -> Box<dyn ICachePolicyEngine<T>> {
+        Self::new_engine(eviction_policy, capacity, Some(on_evict))
+    }
+
+    fn new_engine<T: 'static + Eq + Hash + Clone>(
+        eviction_policy: EvictionPolicy,
+        capacity: usize,
+        on_evict: Option<EvictionCallback<T>>,
     ) -> Box<dyn ICachePolicyEngine<T>> {
         match eviction_policy {
-            EvictionPolicy::LRU => Box::new(LRUCachePolicyEngine::new(capacity)),
-            EvictionPolicy::LFU => panic!("Not yet implemented. Use LRU"),
+            EvictionPolicy::LRU => Box::new(LRUCachePolicyEngine::new(capacity, on_evict)),
+            EvictionPolicy::LFU => Box::new(LFUCachePolicyEngine::new(capacity, on_evict)),
+            EvictionPolicy::Clock => Box::new(ClockCachePolicyEngine::new(capacity, on_evict)),
+            EvictionPolicy::TwoQ => Box::new(TwoQCachePolicyEngine::new(capacity, on_evict)),
+            EvictionPolicy::SLRU { protected_ratio } => Box::new(SLRUCachePolicyEngine::new(
+                capacity,
+                protected_ratio,
+                on_evict,
+            )),
         }
     }
 }
@@ -62,6 +444,8 @@ impl CachePolicyEngineFactory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
     #[test]
     fn lru_cache_manager_works() {
         let max_capacity = 2;
@@ -82,4 +466,196 @@ mod tests {
         cache_manager.touch(&second_item);
         assert_eq!(cache_manager.evict(), third_item);
     }
+
+    #[test]
+    fn lfu_cache_manager_works() {
+        let max_capacity = 3;
+        let mut cache_manager: Box<dyn ICachePolicyEngine<usize>> =
+            CachePolicyEngineFactory::get_engine(EvictionPolicy::LFU, max_capacity);
+
+        let first_item = 10;
+        let second_item = 20;
+        let third_item = 30;
+        let fourth_item = 40;
+        cache_manager.touch(&first_item);
+        cache_manager.touch(&first_item);
+        cache_manager.touch(&first_item);
+        cache_manager.touch(&second_item);
+        cache_manager.touch(&second_item);
+        cache_manager.touch(&third_item);
+
+        assert_eq!(cache_manager.get_size(), max_capacity);
+        // The least frequently used item goes first.
+        assert_eq!(cache_manager.evict(), third_item);
+
+        // Items with equal frequency are evicted least recently used first.
+        cache_manager.touch(&fourth_item);
+        cache_manager.touch(&fourth_item);
+        assert_eq!(cache_manager.evict(), second_item);
+        assert_eq!(cache_manager.evict(), fourth_item);
+        assert_eq!(cache_manager.evict(), first_item);
+        assert_eq!(cache_manager.get_size(), 0);
+    }
+
+    #[test]
+    fn clock_cache_manager_works() {
+        let max_capacity = 3;
+        let mut cache_manager: Box<dyn ICachePolicyEngine<usize>> =
+            CachePolicyEngineFactory::get_engine(EvictionPolicy::Clock, max_capacity);
+
+        let first_item = 10;
+        let second_item = 20;
+        let third_item = 30;
+        let fourth_item = 40;
+        cache_manager.touch(&first_item);
+        cache_manager.touch(&second_item);
+        cache_manager.touch(&third_item);
+        assert_eq!(cache_manager.get_size(), max_capacity);
+
+        // Every item is referenced, so the hand clears all the bits and comes
+        // back around to the first item.
+        assert_eq!(cache_manager.evict(), first_item);
+
+        // The second item is touched again and gets a second chance.
+        cache_manager.touch(&fourth_item);
+        cache_manager.touch(&second_item);
+        assert_eq!(cache_manager.evict(), third_item);
+        assert_eq!(cache_manager.get_size(), 2);
+    }
+
+    #[test]
+    fn two_q_cache_manager_works() {
+        let max_capacity = 4;
+        let mut cache_manager: Box<dyn ICachePolicyEngine<usize>> =
+            CachePolicyEngineFactory::get_engine(EvictionPolicy::TwoQ, max_capacity);
+
+        // The hot item is reused, promoting it to am.
+        let hot_item = 10;
+        cache_manager.touch(&hot_item);
+        cache_manager.touch(&hot_item);
+
+        // A scan touches each item once. The scanned items are evicted first, even though
+        // the hot item was used least recently.
+        for scanned_item in [20, 30, 40] {
+            cache_manager.touch(&scanned_item);
+        }
+        assert_eq!(cache_manager.get_size(), max_capacity);
+        assert_eq!(cache_manager.evict(), 20);
+        assert_eq!(cache_manager.evict(), 30);
+
+        // An item that comes back soon after being evicted goes straight to am, behind the
+        // hot item. a1in is down to its share of the capacity, so am is evicted from now.
+        cache_manager.touch(&20);
+        assert_eq!(cache_manager.evict(), hot_item);
+        assert_eq!(cache_manager.evict(), 20);
+        assert_eq!(cache_manager.evict(), 40);
+        assert_eq!(cache_manager.get_size(), 0);
+    }
+
+    #[test]
+    fn removed_items_are_not_evicted() {
+        let eviction_policies = [
+            EvictionPolicy::LRU,
+            EvictionPolicy::LFU,
+            EvictionPolicy::SLRU {
+                protected_ratio: 0.5,
+            },
+            EvictionPolicy::Clock,
+            EvictionPolicy::TwoQ,
+        ];
+        for eviction_policy in eviction_policies {
+            let mut cache_manager: Box<dyn ICachePolicyEngine<usize>> =
+                CachePolicyEngineFactory::get_engine(eviction_policy, 3);
+            cache_manager.touch(&10);
+            cache_manager.touch(&20);
+            cache_manager.touch(&30);
+
+            cache_manager.remove(&10);
+            cache_manager.remove(&40); // Not tracked.
+            assert_eq!(cache_manager.get_size(), 2);
+
+            let mut evicted = vec![cache_manager.evict(), cache_manager.evict()];
+            evicted.sort();
+            assert_eq!(evicted, vec![20, 30]);
+            assert_eq!(cache_manager.get_size(), 0);
+        }
+    }
+
+    #[test]
+    fn slru_cache_manager_keeps_hot_items_during_scan() {
+        let max_capacity = 4;
+        let mut cache_manager: Box<dyn ICachePolicyEngine<usize>> =
+            CachePolicyEngineFactory::get_engine(
+                EvictionPolicy::SLRU {
+                    protected_ratio: 0.5,
+                },
+                max_capacity,
+            );
+
+        // Mimic the buffer pool: an item is only touched once it is resident,
+        // evicting first if the cache is full.
+        let mut resident: Vec<usize> = Vec::new();
+        let mut evicted: Vec<usize> = Vec::new();
+        let mut access = |item: usize, cache_manager: &mut Box<dyn ICachePolicyEngine<usize>>| {
+            if !resident.contains(&item) {
+                if cache_manager.get_size() == max_capacity {
+                    let victim = cache_manager.evict();
+                    resident.retain(|r| *r != victim);
+                    evicted.push(victim);
+                }
+                resident.push(item);
+            }
+            cache_manager.touch(&item);
+        };
+
+        let hot_items = [1, 2];
+        for item in hot_items {
+            access(item, &mut cache_manager);
+            access(item, &mut cache_manager);
+        }
+
+        // One-shot scan interleaved with hot item accesses.
+        for item in 100..120 {
+            access(item, &mut cache_manager);
+            access(hot_items[item % 2], &mut cache_manager);
+        }
+
+        assert!(!evicted.is_empty());
+        assert!(hot_items.iter().all(|item| !evicted.contains(item)));
+    }
+
+    #[test]
+    fn on_evict_observes_evicted_items() {
+        let eviction_policies = [
+            EvictionPolicy::LRU,
+            EvictionPolicy::LFU,
+            EvictionPolicy::SLRU {
+                protected_ratio: 0.5,
+            },
+            EvictionPolicy::Clock,
+            EvictionPolicy::TwoQ,
+        ];
+        for eviction_policy in eviction_policies {
+            let evicted = Rc::new(RefCell::new(Vec::new()));
+            let observed = Rc::clone(&evicted);
+            let mut cache_manager = CachePolicyEngineFactory::get_engine_with_on_evict(
+                eviction_policy,
+                2,
+                Box::new(move |item: &usize| observed.borrow_mut().push(*item)),
+            );
+
+            cache_manager.touch(&10);
+            cache_manager.touch(&20);
+            cache_manager.touch(&20);
+            assert!(evicted.borrow().is_empty());
+
+            // 10 is both the least recently and the least frequently used, and the only
+            // item accessed once.
+            assert_eq!(cache_manager.evict(), 10);
+            assert_eq!(*evicted.borrow(), vec![10]);
+            // Removing an item isn't an eviction.
+            cache_manager.remove(&20);
+            assert_eq!(*evicted.borrow(), vec![10]);
+        }
+    }
 }
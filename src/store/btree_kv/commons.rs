@@ -1,18 +1,27 @@
+use std::mem::size_of;
+
 pub const PAGE_SIZE: usize = 8000; // 8kb.
 
+///
+/// Every page starts with a CRC32 checksum of the rest of the page. The DiskManager
+/// computes it on write and verifies it on read; page layouts must leave these bytes alone.
+///
+pub const PAGE_CHECKSUM_OFFSET: usize = 0;
+pub const PAGE_CHECKSUM_SIZE: usize = size_of::<u32>(); // 4 bytes
+
 ///
 /// Defines the PageId of a Page of data in disk.
 ///
 /// TODO: Data is assumed to be stored in a single file. Handle multiple
 /// files.
 ///
-#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct PageId(u64);
 
 impl PageId {
     pub const INVALID: PageId = PageId(u64::MAX);
 
-    pub fn new(id: u64) -> Self {
+    pub const fn new(id: u64) -> Self {
         Self(id)
     }
 
@@ -0,0 +1,68 @@
+use crate::store::btree_kv::helpers::byte_ordering::cmp_lex_bytes;
+use std::cmp::Ordering;
+
+///
+/// Defines the order of the keys in a BTree.
+///
+/// The name of the comparator is stored in the data file when the tree is created, and
+/// opening the tree with a comparator of a different name fails, since its pages would be
+/// searched in the wrong order.
+///
+/// # Examples
+/// // Compares keys with their ASCII case folded.
+/// struct CaseInsensitive;
+/// impl Comparator for CaseInsensitive {
+///     fn name(&self) -> &str {
+///         "case-insensitive"
+///     }
+///     fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+///         a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase())
+///     }
+/// }
+/// let btree = BTree::with_comparator(buffer_manager, &CaseInsensitive)?;
+/// // Once the data file is reopened.
+/// let btree = BTree::open_with_comparator(buffer_manager, root, &CaseInsensitive)?;
+///
+pub trait Comparator: Send + Sync {
+    ///
+    /// Identifies the comparator in the data file. At most `MAX_COMPARATOR_NAME_SIZE`
+    /// bytes long, and must change whenever the order does.
+    ///
+    fn name(&self) -> &str;
+
+    ///
+    /// Compares two keys. Keys comparing as `Equal` are the same key, even if their bytes
+    /// differ. The empty key must compare less than every other key, since it's the first
+    /// separator of internal pages.
+    ///
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering;
+
+    ///
+    /// Returns `true` if keys are ordered byte by byte, and only equal when their bytes are.
+    /// Pages can then store the prefix shared by their keys once, and look keys up in a
+    /// filter of their bytes before searching.
+    ///
+    fn is_lexicographic(&self) -> bool {
+        false
+    }
+}
+
+///
+/// Orders keys lexicographically, byte by byte, see `cmp_lex_bytes`. The default order of
+/// a BTree.
+///
+pub struct Lexicographic;
+
+impl Comparator for Lexicographic {
+    fn name(&self) -> &str {
+        "lexicographic"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        cmp_lex_bytes(a, b)
+    }
+
+    fn is_lexicographic(&self) -> bool {
+        true
+    }
+}
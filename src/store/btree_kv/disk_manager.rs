@@ -1,18 +1,145 @@
-use crate::store::btree_kv::commons::{PAGE_SIZE, PageId};
+use crate::store::btree_kv::commons::{
+    PAGE_CHECKSUM_OFFSET, PAGE_CHECKSUM_SIZE, PAGE_SIZE, PageId,
+};
+use crate::store::btree_kv::error::RustyKVError;
+use crate::store::btree_kv::helpers::checksum::crc32;
+use crate::store::btree_kv::page::{BTreePage, OTHER_PAGE_DATA_OFFSET};
+use crate::store::btree_kv::storage_backend::StorageBackend;
 use std::{
-    fs::{File, OpenOptions},
-    io::{Read, Seek, SeekFrom, Write},
-    path::Path,
+    collections::HashSet,
+    fs::{File, OpenOptions, TryLockError},
+    io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write},
+    mem::size_of,
+    path::{Path, PathBuf},
 };
+use tempfile::NamedTempFile;
+
+///
+/// Page reserved for the DiskManager's own metadata.
+///
+pub const METADATA_PAGE_ID: PageId = PageId::new(0);
+
+///
+/// Longest comparator name that can be stored in the metadata page, in bytes.
+///
+pub const MAX_COMPARATOR_NAME_SIZE: usize = 63;
+
+///
+/// Identifies rusty-kv data files. Stored at the start of the metadata page, after its
+/// checksum.
+///
+pub const FILE_MAGIC: [u8; 4] = *b"RKVF";
+
+///
+/// Version of the data file format. Files of any other version are rejected when opened,
+/// and it must be bumped whenever the layout of the file changes.
+///
+pub const FORMAT_VERSION: u16 = 2;
+
+// Metadata Page Layout
+// The magic number and format version, checked before anything else in the file is read.
+const MAGIC_OFFSET: usize = PAGE_CHECKSUM_OFFSET + PAGE_CHECKSUM_SIZE;
+const MAGIC_SIZE: usize = FILE_MAGIC.len(); // 4 bytes
+const FORMAT_VERSION_OFFSET: usize = MAGIC_OFFSET + MAGIC_SIZE;
+const FORMAT_VERSION_SIZE: usize = size_of::<u16>(); // 2 bytes
+// The name of the comparator the keys are ordered by, stored as its size followed by its
+// bytes. Empty until set by the first BTree created in the file.
+const COMPARATOR_NAME_SIZE_OFFSET: usize = FORMAT_VERSION_OFFSET + FORMAT_VERSION_SIZE;
+const COMPARATOR_NAME_SIZE_SIZE: usize = size_of::<u8>(); // 1 byte
+const COMPARATOR_NAME_OFFSET: usize = COMPARATOR_NAME_SIZE_OFFSET + COMPARATOR_NAME_SIZE_SIZE;
+// The head of the free list. Page IDs that don't fit are stored in a chain of free list
+// pages, starting at the one after the count.
+const FREE_PAGE_COUNT_OFFSET: usize = COMPARATOR_NAME_OFFSET + MAX_COMPARATOR_NAME_SIZE;
+const FREE_PAGE_COUNT_SIZE: usize = size_of::<u32>(); // 4 bytes
+const FREE_LIST_NEXT_OFFSET: usize = FREE_PAGE_COUNT_OFFSET + FREE_PAGE_COUNT_SIZE;
+const FREE_LIST_NEXT_SIZE: usize = size_of::<u64>(); // 8 bytes
+const FREE_PAGES_OFFSET: usize = FREE_LIST_NEXT_OFFSET + FREE_LIST_NEXT_SIZE;
+const FREE_PAGE_ID_SIZE: usize = size_of::<u64>(); // 8 bytes
+const MAX_FREE_PAGES: usize = (PAGE_SIZE - FREE_PAGES_OFFSET) / FREE_PAGE_ID_SIZE;
+
+// Free List Page Layout: the Page ID of the next free list page, the number of Page IDs in
+// the page, and the Page IDs. Free list pages are free pages themselves, and are marked as
+// not being BTree pages. The list ends at a next Page ID of METADATA_PAGE_ID.
+const FREE_LIST_PAGE_NEXT_OFFSET: usize = OTHER_PAGE_DATA_OFFSET;
+const FREE_LIST_PAGE_COUNT_OFFSET: usize = FREE_LIST_PAGE_NEXT_OFFSET + FREE_LIST_NEXT_SIZE;
+const FREE_LIST_PAGE_IDS_OFFSET: usize = FREE_LIST_PAGE_COUNT_OFFSET + FREE_PAGE_COUNT_SIZE;
+const MAX_FREE_LIST_PAGE_IDS: usize = (PAGE_SIZE - FREE_LIST_PAGE_IDS_OFFSET) / FREE_PAGE_ID_SIZE;
+
+// Scratch File Layout: the Page ID of the page being written, followed by the page.
+const SCRATCH_PAGE_ID_SIZE: usize = size_of::<u64>(); // 8 bytes
+const SCRATCH_FILE_SIZE: usize = SCRATCH_PAGE_ID_SIZE + PAGE_SIZE;
+
+///
+/// Controls when written pages are synced to the physical disk.
+///
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum SyncMode {
+    ///
+    /// Sync after every `write_page`. Durable, but slow under bulk loads.
+    ///
+    PerWrite,
+    ///
+    /// Only sync when `sync` is called.
+    ///
+    Deferred,
+    ///
+    /// Never sync. `sync` is a no-op. Only suitable for ephemeral data.
+    ///
+    Never,
+}
+
+///
+/// Result of scanning a data file with `DiskManager::verify`.
+///
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct VerifyReport {
+    ///
+    /// Number of pages in the file, including a trailing partial page.
+    ///
+    pub num_pages: usize,
+    ///
+    /// Pages that failed verification, in Page ID order.
+    ///
+    pub corrupt_pages: Vec<PageId>,
+}
+
+impl VerifyReport {
+    ///
+    /// Returns `true` if no page failed verification.
+    ///
+    pub fn is_ok(&self) -> bool {
+        self.corrupt_pages.is_empty()
+    }
+}
 
 ///
 /// Handles disk operations for the data.
 ///
-/// TODO: Implement deallocation and reuse them.
+/// Page 0 is reserved for metadata. It starts with a magic number and format version, so
+/// that files of another format aren't mistaken for data files. It also holds the list of
+/// deallocated pages so they can be reused after the file is reopened, and the name of the
+/// comparator that orders the keys stored in the file. Deallocated pages that don't fit in
+/// the metadata page are listed in deallocated pages themselves, chained from it.
+///
+/// `write_page_atomic` stages pages in a scratch file next to the data file, named after it
+/// with a `.scratch` suffix.
 ///
 pub struct DiskManager {
     file: File,
+    path: PathBuf,
+    scratch_path: PathBuf,
+    // Opened by the first `write_page_atomic`.
+    scratch_file: Option<File>,
     num_pages: usize,
+    sync_mode: SyncMode,
+    // Pages that have been deallocated and can be handed out again.
+    free_pages: Vec<PageId>,
+    // Name of the comparator the keys in the file are ordered by. Empty if unset.
+    comparator_name: String,
+    // Set by `open_readonly`. Every operation that would modify the file fails.
+    read_only: bool,
+    // Verifies page checksums in `read_page`, if set. On by default.
+    verify_on_read: bool,
 }
 
 impl DiskManager {
@@ -21,27 +148,163 @@ impl DiskManager {
     ///
     /// # Arguments
     /// * `path`: Path to the file that can be used for storing data.
+    /// * `sync_mode`: Controls when writes are synced to the physical disk.
     ///
     /// # Returns
     /// * `Ok(Self)` if the disk manager was initialised successfully.
     /// * `Err(std::io::Error)` if an error occurred while reading the file.
     ///
-    pub fn new(path: &Path) -> Result<Self, std::io::Error> {
+    /// # Errors
+    /// A fresh file is stamped with FILE_MAGIC and FORMAT_VERSION. An existing file whose
+    /// metadata page doesn't start with FILE_MAGIC is rejected with an error of kind
+    /// `InvalidData` wrapping `RustyKVError::InvalidMagic`, and one of another format version
+    /// with `RustyKVError::UnsupportedVersion`. The file is left as is.
+    ///
+    /// An exclusive advisory lock is taken on the file so that two processes can't
+    /// write to it at the same time. If the lock is held elsewhere, this returns an
+    /// error of kind `WouldBlock` wrapping `RustyKVError::AlreadyOpen`. The lock is
+    /// released when the DiskManager is dropped. Read-only handles don't take the
+    /// lock, so they can still open the file.
+    ///
+    pub fn new(path: &Path, sync_mode: SyncMode) -> Result<Self, std::io::Error> {
         let file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(path)?;
 
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(Error::new(ErrorKind::WouldBlock, RustyKVError::AlreadyOpen));
+            }
+            Err(TryLockError::Error(error)) => return Err(error),
+        }
+
+        let scratch_path = Self::get_scratch_path(path);
+        Self::recover_scratch_page(&file, &scratch_path)?;
         let metadata = file.metadata()?;
 
         // Arrive at num_pages based on the current size of the file to prevent
         // overwriting it.
         let num_pages = (metadata.len() / PAGE_SIZE as u64) as usize;
 
+        let mut disk_manager = Self {
+            file,
+            path: path.to_path_buf(),
+            scratch_path,
+            scratch_file: None,
+            num_pages,
+            sync_mode,
+            free_pages: Vec::new(),
+            comparator_name: String::new(),
+            read_only: false,
+            verify_on_read: true,
+        };
+        if num_pages == 0 {
+            // Fresh file. Reserve the metadata page.
+            disk_manager.num_pages = 1;
+            disk_manager.write_metadata()?;
+        } else {
+            disk_manager.read_metadata()?;
+        }
+        Ok(disk_manager)
+    }
+
+    ///
+    /// Creates an instance of DiskManager like `new`, and preallocates the file to hold
+    /// `pages` pages, so that bulk loads don't grow it one page at a time.
+    ///
+    /// # Arguments
+    /// * `path`: Path to the file that can be used for storing data.
+    /// * `sync_mode`: Controls when writes are synced to the physical disk.
+    /// * `pages`: Number of pages to preallocate, including the metadata page. A file that
+    ///   is already at least this large is left as is.
+    ///
+    /// # Returns
+    /// * `Ok(Self)` if the disk manager was initialised successfully.
+    /// * `Err(std::io::Error)` if an error occurred while reading or resizing the file.
+    ///
+    /// # Impl Note
+    /// The preallocated pages are put on the free list, so that they're still known to be
+    /// unused once the file is reopened, and `allocate_page` hands them out in Page ID order
+    /// before the file grows again. Preallocated pages read as zeroes. Past the Page IDs
+    /// that fit in the metadata page, every allocation rewrites the free list pages, see
+    /// `deallocate_page`.
+    ///
+    pub fn with_capacity(
+        path: &Path,
+        sync_mode: SyncMode,
+        pages: usize,
+    ) -> Result<Self, std::io::Error> {
+        let mut disk_manager = Self::new(path, sync_mode)?;
+        if disk_manager.num_pages < pages {
+            disk_manager.file.set_len((pages * PAGE_SIZE) as u64)?;
+            // Pushed from the last, so that the first is popped first.
+            disk_manager.free_pages.extend(
+                (disk_manager.num_pages..pages)
+                    .rev()
+                    .map(|id| PageId::new(id as u64)),
+            );
+            disk_manager.num_pages = pages;
+            disk_manager.write_metadata()?;
+        }
+        Ok(disk_manager)
+    }
+
+    ///
+    /// Opens an existing data file for reading only, e.g. to serve reads from a replica.
+    /// Pages can be read, but writing, allocating or deallocating them fails with an error
+    /// of kind `PermissionDenied` wrapping `RustyKVError::ReadOnly`, and `sync` is a no-op.
+    ///
+    /// # Arguments
+    /// * `path`: Path to the data file. It only needs read permissions.
+    ///
+    /// # Returns
+    /// * `Ok(Self)` if the disk manager was initialised successfully.
+    /// * `Err(std::io::Error)` if the file couldn't be opened or its metadata page read. If
+    ///   the file is empty, the error is of kind `NotFound`. Files that aren't data files of
+    ///   this format version are rejected as by `new`.
+    ///
+    /// # Impl Note
+    /// Like other read-only handles, this doesn't take the lock, so the file can be open for
+    /// writing elsewhere at the same time. A `write_page_atomic` interrupted by a crash isn't
+    /// recovered, since that needs a write; the torn page fails its checksum until the file
+    /// is opened with `new`.
+    ///
+    pub fn open_readonly(path: &Path) -> Result<Self, std::io::Error> {
+        let mut disk_manager = Self::open_readonly_file(path)?;
+        disk_manager.read_metadata()?;
+        Ok(disk_manager)
+    }
+
+    ///
+    /// Opens a possibly corrupt data file for reading only, like `open_readonly`, but doesn't
+    /// fail if the metadata page can't be read. The file then has no comparator name and no
+    /// free pages, and every other page can still be read.
+    ///
+    pub(crate) fn open_for_salvage(path: &Path) -> Result<Self, std::io::Error> {
+        let mut disk_manager = Self::open_readonly_file(path)?;
+        // The comparator name and free list are only set once they've been read.
+        let _ = disk_manager.read_metadata();
+        Ok(disk_manager)
+    }
+
+    fn open_readonly_file(path: &Path) -> Result<Self, std::io::Error> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        let num_pages = (file.metadata()?.len() / PAGE_SIZE as u64) as usize;
         Ok(Self {
-            file: file,
-            num_pages: num_pages,
+            file,
+            path: path.to_path_buf(),
+            scratch_path: Self::get_scratch_path(path),
+            scratch_file: None,
+            num_pages,
+            sync_mode: SyncMode::Never,
+            free_pages: Vec::new(),
+            comparator_name: String::new(),
+            read_only: true,
+            verify_on_read: true,
         })
     }
 
@@ -60,17 +323,55 @@ impl DiskManager {
     /// This function returns an error if:
     /// * The provided buffer length does not match the page size.
     /// * The underlying file I/O operation fails.
+    /// * The page checksum doesn't match its contents. The error is of kind `InvalidData`
+    ///   and wraps `RustyKVError::ChecksumMismatch`. Skipped if verification on read has
+    ///   been turned off with `set_verify_on_read`.
+    /// * The page hasn't been allocated, i.e. `id` is at or beyond `num_pages`. The error is
+    ///   of kind `NotFound` and wraps `RustyKVError::ItemNotFound`.
+    ///
+    /// # Impl Note
+    /// An allocated page that has never been written (past the end of the file, or a hole
+    /// in it) reads as all zeroes and is not verified.
     ///
     pub fn read_page(
         &mut self,
         id: &PageId,
         buffer: &mut [u8; PAGE_SIZE],
     ) -> Result<(), std::io::Error> {
+        self.read_page_head(id, buffer)?;
+        if self.verify_on_read && !Self::verify_checksum(buffer) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                RustyKVError::ChecksumMismatch,
+            ));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Fetches the first `buffer.len()` bytes of a page, without the rest of it. The
+    /// checksum covers the whole page, so the bytes aren't verified.
+    ///
+    /// # Arguments
+    /// * `id`: Page ID which needs to be fetched.
+    /// * `buffer`: Buffer that needs to be populated. At most PAGE_SIZE bytes long.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the bytes were read. Bytes past the end of the file read as zeroes.
+    /// * `Err(std::io::Error)` if an error occurred while reading from the disk. If the page
+    ///   hasn't been allocated, the error is of kind `NotFound` and wraps
+    ///   `RustyKVError::ItemNotFound`.
+    ///
+    pub fn read_page_head(&mut self, id: &PageId, buffer: &mut [u8]) -> Result<(), Error> {
+        assert!(buffer.len() <= PAGE_SIZE, "Can't read more than a page");
+        if id.value() >= self.num_pages as u64 {
+            return Err(Error::new(ErrorKind::NotFound, RustyKVError::ItemNotFound));
+        }
         let offset = id.value() * PAGE_SIZE as u64;
         self.file.seek(SeekFrom::Start(offset))?;
 
         let mut bytes_read = 0;
-        while bytes_read < PAGE_SIZE {
+        while bytes_read < buffer.len() {
             match self.file.read(&mut buffer[bytes_read..])? {
                 0 => {
                     buffer[bytes_read..].fill(0);
@@ -82,6 +383,19 @@ impl DiskManager {
         Ok(())
     }
 
+    ///
+    /// Sets whether `read_page` verifies the checksum of every page it reads. Verification
+    /// is on by default. Turning it off saves hashing each page on a buffer pool miss, at
+    /// the cost of handing out corrupt pages as if they were intact; `verify` still checks
+    /// every page.
+    ///
+    /// # Arguments
+    /// * `verify_on_read`: `true` to verify page checksums on read, `false` to skip it.
+    ///
+    pub fn set_verify_on_read(&mut self, verify_on_read: bool) {
+        self.verify_on_read = verify_on_read;
+    }
+
     ///
     /// Writes data to a Page.
     ///
@@ -97,77 +411,1505 @@ impl DiskManager {
     /// This function returns an error if:
     /// * The provided data length does not match the page size.
     /// * The underlying file I/O operation fails.
+    /// * The file was opened with `open_readonly`. The error is of kind `PermissionDenied`
+    ///   and wraps `RustyKVError::ReadOnly`.
+    ///
+    /// # Impl Note
+    /// The first PAGE_CHECKSUM_SIZE bytes of the buffer are ignored; a checksum of the rest
+    /// of the page is written in their place. Writing a page that hasn't been allocated
+    /// extends the file, and every page up to it counts as allocated, as it would once the
+    /// file is reopened.
     ///
     pub fn write_page(
         &mut self,
         id: &PageId,
         buffer: &[u8; PAGE_SIZE],
     ) -> Result<(), std::io::Error> {
+        self.check_writable()?;
+        let page = Self::with_checksum(buffer);
         let offset = id.value() * PAGE_SIZE as u64;
         self.file.seek(SeekFrom::Start(offset))?;
-        self.file.write_all(buffer)?;
+        self.file.write_all(&page)?;
         self.file.flush()?;
+        if self.sync_mode == SyncMode::PerWrite {
+            self.file.sync_all()?;
+        }
+        self.num_pages = self.num_pages.max(id.value() as usize + 1);
         Ok(())
     }
 
     ///
-    /// Allocates a Page of data in the file.
+    /// Writes data to a Page such that a crash mid-write can't leave the page torn, half old
+    /// and half new.
+    ///
+    /// # Arguments
+    /// * `id`: Page ID of the Page.
+    /// * `data`: Data that needs to be written.
     ///
     /// # Returns
-    /// * `PageId`: The PageID of the page allocated.
+    /// * `Ok(())` if the page was successfully written and synced.
+    /// * `Err(std::io::Error)` if an error occurred while writing to the disk.
     ///
-    pub fn allocate_page(&mut self) -> PageId {
-        let page_id = PageId::new(self.num_pages as u64);
-        self.num_pages += 1;
-        page_id
+    /// # Impl Note
+    /// The page is first written and synced to the scratch file, and only then written in
+    /// place. If the in-place write is torn, the next `DiskManager::new` finds the complete
+    /// page in the scratch file and writes it again. If the scratch write is torn, the
+    /// scratch file fails its checksum and is discarded, leaving the old page. Either way the
+    /// page reads as entirely old or entirely new. Both files are synced regardless of the
+    /// sync mode, so this costs two syncs per page.
+    ///
+    /// TODO: Drop this once a WAL can replay torn pages.
+    ///
+    pub fn write_page_atomic(
+        &mut self,
+        id: &PageId,
+        buffer: &[u8; PAGE_SIZE],
+    ) -> Result<(), std::io::Error> {
+        self.check_writable()?;
+        let page = Self::with_checksum(buffer);
+        let scratch_file = match &mut self.scratch_file {
+            Some(scratch_file) => scratch_file,
+            None => self.scratch_file.insert(
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(&self.scratch_path)?,
+            ),
+        };
+        scratch_file.seek(SeekFrom::Start(0))?;
+        scratch_file.write_all(&id.value().to_le_bytes())?;
+        scratch_file.write_all(&page)?;
+        scratch_file.sync_all()?;
+
+        let offset = id.value() * PAGE_SIZE as u64;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&page)?;
+        self.file.sync_all()?;
+        self.num_pages = self.num_pages.max(id.value() as usize + 1);
+
+        // The page is durable in place, so the scratch copy is no longer needed.
+        scratch_file.set_len(0)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use tempfile::NamedTempFile;
+    ///
+    /// Finishes a `write_page_atomic` interrupted by a crash, by writing the page left in the
+    /// scratch file in place again. A torn scratch file is discarded.
+    ///
+    fn recover_scratch_page(file: &File, scratch_path: &Path) -> Result<(), std::io::Error> {
+        let mut scratch_file = match OpenOptions::new().read(true).write(true).open(scratch_path) {
+            Ok(scratch_file) => scratch_file,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        let mut contents = Vec::with_capacity(SCRATCH_FILE_SIZE);
+        scratch_file.read_to_end(&mut contents)?;
 
-    use super::*;
+        if contents.len() == SCRATCH_FILE_SIZE {
+            let page_id = u64::from_le_bytes(contents[..SCRATCH_PAGE_ID_SIZE].try_into().unwrap());
+            let page: &[u8; PAGE_SIZE] = contents[SCRATCH_PAGE_ID_SIZE..].try_into().unwrap();
+            // Unlike `verify_checksum`, don't accept a zeroed page, which a torn write could
+            // leave behind too.
+            if Self::with_checksum(page) == *page {
+                let mut file = file;
+                file.seek(SeekFrom::Start(page_id * PAGE_SIZE as u64))?;
+                file.write_all(page)?;
+                file.sync_all()?;
+            }
+        }
+        scratch_file.set_len(0)
+    }
 
-    #[test]
-    fn test_create_and_get() {
-        let temp_file = NamedTempFile::new().unwrap();
+    ///
+    /// Returns the path of the scratch file used by `write_page_atomic`.
+    ///
+    fn get_scratch_path(path: &Path) -> PathBuf {
+        let mut scratch_path = path.as_os_str().to_owned();
+        scratch_path.push(".scratch");
+        PathBuf::from(scratch_path)
+    }
 
-        let mut disk_manager = DiskManager::new(temp_file.path()).unwrap();
+    ///
+    /// Returns the path to the data file.
+    ///
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
 
-        let data = [10, 20, 30];
-        let mut page = [0u8; PAGE_SIZE];
-        page[..data.len()].copy_from_slice(&data);
+    ///
+    /// Returns the number of pages allocated in the file, including the metadata page and
+    /// pages that have been deallocated but not handed out again.
+    ///
+    pub fn num_pages(&self) -> usize {
+        self.num_pages
+    }
 
-        let id = disk_manager.allocate_page();
+    ///
+    /// Returns the Page IDs of the pages that have been deallocated but not handed out
+    /// again.
+    ///
+    pub fn free_pages(&self) -> &[PageId] {
+        &self.free_pages
+    }
 
-        disk_manager.write_page(&id, &page).unwrap();
-        let mut data_read = [0u8; PAGE_SIZE];
-        disk_manager.read_page(&id, &mut data_read).unwrap();
-        assert_eq!(&page, &data_read);
+    ///
+    /// Syncs all written pages to the physical disk. This is a no-op in `SyncMode::Never`.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the data was synced.
+    /// * `Err(std::io::Error)` if an error occurred while syncing.
+    ///
+    pub fn sync(&mut self) -> Result<(), std::io::Error> {
+        // Read-only disk managers are opened with SyncMode::Never.
+        match self.sync_mode {
+            SyncMode::Never => Ok(()),
+            SyncMode::PerWrite | SyncMode::Deferred => self.file.sync_all(),
+        }
     }
 
-    #[test]
-    fn test_create_existing_key() {
-        let temp_file = NamedTempFile::new().unwrap();
-        let mut disk_manager = DiskManager::new(temp_file.path()).unwrap();
+    ///
+    /// Copies the data file to `path`. Only pages that have been written are copied, so
+    /// callers must write back any buffered pages first.
+    ///
+    /// # Arguments
+    /// * `path`: Path the snapshot is written to. An existing file is replaced.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the snapshot was written.
+    /// * `Err(std::io::Error)` if an error occurred while copying the file.
+    ///
+    /// # Impl Note
+    /// The snapshot is written to a temporary file next to `path` and renamed into place,
+    /// so `path` never holds a partial snapshot.
+    ///
+    pub fn export_snapshot(&mut self, path: &Path) -> Result<(), std::io::Error> {
+        let mut snapshot = Self::new_snapshot_file(path)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut self.file, snapshot.as_file_mut())?;
+        Self::persist_snapshot_file(snapshot, path)
+    }
 
-        let data = [10, 20, 30];
-        let mut page = [0u8; PAGE_SIZE];
-        page[..data.len()].copy_from_slice(&data);
+    ///
+    /// Copies a snapshot written by `export_snapshot` to `dest`, verifying the checksum of
+    /// every page.
+    ///
+    /// # Arguments
+    /// * `path`: Path of the snapshot.
+    /// * `dest`: Path the data file is restored to. An existing file is replaced. It must
+    ///   not be open in a DiskManager.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the snapshot was restored.
+    /// * `Err(std::io::Error)` if an error occurred while copying the file. If a page
+    ///   checksum doesn't match, the error is of kind `InvalidData` and wraps
+    ///   `RustyKVError::ChecksumMismatch`. `dest` is left untouched on error.
+    ///
+    pub fn import_snapshot(path: &Path, dest: &Path) -> Result<(), std::io::Error> {
+        let mut source = File::open(path)?;
+        if source.metadata()?.len() % PAGE_SIZE as u64 != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Snapshot length is not a multiple of the page size",
+            ));
+        }
 
-        let id = disk_manager.allocate_page();
+        let mut snapshot = Self::new_snapshot_file(dest)?;
+        let mut buffer = [0u8; PAGE_SIZE];
+        loop {
+            match source.read_exact(&mut buffer) {
+                Ok(()) => {}
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(error),
+            }
+            if !Self::verify_checksum(&buffer) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    RustyKVError::ChecksumMismatch,
+                ));
+            }
+            snapshot.write_all(&buffer)?;
+        }
+        Self::persist_snapshot_file(snapshot, dest)
+    }
 
-        disk_manager.write_page(&id, &page).unwrap();
+    ///
+    /// Scans a data file for corrupt pages, without modifying it. Every page's checksum is
+    /// verified, and the free list in the metadata page must only hold distinct, allocated
+    /// Page IDs. Allocated BTree pages must be well formed: their slot map and rows must fit
+    /// in the page body, see `BTreePage::is_well_formed`. A trailing partial page, left by a
+    /// torn write, is reported as corrupt.
+    ///
+    /// # Arguments
+    /// * `path`: Path to the data file. It can be open in a DiskManager, since no lock is
+    ///   taken, but pages written during the scan may be reported as corrupt.
+    ///
+    /// # Returns
+    /// * `Ok(VerifyReport)`: The pages that failed verification.
+    /// * `Err(std::io::Error)` if the file couldn't be read.
+    ///
+    /// # Impl Note
+    /// Pages that aren't BTree pages, like overflow pages, are told apart by their page
+    /// type, see `BTreePage::mark_as_other_page`. Free pages aren't checked, since they may
+    /// hold anything. If the metadata page is corrupt, every page is checked.
+    ///
+    pub fn verify(path: &Path) -> Result<VerifyReport, std::io::Error> {
+        let mut file = File::open(path)?;
+        let length = file.metadata()?.len() as usize;
+        let num_pages = length.div_ceil(PAGE_SIZE);
 
-        let new_data = [2, 10, 36];
-        let mut new_page = [0u8; PAGE_SIZE];
-        new_page[..new_data.len()].copy_from_slice(&new_data);
+        // The free list can span pages anywhere in the file, so it's read first.
+        let mut buffer = [0u8; PAGE_SIZE];
+        let mut read_page = |page_id: PageId, buffer: &mut [u8; PAGE_SIZE]| {
+            if page_id.value() >= num_pages as u64 {
+                return Err(Error::from(RustyKVError::ItemNotFound));
+            }
+            file.seek(SeekFrom::Start(page_id.value() * PAGE_SIZE as u64))?;
+            file.read_exact(buffer)?;
+            if !Self::verify_checksum(buffer) {
+                return Err(RustyKVError::ChecksumMismatch.into());
+            }
+            Ok(())
+        };
+        let free_pages = match read_page(METADATA_PAGE_ID, &mut buffer) {
+            Ok(()) => Self::read_free_list(&buffer, &mut read_page).ok(),
+            Err(_) => None,
+        };
+        file.seek(SeekFrom::Start(0))?;
 
-        disk_manager.write_page(&id, &new_page).unwrap();
+        let mut corrupt_pages = Vec::new();
+        for index in 0..num_pages {
+            let page_id = PageId::new(index as u64);
+            let page_size = PAGE_SIZE.min(length - index * PAGE_SIZE);
+            file.read_exact(&mut buffer[..page_size])?;
 
-        let mut data_read = [0u8; PAGE_SIZE];
-        disk_manager.read_page(&id, &mut data_read).unwrap();
-        assert_eq!(&new_page, &data_read);
+            let valid = page_size == PAGE_SIZE
+                && Self::verify_checksum(&buffer)
+                && if page_id == METADATA_PAGE_ID {
+                    Self::check_header(&buffer).is_ok()
+                        && buffer[COMPARATOR_NAME_SIZE_OFFSET] as usize <= MAX_COMPARATOR_NAME_SIZE
+                        && free_pages.as_ref().is_some_and(|free_pages| {
+                            free_pages
+                                .iter()
+                                .all(|page_id| page_id.value() < num_pages as u64)
+                        })
+                } else {
+                    free_pages
+                        .as_ref()
+                        .is_some_and(|free_pages| free_pages.contains(&page_id))
+                        || !BTreePage::is_btree_page(&buffer)
+                        || BTreePage::is_well_formed(&buffer)
+                };
+            if !valid {
+                corrupt_pages.push(page_id);
+            }
+        }
+        Ok(VerifyReport {
+            num_pages,
+            corrupt_pages,
+        })
+    }
+
+    ///
+    /// Reads the free list: the Page IDs in a metadata page, followed by the ones in the chain
+    /// of free list pages it points to, read with `read_page`.
+    ///
+    /// # Returns
+    /// * `Ok(free_pages)`: The Page IDs, in the order they were deallocated.
+    /// * `Err(std::io::Error)` if a free list page couldn't be read. If a count doesn't fit
+    ///   in its page, or the list holds the metadata page, a Page ID twice or a cycle, the
+    ///   error is of kind `InvalidData`.
+    ///
+    fn read_free_list(
+        metadata: &[u8; PAGE_SIZE],
+        mut read_page: impl FnMut(PageId, &mut [u8; PAGE_SIZE]) -> Result<(), Error>,
+    ) -> Result<Vec<PageId>, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "corrupt free list");
+        let count = Self::read_u32(metadata, FREE_PAGE_COUNT_OFFSET) as usize;
+        if count > MAX_FREE_PAGES {
+            return Err(invalid());
+        }
+        let mut free_pages: Vec<PageId> =
+            Self::read_page_ids(metadata, FREE_PAGES_OFFSET, count).collect();
+        let mut next = Self::read_page_id(metadata, FREE_LIST_NEXT_OFFSET);
+        let mut buffer = [0u8; PAGE_SIZE];
+        while next != METADATA_PAGE_ID {
+            // Every free list page is a free page, so a cycle lists one twice.
+            if free_pages.contains(&next) {
+                return Err(invalid());
+            }
+            read_page(next, &mut buffer)?;
+            let count = Self::read_u32(&buffer, FREE_LIST_PAGE_COUNT_OFFSET) as usize;
+            if count > MAX_FREE_LIST_PAGE_IDS {
+                return Err(invalid());
+            }
+            let page_ids: Vec<PageId> =
+                Self::read_page_ids(&buffer, FREE_LIST_PAGE_IDS_OFFSET, count).collect();
+            if page_ids.first() != Some(&next) {
+                return Err(invalid());
+            }
+            free_pages.extend(page_ids);
+            next = Self::read_page_id(&buffer, FREE_LIST_PAGE_NEXT_OFFSET);
+        }
+
+        let mut seen = HashSet::with_capacity(free_pages.len());
+        if free_pages
+            .iter()
+            .any(|page_id| *page_id == METADATA_PAGE_ID || !seen.insert(*page_id))
+        {
+            return Err(invalid());
+        }
+        Ok(free_pages)
+    }
+
+    ///
+    /// Creates a temporary file in the same directory as `path`, so that it can be renamed
+    /// to `path`.
+    ///
+    pub(crate) fn new_snapshot_file(path: &Path) -> Result<NamedTempFile, std::io::Error> {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => NamedTempFile::new_in(parent),
+            _ => NamedTempFile::new_in("."),
+        }
+    }
+
+    ///
+    /// Syncs a temporary file created by `new_snapshot_file` and renames it to `path`.
+    ///
+    pub(crate) fn persist_snapshot_file(
+        snapshot: NamedTempFile,
+        path: &Path,
+    ) -> Result<(), std::io::Error> {
+        snapshot.as_file().sync_all()?;
+        snapshot.persist(path).map_err(|error| error.error)?;
+        Ok(())
+    }
+
+    ///
+    /// Returns a copy of the page with the checksum of its contents in the first
+    /// PAGE_CHECKSUM_SIZE bytes.
+    ///
+    pub(crate) fn with_checksum(buffer: &[u8; PAGE_SIZE]) -> [u8; PAGE_SIZE] {
+        let mut page = *buffer;
+        let checksum = crc32(&page[PAGE_CHECKSUM_OFFSET + PAGE_CHECKSUM_SIZE..]);
+        page[PAGE_CHECKSUM_OFFSET..PAGE_CHECKSUM_OFFSET + PAGE_CHECKSUM_SIZE]
+            .copy_from_slice(&checksum.to_le_bytes());
+        page
+    }
+
+    ///
+    /// Checks the checksum stored in a page against its contents.
+    ///
+    /// # Returns
+    /// * `true` if the checksum matches or the page has never been written, `false` otherwise.
+    ///
+    fn verify_checksum(buffer: &[u8; PAGE_SIZE]) -> bool {
+        let stored_checksum = u32::from_le_bytes(
+            buffer[PAGE_CHECKSUM_OFFSET..PAGE_CHECKSUM_OFFSET + PAGE_CHECKSUM_SIZE]
+                .try_into()
+                .unwrap(),
+        );
+        let data = &buffer[PAGE_CHECKSUM_OFFSET + PAGE_CHECKSUM_SIZE..];
+        if stored_checksum == 0 && data.iter().all(|byte| *byte == 0) {
+            return true;
+        }
+        stored_checksum == crc32(data)
+    }
+
+    ///
+    /// Allocates a Page of data in the file.
+    ///
+    /// # Returns
+    /// * `PageId`: The PageID of the page allocated.
+    ///
+    pub fn allocate_page(&mut self) -> Result<PageId, std::io::Error> {
+        self.check_writable()?;
+        if let Some(page_id) = self.free_pages.pop() {
+            self.write_metadata()?;
+            return Ok(page_id);
+        }
+        let page_id = PageId::new(self.num_pages as u64);
+        self.num_pages += 1;
+        Ok(page_id)
+    }
+
+    ///
+    /// Deallocates a Page so that it can be handed out again by `allocate_page`.
+    ///
+    /// # Arguments
+    /// * `id`: Page ID of the Page to be deallocated.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the page was deallocated.
+    /// * `Err(std::io::Error)` if an error occurred while persisting the free list. If `id`
+    ///   is the metadata page, hasn't been allocated or is already deallocated, the error is
+    ///   of kind `InvalidInput` and the free list is left as is.
+    ///
+    /// # Impl Note
+    /// Once the metadata page is full, the rest of the free list is written to deallocated
+    /// pages, so every page written to the free list is rewritten each time it changes.
+    ///
+    pub fn deallocate_page(&mut self, id: PageId) -> Result<(), std::io::Error> {
+        self.check_writable()?;
+        Self::check_deallocation(id, self.num_pages, &self.free_pages)?;
+        self.free_pages.push(id);
+        self.write_metadata()
+    }
+
+    ///
+    /// Checks that a page can be deallocated: it isn't the metadata page, it is one of the
+    /// `num_pages` pages allocated, and it isn't in `free_pages` already.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the page can be deallocated.
+    /// * `Err(std::io::Error)` of kind `InvalidInput` otherwise.
+    ///
+    pub(crate) fn check_deallocation(
+        id: PageId,
+        num_pages: usize,
+        free_pages: &[PageId],
+    ) -> Result<(), std::io::Error> {
+        let error = if id == METADATA_PAGE_ID {
+            "the metadata page can't be deallocated"
+        } else if id.value() >= num_pages as u64 {
+            "page hasn't been allocated"
+        } else if free_pages.contains(&id) {
+            "page is already deallocated"
+        } else {
+            return Ok(());
+        };
+        Err(Error::new(ErrorKind::InvalidInput, error))
+    }
+
+    ///
+    /// Returns the name of the comparator the keys in the file are ordered by, as recorded
+    /// by `set_comparator_name`.
+    ///
+    /// # Returns
+    /// * `Some(name)` if a name was recorded, `None` otherwise.
+    ///
+    pub fn get_comparator_name(&self) -> Option<&str> {
+        Some(self.comparator_name.as_str()).filter(|name| !name.is_empty())
+    }
+
+    ///
+    /// Records the name of the comparator the keys in the file are ordered by, in the
+    /// metadata page.
+    ///
+    /// # Arguments
+    /// * `name`: Name of the comparator, at most `MAX_COMPARATOR_NAME_SIZE` bytes long.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the name was recorded.
+    /// * `Err(std::io::Error)` if the metadata page couldn't be written. If the name is too
+    ///   long, the error is of kind `InvalidInput`.
+    ///
+    pub fn set_comparator_name(&mut self, name: &str) -> Result<(), std::io::Error> {
+        self.check_writable()?;
+        if name.len() > MAX_COMPARATOR_NAME_SIZE {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "comparator name is too long",
+            ));
+        }
+        self.comparator_name = name.to_string();
+        self.write_metadata()
+    }
+
+    ///
+    /// Fails with `RustyKVError::ReadOnly` if the file was opened with `open_readonly`.
+    ///
+    fn check_writable(&self) -> Result<(), std::io::Error> {
+        if self.read_only {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                RustyKVError::ReadOnly,
+            ));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Loads the free list and comparator name from the metadata page.
+    ///
+    fn read_metadata(&mut self) -> Result<(), std::io::Error> {
+        let mut buffer = [0u8; PAGE_SIZE];
+        if let Err(error) = self.read_page(&METADATA_PAGE_ID, &mut buffer) {
+            // Files of another format fail the checksum too, but aren't corrupt data files.
+            if error.kind() != ErrorKind::InvalidData || Self::has_magic(&buffer) {
+                return Err(error);
+            }
+        }
+        Self::check_header(&buffer)?;
+
+        let name_size =
+            (buffer[COMPARATOR_NAME_SIZE_OFFSET] as usize).min(MAX_COMPARATOR_NAME_SIZE);
+        self.comparator_name = String::from_utf8_lossy(
+            &buffer[COMPARATOR_NAME_OFFSET..COMPARATOR_NAME_OFFSET + name_size],
+        )
+        .into_owned();
+
+        // Pages allocated but never written aren't in the file once it's reopened, so they'll
+        // be allocated again by growing it.
+        let num_pages = self.num_pages as u64;
+        self.free_pages =
+            Self::read_free_list(&buffer, |page_id, page| self.read_page(&page_id, page))?
+                .into_iter()
+                .filter(|page_id| page_id.value() < num_pages)
+                .collect();
+        Ok(())
+    }
+
+    ///
+    /// Returns `true` if a metadata page starts with FILE_MAGIC.
+    ///
+    fn has_magic(buffer: &[u8; PAGE_SIZE]) -> bool {
+        buffer[MAGIC_OFFSET..MAGIC_OFFSET + MAGIC_SIZE] == FILE_MAGIC
+    }
+
+    ///
+    /// Checks the magic number and format version of a metadata page.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the page belongs to a data file of this format version.
+    /// * `Err(std::io::Error)` of kind `InvalidData` wrapping `RustyKVError::InvalidMagic` or
+    ///   `RustyKVError::UnsupportedVersion` otherwise.
+    ///
+    fn check_header(buffer: &[u8; PAGE_SIZE]) -> Result<(), std::io::Error> {
+        if !Self::has_magic(buffer) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                RustyKVError::InvalidMagic,
+            ));
+        }
+        let version = u16::from_le_bytes(
+            buffer[FORMAT_VERSION_OFFSET..FORMAT_VERSION_OFFSET + FORMAT_VERSION_SIZE]
+                .try_into()
+                .unwrap(),
+        );
+        if version != FORMAT_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                RustyKVError::UnsupportedVersion(version),
+            ));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Returns a metadata page with the magic number and format version, and no comparator
+    /// name or free pages. Its checksum isn't set.
+    ///
+    pub(crate) fn new_metadata_page() -> [u8; PAGE_SIZE] {
+        let mut buffer = [0u8; PAGE_SIZE];
+        buffer[MAGIC_OFFSET..MAGIC_OFFSET + MAGIC_SIZE].copy_from_slice(&FILE_MAGIC);
+        buffer[FORMAT_VERSION_OFFSET..FORMAT_VERSION_OFFSET + FORMAT_VERSION_SIZE]
+            .copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        buffer
+    }
+
+    fn read_u32(buffer: &[u8; PAGE_SIZE], offset: usize) -> u32 {
+        u32::from_le_bytes(
+            buffer[offset..offset + size_of::<u32>()]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    fn read_page_id(buffer: &[u8; PAGE_SIZE], offset: usize) -> PageId {
+        PageId::new(u64::from_le_bytes(
+            buffer[offset..offset + FREE_PAGE_ID_SIZE]
+                .try_into()
+                .unwrap(),
+        ))
+    }
+
+    ///
+    /// Reads `count` Page IDs stored one after another from `offset`.
+    ///
+    fn read_page_ids(
+        buffer: &[u8; PAGE_SIZE],
+        offset: usize,
+        count: usize,
+    ) -> impl Iterator<Item = PageId> {
+        buffer[offset..]
+            .chunks_exact(FREE_PAGE_ID_SIZE)
+            .take(count)
+            .map(|bytes| PageId::new(u64::from_le_bytes(bytes.try_into().unwrap())))
+    }
+
+    ///
+    /// Writes Page IDs one after another from `offset`, preceded by their count at
+    /// `count_offset` and followed by the Page ID of the next free list page at
+    /// `next_offset`.
+    ///
+    fn write_page_ids(
+        buffer: &mut [u8; PAGE_SIZE],
+        (count_offset, next_offset, offset): (usize, usize, usize),
+        page_ids: &[PageId],
+        next: PageId,
+    ) {
+        buffer[count_offset..count_offset + FREE_PAGE_COUNT_SIZE]
+            .copy_from_slice(&(page_ids.len() as u32).to_le_bytes());
+        buffer[next_offset..next_offset + FREE_LIST_NEXT_SIZE]
+            .copy_from_slice(&next.value().to_le_bytes());
+        for (index, page_id) in page_ids.iter().enumerate() {
+            let offset = offset + index * FREE_PAGE_ID_SIZE;
+            buffer[offset..offset + FREE_PAGE_ID_SIZE]
+                .copy_from_slice(&page_id.value().to_le_bytes());
+        }
+    }
+
+    ///
+    /// Persists the free list and comparator name to the metadata page. Page IDs that don't
+    /// fit in it are written to free list pages, each stored in the first free page it
+    /// lists.
+    ///
+    fn write_metadata(&mut self) -> Result<(), std::io::Error> {
+        let free_pages = self.free_pages.clone();
+        let (head, rest) = free_pages.split_at(free_pages.len().min(MAX_FREE_PAGES));
+        // Written back to front, so that each page can point to the next one.
+        let mut next = METADATA_PAGE_ID;
+        for page_ids in rest.chunks(MAX_FREE_LIST_PAGE_IDS).rev() {
+            let mut buffer = [0u8; PAGE_SIZE];
+            BTreePage::mark_as_other_page(&mut buffer);
+            Self::write_page_ids(
+                &mut buffer,
+                (
+                    FREE_LIST_PAGE_COUNT_OFFSET,
+                    FREE_LIST_PAGE_NEXT_OFFSET,
+                    FREE_LIST_PAGE_IDS_OFFSET,
+                ),
+                page_ids,
+                next,
+            );
+            self.write_page(&page_ids[0], &buffer)?;
+            next = page_ids[0];
+        }
+
+        let mut buffer = Self::new_metadata_page();
+        let name = self.comparator_name.as_bytes();
+        buffer[COMPARATOR_NAME_SIZE_OFFSET] = name.len() as u8;
+        buffer[COMPARATOR_NAME_OFFSET..COMPARATOR_NAME_OFFSET + name.len()].copy_from_slice(name);
+        Self::write_page_ids(
+            &mut buffer,
+            (
+                FREE_PAGE_COUNT_OFFSET,
+                FREE_LIST_NEXT_OFFSET,
+                FREE_PAGES_OFFSET,
+            ),
+            head,
+            next,
+        );
+        self.write_page(&METADATA_PAGE_ID, &buffer)
+    }
+}
+
+impl StorageBackend for DiskManager {
+    fn read_page(&mut self, id: &PageId, buffer: &mut [u8; PAGE_SIZE]) -> Result<(), Error> {
+        DiskManager::read_page(self, id, buffer)
+    }
+
+    fn read_page_head(&mut self, id: &PageId, buffer: &mut [u8]) -> Result<(), Error> {
+        DiskManager::read_page_head(self, id, buffer)
+    }
+
+    fn write_page(&mut self, id: &PageId, buffer: &[u8; PAGE_SIZE]) -> Result<(), Error> {
+        DiskManager::write_page(self, id, buffer)
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId, Error> {
+        DiskManager::allocate_page(self)
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), Error> {
+        DiskManager::deallocate_page(self, id)
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        DiskManager::sync(self)
+    }
+
+    fn export_snapshot(&mut self, path: &Path) -> Result<(), Error> {
+        DiskManager::export_snapshot(self, path)
+    }
+
+    fn num_pages(&self) -> usize {
+        DiskManager::num_pages(self)
+    }
+
+    fn free_pages(&self) -> &[PageId] {
+        DiskManager::free_pages(self)
+    }
+
+    fn get_comparator_name(&self) -> Option<&str> {
+        DiskManager::get_comparator_name(self)
+    }
+
+    fn set_comparator_name(&mut self, name: &str) -> Result<(), Error> {
+        DiskManager::set_comparator_name(self, name)
+    }
+}
+
+impl Drop for DiskManager {
+    fn drop(&mut self) {
+        // Closing the file would release the lock anyway, but be explicit.
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_create_and_get() {
+        let temp_file = NamedTempFile::new().unwrap();
+
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+
+        let data = [10, 20, 30];
+        let mut page = [0u8; PAGE_SIZE];
+        page[PAGE_CHECKSUM_SIZE..PAGE_CHECKSUM_SIZE + data.len()].copy_from_slice(&data);
+
+        let id = disk_manager.allocate_page().unwrap();
+
+        disk_manager.write_page(&id, &page).unwrap();
+        let mut data_read = [0u8; PAGE_SIZE];
+        disk_manager.read_page(&id, &mut data_read).unwrap();
+        assert_eq!(
+            &page[PAGE_CHECKSUM_SIZE..],
+            &data_read[PAGE_CHECKSUM_SIZE..]
+        );
+    }
+
+    #[test]
+    fn test_create_existing_key() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+
+        let data = [10, 20, 30];
+        let mut page = [0u8; PAGE_SIZE];
+        page[PAGE_CHECKSUM_SIZE..PAGE_CHECKSUM_SIZE + data.len()].copy_from_slice(&data);
+
+        let id = disk_manager.allocate_page().unwrap();
+
+        disk_manager.write_page(&id, &page).unwrap();
+
+        let new_data = [2, 10, 36];
+        let mut new_page = [0u8; PAGE_SIZE];
+        new_page[PAGE_CHECKSUM_SIZE..PAGE_CHECKSUM_SIZE + new_data.len()]
+            .copy_from_slice(&new_data);
+
+        disk_manager.write_page(&id, &new_page).unwrap();
+
+        let mut data_read = [0u8; PAGE_SIZE];
+        disk_manager.read_page(&id, &mut data_read).unwrap();
+        assert_eq!(
+            &new_page[PAGE_CHECKSUM_SIZE..],
+            &data_read[PAGE_CHECKSUM_SIZE..]
+        );
+    }
+
+    #[test]
+    fn test_corrupt_page_fails_checksum() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+
+        let id = disk_manager.allocate_page().unwrap();
+        disk_manager.write_page(&id, &[7u8; PAGE_SIZE]).unwrap();
+
+        // Flip a byte in the middle of the page behind the disk manager's back.
+        let corrupt_offset = id.value() * PAGE_SIZE as u64 + (PAGE_SIZE / 2) as u64;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(temp_file.path())
+            .unwrap();
+        file.seek(SeekFrom::Start(corrupt_offset)).unwrap();
+        file.write_all(&[8u8]).unwrap();
+
+        let mut data_read = [0u8; PAGE_SIZE];
+        let error = disk_manager.read_page(&id, &mut data_read).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<RustyKVError>(),
+            Some(&RustyKVError::ChecksumMismatch)
+        );
+    }
+
+    #[test]
+    fn test_verify_on_read_toggle() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+
+        let id = disk_manager.allocate_page().unwrap();
+        disk_manager.write_page(&id, &[7u8; PAGE_SIZE]).unwrap();
+
+        let corrupt_offset = id.value() * PAGE_SIZE as u64 + (PAGE_SIZE / 2) as u64;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(temp_file.path())
+            .unwrap();
+        file.seek(SeekFrom::Start(corrupt_offset)).unwrap();
+        file.write_all(&[8u8]).unwrap();
+
+        // With verification off, the corrupt page is returned as is.
+        disk_manager.set_verify_on_read(false);
+        let mut data_read = [0u8; PAGE_SIZE];
+        disk_manager.read_page(&id, &mut data_read).unwrap();
+        assert_eq!(data_read[PAGE_SIZE / 2], 8);
+        assert_eq!(data_read[PAGE_SIZE - 1], 7);
+
+        disk_manager.set_verify_on_read(true);
+        let error = disk_manager.read_page(&id, &mut data_read).err().unwrap();
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<RustyKVError>(),
+            Some(&RustyKVError::ChecksumMismatch)
+        );
+    }
+
+    #[test]
+    fn test_unwritten_page_passes_checksum() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+
+        let id = disk_manager.allocate_page().unwrap();
+        let mut data_read = [1u8; PAGE_SIZE];
+        disk_manager.read_page(&id, &mut data_read).unwrap();
+        assert_eq!(data_read, [0u8; PAGE_SIZE]);
+    }
+
+    #[test]
+    fn test_second_writer_is_rejected() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+
+        // A second read-write open fails while the first one holds the lock.
+        let error = DiskManager::new(temp_file.path(), SyncMode::Deferred)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::WouldBlock);
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<RustyKVError>(),
+            Some(&RustyKVError::AlreadyOpen)
+        );
+
+        // Read-only handles don't take the lock.
+        let mut reader = File::open(temp_file.path()).unwrap();
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents).unwrap();
+
+        // Dropping the first manager releases the lock.
+        drop(disk_manager);
+        assert!(DiskManager::new(temp_file.path(), SyncMode::Deferred).is_ok());
+    }
+
+    ///
+    /// Contents of the scratch file after `write_page_atomic` has staged a page.
+    ///
+    fn scratch_contents(id: &PageId, buffer: &[u8; PAGE_SIZE]) -> Vec<u8> {
+        let mut contents = id.value().to_le_bytes().to_vec();
+        contents.extend_from_slice(&DiskManager::with_checksum(buffer));
+        contents
+    }
+
+    #[test]
+    fn test_write_page_atomic() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let scratch_path = DiskManager::get_scratch_path(temp_file.path());
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Never).unwrap();
+
+        let id = disk_manager.allocate_page().unwrap();
+        let page = [3u8; PAGE_SIZE];
+        disk_manager.write_page_atomic(&id, &page).unwrap();
+        let mut data_read = [0u8; PAGE_SIZE];
+        disk_manager.read_page(&id, &mut data_read).unwrap();
+        assert_eq!(
+            &page[PAGE_CHECKSUM_SIZE..],
+            &data_read[PAGE_CHECKSUM_SIZE..]
+        );
+        assert_eq!(disk_manager.num_pages(), 2);
+        // The scratch copy is dropped once the page is in place.
+        assert_eq!(fs::metadata(&scratch_path).unwrap().len(), 0);
+
+        let _ = fs::remove_file(&scratch_path);
+    }
+
+    #[test]
+    fn test_torn_atomic_write_keeps_old_page() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let scratch_path = DiskManager::get_scratch_path(temp_file.path());
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        let id = disk_manager.allocate_page().unwrap();
+        let old_page = [1u8; PAGE_SIZE];
+        disk_manager.write_page(&id, &old_page).unwrap();
+        drop(disk_manager);
+
+        // Crash while staging the new page: only half of it made it to the scratch file.
+        let contents = scratch_contents(&id, &[2u8; PAGE_SIZE]);
+        fs::write(&scratch_path, &contents[..SCRATCH_FILE_SIZE / 2]).unwrap();
+
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        let mut data_read = [0u8; PAGE_SIZE];
+        disk_manager.read_page(&id, &mut data_read).unwrap();
+        assert_eq!(
+            &old_page[PAGE_CHECKSUM_SIZE..],
+            &data_read[PAGE_CHECKSUM_SIZE..]
+        );
+        assert_eq!(fs::metadata(&scratch_path).unwrap().len(), 0);
+
+        let _ = fs::remove_file(&scratch_path);
+    }
+
+    #[test]
+    fn test_torn_in_place_write_is_recovered() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let scratch_path = DiskManager::get_scratch_path(temp_file.path());
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        let id = disk_manager.allocate_page().unwrap();
+        disk_manager.write_page(&id, &[1u8; PAGE_SIZE]).unwrap();
+        drop(disk_manager);
+
+        // Crash while writing the staged page in place: only its first half overwrote the
+        // old page.
+        let new_page = [2u8; PAGE_SIZE];
+        let contents = scratch_contents(&id, &new_page);
+        fs::write(&scratch_path, &contents).unwrap();
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(temp_file.path())
+            .unwrap();
+        file.seek(SeekFrom::Start(id.value() * PAGE_SIZE as u64))
+            .unwrap();
+        file.write_all(&contents[SCRATCH_PAGE_ID_SIZE..SCRATCH_PAGE_ID_SIZE + PAGE_SIZE / 2])
+            .unwrap();
+        assert!(
+            DiskManager::verify(temp_file.path())
+                .unwrap()
+                .corrupt_pages
+                .contains(&id)
+        );
+
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        let mut data_read = [0u8; PAGE_SIZE];
+        disk_manager.read_page(&id, &mut data_read).unwrap();
+        assert_eq!(
+            &new_page[PAGE_CHECKSUM_SIZE..],
+            &data_read[PAGE_CHECKSUM_SIZE..]
+        );
+
+        let _ = fs::remove_file(&scratch_path);
+    }
+
+    #[test]
+    fn test_open_readonly() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        let id = disk_manager.allocate_page().unwrap();
+        let page = [6u8; PAGE_SIZE];
+        disk_manager.write_page(&id, &page).unwrap();
+
+        // The writer keeps the lock, which read-only handles don't need.
+        let mut reader = DiskManager::open_readonly(temp_file.path()).unwrap();
+        assert_eq!(reader.num_pages(), 2);
+        let mut data_read = [0u8; PAGE_SIZE];
+        reader.read_page(&id, &mut data_read).unwrap();
+        assert_eq!(
+            &page[PAGE_CHECKSUM_SIZE..],
+            &data_read[PAGE_CHECKSUM_SIZE..]
+        );
+
+        let errors = [
+            reader.write_page(&id, &[7u8; PAGE_SIZE]).err().unwrap(),
+            reader
+                .write_page_atomic(&id, &[7u8; PAGE_SIZE])
+                .err()
+                .unwrap(),
+            reader.allocate_page().err().unwrap(),
+            reader.deallocate_page(id).err().unwrap(),
+        ];
+        for error in errors {
+            assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+            assert_eq!(RustyKVError::from(error), RustyKVError::ReadOnly);
+        }
+        reader.sync().unwrap();
+        assert_eq!(reader.num_pages(), 2);
+
+        // The page is unchanged.
+        drop(reader);
+        disk_manager.read_page(&id, &mut data_read).unwrap();
+        assert_eq!(
+            &page[PAGE_CHECKSUM_SIZE..],
+            &data_read[PAGE_CHECKSUM_SIZE..]
+        );
+
+        let empty_file = NamedTempFile::new().unwrap();
+        let error = DiskManager::open_readonly(empty_file.path()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_large_page_id() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+
+        let id = PageId::new(1000);
+        let page = [5u8; PAGE_SIZE];
+        disk_manager.write_page(&id, &page).unwrap();
+
+        let mut data_read = [0u8; PAGE_SIZE];
+        disk_manager.read_page(&id, &mut data_read).unwrap();
+        assert_eq!(
+            &page[PAGE_CHECKSUM_SIZE..],
+            &data_read[PAGE_CHECKSUM_SIZE..]
+        );
+        assert_eq!(
+            temp_file.as_file().metadata().unwrap().len(),
+            1001 * PAGE_SIZE as u64
+        );
+        assert_eq!(disk_manager.num_pages(), 1001);
+    }
+
+    #[test]
+    fn test_num_pages() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        // The metadata page is always allocated.
+        assert_eq!(disk_manager.num_pages(), 1);
+
+        let first = disk_manager.allocate_page().unwrap();
+        disk_manager.allocate_page().unwrap();
+        assert_eq!(disk_manager.num_pages(), 3);
+
+        // Reusing a deallocated page doesn't add one.
+        disk_manager.deallocate_page(first).unwrap();
+        assert_eq!(disk_manager.num_pages(), 3);
+        assert_eq!(disk_manager.allocate_page().unwrap(), first);
+        assert_eq!(disk_manager.num_pages(), 3);
+
+        // Only pages that have been written are in the file once it's reopened.
+        disk_manager.write_page(&first, &[0u8; PAGE_SIZE]).unwrap();
+        drop(disk_manager);
+        let disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        assert_eq!(disk_manager.num_pages(), 2);
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager =
+            DiskManager::with_capacity(temp_file.path(), SyncMode::Deferred, 100).unwrap();
+        let file_length = || temp_file.as_file().metadata().unwrap().len();
+        assert_eq!(file_length(), 100 * PAGE_SIZE as u64);
+        assert_eq!(disk_manager.num_pages(), 100);
+        assert_eq!(disk_manager.free_pages().len(), 99);
+
+        // The metadata page is preallocated too, leaving 99 pages to hand out.
+        for index in 1..100 {
+            let id = disk_manager.allocate_page().unwrap();
+            assert_eq!(id, PageId::new(index));
+            disk_manager.write_page(&id, &[4u8; PAGE_SIZE]).unwrap();
+            assert_eq!(file_length(), 100 * PAGE_SIZE as u64);
+        }
+
+        // Once they're exhausted, the file grows again.
+        let id = disk_manager.allocate_page().unwrap();
+        assert_eq!(id, PageId::new(100));
+        disk_manager.write_page(&id, &[4u8; PAGE_SIZE]).unwrap();
+        assert_eq!(file_length(), 101 * PAGE_SIZE as u64);
+
+        // A file that is already large enough isn't shrunk.
+        drop(disk_manager);
+        let disk_manager =
+            DiskManager::with_capacity(temp_file.path(), SyncMode::Deferred, 10).unwrap();
+        assert_eq!(file_length(), 101 * PAGE_SIZE as u64);
+        assert_eq!(disk_manager.num_pages(), 101);
+        assert!(disk_manager.free_pages().is_empty());
+    }
+
+    #[test]
+    fn test_with_capacity_reopen_reuses_preallocated_pages() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager =
+            DiskManager::with_capacity(temp_file.path(), SyncMode::Deferred, 10).unwrap();
+        for index in 1..4 {
+            let id = disk_manager.allocate_page().unwrap();
+            assert_eq!(id, PageId::new(index));
+            disk_manager.write_page(&id, &[4u8; PAGE_SIZE]).unwrap();
+        }
+        drop(disk_manager);
+
+        // The file is as long as it was preallocated, but the pages that weren't handed out
+        // are still free.
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        assert_eq!(disk_manager.num_pages(), 10);
+        assert_eq!(disk_manager.free_pages().len(), 6);
+        for index in 4..10 {
+            assert_eq!(disk_manager.allocate_page().unwrap(), PageId::new(index));
+        }
+        assert_eq!(disk_manager.allocate_page().unwrap(), PageId::new(10));
+        assert_eq!(
+            temp_file.as_file().metadata().unwrap().len(),
+            10 * PAGE_SIZE as u64
+        );
+    }
+
+    #[test]
+    fn test_read_unallocated_page() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+
+        let mut data_read = [0u8; PAGE_SIZE];
+        for id in [1, 1000] {
+            let error = disk_manager
+                .read_page(&PageId::new(id), &mut data_read)
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), ErrorKind::NotFound);
+            assert_eq!(
+                error.get_ref().unwrap().downcast_ref::<RustyKVError>(),
+                Some(&RustyKVError::ItemNotFound)
+            );
+        }
+
+        let id = disk_manager.allocate_page().unwrap();
+        assert_eq!(id, PageId::new(1));
+        assert!(disk_manager.read_page(&id, &mut data_read).is_ok());
+    }
+
+    #[test]
+    fn test_allocate_skips_metadata_page() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        assert_eq!(disk_manager.allocate_page().unwrap(), PageId::new(1));
+        assert_eq!(disk_manager.allocate_page().unwrap(), PageId::new(2));
+    }
+
+    #[test]
+    fn test_deallocated_page_is_reused() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+
+        let first = disk_manager.allocate_page().unwrap();
+        let second = disk_manager.allocate_page().unwrap();
+        let third = disk_manager.allocate_page().unwrap();
+
+        disk_manager.deallocate_page(second).unwrap();
+        assert_eq!(disk_manager.allocate_page().unwrap(), second);
+
+        // Once the free list is exhausted, the file is extended again.
+        let fourth = disk_manager.allocate_page().unwrap();
+        assert!(fourth != first && fourth != second && fourth != third);
+    }
+
+    #[test]
+    fn test_deallocate_rejects_invalid_pages() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        let page_id = disk_manager.allocate_page().unwrap();
+        disk_manager.deallocate_page(page_id).unwrap();
+
+        // A double free, the metadata page, and a page past the last one allocated.
+        for invalid in [page_id, METADATA_PAGE_ID, PageId::new(2), PageId::new(1000)] {
+            let error = disk_manager.deallocate_page(invalid).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+        assert_eq!(disk_manager.free_pages(), &[page_id]);
+
+        // Unallocated pages are never handed out.
+        assert_eq!(disk_manager.allocate_page().unwrap(), page_id);
+        assert_eq!(disk_manager.allocate_page().unwrap(), PageId::new(2));
+    }
+
+    #[test]
+    fn test_free_list_larger_than_metadata_page() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let page_count = MAX_FREE_PAGES + 2 * MAX_FREE_LIST_PAGE_IDS + 10;
+        let page_ids: Vec<PageId> = {
+            let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Never).unwrap();
+            let page_ids: Vec<PageId> = (0..page_count)
+                .map(|_| disk_manager.allocate_page().unwrap())
+                .collect();
+            for page_id in &page_ids {
+                disk_manager.write_page(page_id, &[2u8; PAGE_SIZE]).unwrap();
+            }
+            for page_id in &page_ids {
+                disk_manager.deallocate_page(*page_id).unwrap();
+            }
+            // None are leaked.
+            assert_eq!(disk_manager.free_pages(), page_ids.as_slice());
+            page_ids
+        };
+        let report = DiskManager::verify(temp_file.path()).unwrap();
+        assert!(report.is_ok());
+
+        // The whole list survives reopening, and is handed out in the same order.
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Never).unwrap();
+        assert_eq!(disk_manager.free_pages(), page_ids.as_slice());
+        for page_id in page_ids.iter().rev().take(MAX_FREE_LIST_PAGE_IDS + 5) {
+            assert_eq!(disk_manager.allocate_page().unwrap(), *page_id);
+        }
+        drop(disk_manager);
+        let disk_manager = DiskManager::new(temp_file.path(), SyncMode::Never).unwrap();
+        assert_eq!(
+            disk_manager.free_pages(),
+            &page_ids[..page_count - MAX_FREE_LIST_PAGE_IDS - 5]
+        );
+    }
+
+    #[test]
+    fn test_free_list_survives_reopen() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let page = [1u8; PAGE_SIZE];
+        let (first, second) = {
+            let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+            let first = disk_manager.allocate_page().unwrap();
+            let second = disk_manager.allocate_page().unwrap();
+            disk_manager.write_page(&first, &page).unwrap();
+            disk_manager.write_page(&second, &page).unwrap();
+            disk_manager.deallocate_page(first).unwrap();
+            (first, second)
+        };
+
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        assert_eq!(disk_manager.allocate_page().unwrap(), first);
+        let next = disk_manager.allocate_page().unwrap();
+        assert!(next != first && next != second);
+    }
+
+    #[test]
+    fn test_comparator_name_survives_reopen() {
+        let temp_file = NamedTempFile::new().unwrap();
+        {
+            let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+            assert_eq!(disk_manager.get_comparator_name(), None);
+            let too_long = "c".repeat(MAX_COMPARATOR_NAME_SIZE + 1);
+            assert_eq!(
+                disk_manager
+                    .set_comparator_name(&too_long)
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::InvalidInput
+            );
+            disk_manager.set_comparator_name("reverse").unwrap();
+            disk_manager.allocate_page().unwrap();
+            disk_manager.deallocate_page(PageId::new(1)).unwrap();
+        }
+
+        // The free list is stored after the name.
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        assert_eq!(disk_manager.get_comparator_name(), Some("reverse"));
+        assert_eq!(disk_manager.allocate_page().unwrap(), PageId::new(1));
+    }
+
+    #[test]
+    fn test_file_header() {
+        let temp_file = NamedTempFile::new().unwrap();
+        DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+
+        let bytes = fs::read(temp_file.path()).unwrap();
+        assert_eq!(bytes[MAGIC_OFFSET..MAGIC_OFFSET + MAGIC_SIZE], FILE_MAGIC);
+        assert!(DiskManager::new(temp_file.path(), SyncMode::Deferred).is_ok());
+        assert!(DiskManager::open_readonly(temp_file.path()).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_bad_magic() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let contents = b"not a data file".repeat(PAGE_SIZE / 8);
+        fs::write(temp_file.path(), &contents).unwrap();
+
+        for result in [
+            DiskManager::new(temp_file.path(), SyncMode::Deferred),
+            DiskManager::open_readonly(temp_file.path()),
+        ] {
+            let error = result.err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert_eq!(RustyKVError::from(error), RustyKVError::InvalidMagic);
+        }
+        // The file isn't overwritten.
+        assert_eq!(fs::read(temp_file.path()).unwrap(), contents);
+    }
+
+    #[test]
+    fn test_rejects_future_version() {
+        let temp_file = NamedTempFile::new().unwrap();
+        {
+            let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+            let mut buffer = [0u8; PAGE_SIZE];
+            disk_manager
+                .read_page(&METADATA_PAGE_ID, &mut buffer)
+                .unwrap();
+            buffer[FORMAT_VERSION_OFFSET..FORMAT_VERSION_OFFSET + FORMAT_VERSION_SIZE]
+                .copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+            disk_manager.write_page(&METADATA_PAGE_ID, &buffer).unwrap();
+        }
+
+        let error = DiskManager::new(temp_file.path(), SyncMode::Deferred)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        match RustyKVError::from(error) {
+            RustyKVError::UnsupportedVersion(version) => assert_eq!(version, FORMAT_VERSION + 1),
+            error => panic!("unexpected error: {}", error),
+        }
+        assert!(!DiskManager::verify(temp_file.path()).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_sync_modes() {
+        for sync_mode in [SyncMode::PerWrite, SyncMode::Deferred, SyncMode::Never] {
+            let temp_file = NamedTempFile::new().unwrap();
+            let mut disk_manager = DiskManager::new(temp_file.path(), sync_mode).unwrap();
+
+            let page = [42u8; PAGE_SIZE];
+            let id = disk_manager.allocate_page().unwrap();
+            disk_manager.write_page(&id, &page).unwrap();
+
+            // Writes are readable within the process before any sync.
+            let mut data_read = [0u8; PAGE_SIZE];
+            disk_manager.read_page(&id, &mut data_read).unwrap();
+            assert_eq!(
+                &page[PAGE_CHECKSUM_SIZE..],
+                &data_read[PAGE_CHECKSUM_SIZE..]
+            );
+
+            disk_manager.sync().unwrap();
+            disk_manager.read_page(&id, &mut data_read).unwrap();
+            assert_eq!(
+                &page[PAGE_CHECKSUM_SIZE..],
+                &data_read[PAGE_CHECKSUM_SIZE..]
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_reports_corrupt_pages() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let page_ids: Vec<PageId> = {
+            let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+            let page_ids: Vec<PageId> = (0..4)
+                .map(|_| disk_manager.allocate_page().unwrap())
+                .collect();
+            for page_id in &page_ids {
+                disk_manager.write_page(page_id, &[3u8; PAGE_SIZE]).unwrap();
+            }
+            disk_manager.deallocate_page(page_ids[3]).unwrap();
+            page_ids
+        };
+
+        let report = DiskManager::verify(temp_file.path()).unwrap();
+        assert_eq!(report.num_pages, 5);
+        assert!(report.is_ok());
+
+        // Flip a byte in the second data page.
+        let mut data = fs::read(temp_file.path()).unwrap();
+        data[page_ids[1].value() as usize * PAGE_SIZE + PAGE_SIZE / 2] ^= 0xFF;
+        fs::write(temp_file.path(), &data).unwrap();
+
+        let report = DiskManager::verify(temp_file.path()).unwrap();
+        assert_eq!(report.corrupt_pages, vec![page_ids[1]]);
+        // The file is left untouched.
+        assert_eq!(fs::read(temp_file.path()).unwrap(), data);
+
+        // A torn write leaves a partial page at the end of the file.
+        data.extend_from_slice(&[1u8; 100]);
+        fs::write(temp_file.path(), &data).unwrap();
+        let report = DiskManager::verify(temp_file.path()).unwrap();
+        assert_eq!(report.num_pages, 6);
+        assert_eq!(report.corrupt_pages, vec![page_ids[1], PageId::new(5)]);
+    }
+
+    #[test]
+    fn test_verify_checks_btree_page_headers() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        let page_ids: Vec<PageId> = (0..3)
+            .map(|_| disk_manager.allocate_page().unwrap())
+            .collect();
+        let mut data = [0u8; PAGE_SIZE];
+        let mut page = BTreePage::from(&mut data);
+        page.save(b"key", b"value").unwrap();
+        for page_id in &page_ids {
+            disk_manager.write_page(page_id, &data).unwrap();
+        }
+        assert!(DiskManager::verify(temp_file.path()).unwrap().is_ok());
+
+        // A slot count too large for the slot map to fit in the page, written through the
+        // disk manager so that the checksum is valid.
+        let mut corrupt = data;
+        corrupt[PAGE_CHECKSUM_SIZE..PAGE_CHECKSUM_SIZE + 2]
+            .copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(!BTreePage::is_well_formed(&corrupt));
+        disk_manager.write_page(&page_ids[1], &corrupt).unwrap();
+        // Free pages may hold anything.
+        disk_manager.write_page(&page_ids[2], &corrupt).unwrap();
+        disk_manager.deallocate_page(page_ids[2]).unwrap();
+
+        let report = DiskManager::verify(temp_file.path()).unwrap();
+        assert_eq!(report.corrupt_pages, vec![page_ids[1]]);
+
+        // Pages marked as other pages aren't checked as BTree pages.
+        BTreePage::mark_as_other_page(&mut corrupt);
+        disk_manager.write_page(&page_ids[1], &corrupt).unwrap();
+        assert!(DiskManager::verify(temp_file.path()).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_verify_checks_free_list() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        let page_id = disk_manager.allocate_page().unwrap();
+        disk_manager
+            .write_page(&page_id, &[3u8; PAGE_SIZE])
+            .unwrap();
+
+        // A metadata page with a valid checksum, but a free list pointing past the end of
+        // the file.
+        let mut metadata = [0u8; PAGE_SIZE];
+        metadata[FREE_PAGE_COUNT_OFFSET..FREE_PAGE_COUNT_OFFSET + FREE_PAGE_COUNT_SIZE]
+            .copy_from_slice(&1u32.to_le_bytes());
+        metadata[FREE_PAGES_OFFSET..FREE_PAGES_OFFSET + FREE_PAGE_ID_SIZE]
+            .copy_from_slice(&7u64.to_le_bytes());
+        disk_manager
+            .write_page(&METADATA_PAGE_ID, &metadata)
+            .unwrap();
+
+        let report = DiskManager::verify(temp_file.path()).unwrap();
+        assert_eq!(report.corrupt_pages, vec![METADATA_PAGE_ID]);
+    }
+
+    #[test]
+    fn test_import_rejects_corrupt_snapshot() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let snapshot_file = NamedTempFile::new().unwrap();
+        let dest_file = NamedTempFile::new().unwrap();
+        fs::write(dest_file.path(), b"existing").unwrap();
+
+        let mut disk_manager = DiskManager::new(temp_file.path(), SyncMode::Deferred).unwrap();
+        let page_id = disk_manager.allocate_page().unwrap();
+        disk_manager
+            .write_page(&page_id, &[1u8; PAGE_SIZE])
+            .unwrap();
+        disk_manager.export_snapshot(snapshot_file.path()).unwrap();
+        assert_eq!(
+            fs::read(snapshot_file.path()).unwrap(),
+            fs::read(temp_file.path()).unwrap()
+        );
+
+        // Flip a byte in the data page.
+        let mut snapshot = fs::read(snapshot_file.path()).unwrap();
+        snapshot[page_id.value() as usize * PAGE_SIZE + PAGE_CHECKSUM_SIZE] ^= 0xFF;
+        fs::write(snapshot_file.path(), &snapshot).unwrap();
+
+        let error = DiskManager::import_snapshot(snapshot_file.path(), dest_file.path())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<RustyKVError>(),
+            Some(&RustyKVError::ChecksumMismatch)
+        );
+        assert_eq!(fs::read(dest_file.path()).unwrap(), b"existing");
     }
 }
@@ -0,0 +1,143 @@
+// Order-preserving encodings of integers, for use as BTree keys. Keys are compared
+// lexicographically, byte by byte, so an encoding only sorts like the integers it encodes
+// if its most significant byte comes first. Unsigned integers are encoded big-endian.
+// Signed integers are encoded big-endian with the sign bit flipped, so that negative
+// numbers sort before positive ones.
+
+const I32_SIGN_BIT: u32 = 1 << 31;
+const I64_SIGN_BIT: u64 = 1 << 63;
+
+///
+/// Encodes a u32 so that the encodings sort like the integers.
+///
+pub fn encode_u32(n: u32) -> [u8; 4] {
+    n.to_be_bytes()
+}
+
+///
+/// Decodes a u32 encoded by `encode_u32`.
+///
+pub fn decode_u32(bytes: [u8; 4]) -> u32 {
+    u32::from_be_bytes(bytes)
+}
+
+///
+/// Encodes a u64 so that the encodings sort like the integers.
+///
+pub fn encode_u64(n: u64) -> [u8; 8] {
+    n.to_be_bytes()
+}
+
+///
+/// Decodes a u64 encoded by `encode_u64`.
+///
+pub fn decode_u64(bytes: [u8; 8]) -> u64 {
+    u64::from_be_bytes(bytes)
+}
+
+///
+/// Encodes an i32 so that the encodings sort like the integers.
+///
+pub fn encode_i32(n: i32) -> [u8; 4] {
+    encode_u32(n as u32 ^ I32_SIGN_BIT)
+}
+
+///
+/// Decodes an i32 encoded by `encode_i32`.
+///
+pub fn decode_i32(bytes: [u8; 4]) -> i32 {
+    (decode_u32(bytes) ^ I32_SIGN_BIT) as i32
+}
+
+///
+/// Encodes an i64 so that the encodings sort like the integers.
+///
+pub fn encode_i64(n: i64) -> [u8; 8] {
+    encode_u64(n as u64 ^ I64_SIGN_BIT)
+}
+
+///
+/// Decodes an i64 encoded by `encode_i64`.
+///
+pub fn decode_i64(bytes: [u8; 8]) -> i64 {
+    (decode_u64(bytes) ^ I64_SIGN_BIT) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::btree_kv::helpers::byte_ordering::cmp_lex_bytes;
+
+    ///
+    /// Xorshift generator, so the test cases are random but reproducible.
+    ///
+    struct Xorshift(u64);
+
+    impl Xorshift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    ///
+    /// Random values, plus the edge cases that a uniform distribution would miss.
+    ///
+    fn samples() -> Vec<u64> {
+        let mut rng = Xorshift(0x2545_f491_4f6c_dd1d);
+        let mut samples = vec![
+            0,
+            1,
+            255,
+            256,
+            u64::MAX,
+            u64::MAX - 1,
+            1 << 63,
+            (1 << 63) - 1,
+        ];
+        samples.extend([0, 1, 255, 256].map(|n: u64| (n as i64).wrapping_neg() as u64));
+        // Small values too, since most random u64s are huge.
+        samples.extend((0..500).map(|_| rng.next()));
+        samples.extend((0..500).map(|_| rng.next() % 1000));
+        samples
+    }
+
+    #[test]
+    fn test_unsigned_order_is_preserved() {
+        let samples = samples();
+        for &a in &samples {
+            for &b in &samples {
+                assert_eq!(cmp_lex_bytes(&encode_u64(a), &encode_u64(b)), a.cmp(&b));
+                let (a, b) = (a as u32, b as u32);
+                assert_eq!(cmp_lex_bytes(&encode_u32(a), &encode_u32(b)), a.cmp(&b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_signed_order_is_preserved() {
+        let samples = samples();
+        for &a in &samples {
+            for &b in &samples {
+                let (a, b) = (a as i64, b as i64);
+                assert_eq!(cmp_lex_bytes(&encode_i64(a), &encode_i64(b)), a.cmp(&b));
+                let (a, b) = (a as i32, b as i32);
+                assert_eq!(cmp_lex_bytes(&encode_i32(a), &encode_i32(b)), a.cmp(&b));
+            }
+        }
+        assert!(encode_i64(-1) < encode_i64(0));
+        assert!(encode_i64(i64::MIN) < encode_i64(i64::MAX));
+    }
+
+    #[test]
+    fn test_decode_inverts_encode() {
+        for n in samples() {
+            assert_eq!(decode_u64(encode_u64(n)), n);
+            assert_eq!(decode_u32(encode_u32(n as u32)), n as u32);
+            assert_eq!(decode_i64(encode_i64(n as i64)), n as i64);
+            assert_eq!(decode_i32(encode_i32(n as i32)), n as i32);
+        }
+    }
+}
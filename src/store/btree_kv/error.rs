@@ -1,5 +1,233 @@
-#[derive(Eq, PartialEq, Debug)]
+use crate::store::btree_kv::commons::PageId;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+
+#[derive(Debug)]
 pub enum RustyKVError {
     InsufficientSpace,
     ItemNotFound,
+    AlreadyOpen,
+    BufferPoolExhausted,
+    ChecksumMismatch,
+    InvalidBufferPoolSize,
+    RowTooLarge,
+    KeysOutOfOrder,
+    ReadOnly,
+    ComparatorMismatch,
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    // The error that failed an all-or-nothing operation, and the one that failed undoing
+    // it, leaving it partly applied.
+    RollbackFailed(Error, Error),
+    Io(Error),
+}
+
+impl fmt::Display for RustyKVError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RustyKVError::InsufficientSpace => write!(f, "insufficient space in page"),
+            RustyKVError::ItemNotFound => write!(f, "item not found"),
+            RustyKVError::AlreadyOpen => {
+                write!(
+                    f,
+                    "data file is already open for writing by another process"
+                )
+            }
+            RustyKVError::BufferPoolExhausted => {
+                write!(f, "every frame in the buffer pool is pinned")
+            }
+            RustyKVError::ChecksumMismatch => {
+                write!(f, "page checksum mismatch, the page is corrupt")
+            }
+            RustyKVError::InvalidBufferPoolSize => {
+                write!(
+                    f,
+                    "buffer pool size must be a non-zero multiple of the page size"
+                )
+            }
+            RustyKVError::RowTooLarge => {
+                write!(f, "row is too large to fit in an empty page")
+            }
+            RustyKVError::KeysOutOfOrder => {
+                write!(f, "keys are not in strictly increasing order")
+            }
+            RustyKVError::ReadOnly => write!(f, "data file is open read-only"),
+            RustyKVError::ComparatorMismatch => {
+                write!(f, "data file is ordered by a different comparator")
+            }
+            RustyKVError::InvalidMagic => write!(f, "file is not a rusty-kv data file"),
+            RustyKVError::UnsupportedVersion(version) => {
+                write!(f, "unsupported data file format version {}", version)
+            }
+            RustyKVError::RollbackFailed(error, rollback_error) => {
+                write!(
+                    f,
+                    "{}, and undoing the changes made so far failed: {}",
+                    error, rollback_error
+                )
+            }
+            RustyKVError::Io(error) => write!(f, "I/O error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for RustyKVError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RustyKVError::Io(error) => Some(error),
+            RustyKVError::RollbackFailed(_, rollback_error) => Some(rollback_error),
+            _ => None,
+        }
+    }
+}
+
+///
+/// A problem found by `BTree::check_integrity`, with the page it was found in.
+///
+#[derive(Debug, PartialEq, Eq)]
+pub enum IntegrityError {
+    ///
+    /// The page couldn't be read, e.g. because its checksum doesn't match.
+    ///
+    Unreadable(PageId, RustyKVError),
+    ///
+    /// The page's layout is invalid, e.g. a slot points outside the page body, so its rows
+    /// can't be read.
+    ///
+    MalformedPage(PageId),
+    ///
+    /// The keys of the page aren't in increasing order.
+    ///
+    KeysOutOfOrder(PageId),
+    ///
+    /// A key of the page falls outside the range its parent's separators assign to it.
+    ///
+    KeyOutOfBounds(PageId),
+    ///
+    /// The page is referenced by more than one row.
+    ///
+    ReferencedTwice(PageId),
+    ///
+    /// The page is allocated, but isn't referenced by any page of the tree.
+    ///
+    OrphanedPage(PageId),
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityError::Unreadable(page_id, error) => {
+                write!(f, "page {} can't be read: {}", page_id.value(), error)
+            }
+            IntegrityError::MalformedPage(page_id) => {
+                write!(f, "page {} has an invalid layout", page_id.value())
+            }
+            IntegrityError::KeysOutOfOrder(page_id) => {
+                write!(f, "keys of page {} are out of order", page_id.value())
+            }
+            IntegrityError::KeyOutOfBounds(page_id) => {
+                write!(
+                    f,
+                    "page {} has a key outside the bounds set by its parent",
+                    page_id.value()
+                )
+            }
+            IntegrityError::ReferencedTwice(page_id) => {
+                write!(f, "page {} is referenced more than once", page_id.value())
+            }
+            IntegrityError::OrphanedPage(page_id) => {
+                write!(f, "page {} is allocated but unreachable", page_id.value())
+            }
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+///
+/// I/O errors compare by kind, since `std::io::Error` can't be compared.
+///
+impl PartialEq for RustyKVError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (RustyKVError::Io(error), RustyKVError::Io(other_error)) => {
+                error.kind() == other_error.kind()
+            }
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for RustyKVError {}
+
+///
+/// I/O errors that wrap a RustyKVError, like the ones returned by the DiskManager, are
+/// unwrapped. Any other I/O error becomes `RustyKVError::Io`.
+///
+impl From<Error> for RustyKVError {
+    fn from(error: Error) -> Self {
+        if error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<RustyKVError>())
+        {
+            return *error
+                .into_inner()
+                .unwrap()
+                .downcast::<RustyKVError>()
+                .unwrap();
+        }
+        RustyKVError::Io(error)
+    }
+}
+
+///
+/// Lets RustyKVErrors flow through APIs that return I/O errors. `RustyKVError::Io` is
+/// unwrapped, and every other error is wrapped in an I/O error of the matching kind.
+///
+impl From<RustyKVError> for Error {
+    fn from(error: RustyKVError) -> Self {
+        let kind = match error {
+            RustyKVError::Io(error) => return error,
+            RustyKVError::AlreadyOpen => ErrorKind::WouldBlock,
+            RustyKVError::ChecksumMismatch
+            | RustyKVError::InvalidMagic
+            | RustyKVError::UnsupportedVersion(_) => ErrorKind::InvalidData,
+            RustyKVError::ItemNotFound => ErrorKind::NotFound,
+            RustyKVError::ReadOnly => ErrorKind::PermissionDenied,
+            RustyKVError::InvalidBufferPoolSize
+            | RustyKVError::RowTooLarge
+            | RustyKVError::KeysOutOfOrder
+            | RustyKVError::ComparatorMismatch => ErrorKind::InvalidInput,
+            RustyKVError::InsufficientSpace
+            | RustyKVError::BufferPoolExhausted
+            | RustyKVError::RollbackFailed(..) => ErrorKind::Other,
+        };
+        Error::new(kind, error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_round_trip() {
+        let error = RustyKVError::from(Error::new(
+            ErrorKind::InvalidData,
+            RustyKVError::ChecksumMismatch,
+        ));
+        assert_eq!(error, RustyKVError::ChecksumMismatch);
+
+        let error = Error::from(error);
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<RustyKVError>(),
+            Some(&RustyKVError::ChecksumMismatch)
+        );
+
+        let error = RustyKVError::from(Error::from(ErrorKind::NotFound));
+        assert_eq!(error, RustyKVError::Io(Error::from(ErrorKind::NotFound)));
+        assert_ne!(error, RustyKVError::Io(Error::from(ErrorKind::Other)));
+        assert_eq!(Error::from(error).kind(), ErrorKind::NotFound);
+    }
 }
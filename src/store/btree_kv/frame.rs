@@ -1,42 +1,71 @@
 use crate::store::btree_kv::commons::{PAGE_SIZE, PageId};
-use std::ops::Deref;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 ///
 /// Frame should only contain the data since we'd like to
 /// allocate the entire capacity of the buffer pool for
 /// storing the data alone.
 ///
-#[derive(Clone)]
+/// The data is guarded by a read/write latch, which is shared with the PageLatches handed
+/// out for the frame.
+///
 pub struct Frame {
-    pub data: Arc<[u8; PAGE_SIZE]>,
+    pub data: Arc<RwLock<[u8; PAGE_SIZE]>>,
+    // Set when the page is written through a PageLatch, which can't reach the frame's
+    // metadata.
+    pub(crate) latch_dirty: Arc<AtomicBool>,
+}
+
+impl Frame {
+    ///
+    /// Replaces the data in the frame, dropping the latch of the previous page.
+    ///
+    pub(crate) fn reset(&mut self, data: [u8; PAGE_SIZE]) {
+        debug_assert!(!self.is_latched());
+        *self = Frame {
+            data: Arc::new(RwLock::new(data)),
+            latch_dirty: Arc::new(AtomicBool::new(false)),
+        };
+    }
+
+    ///
+    /// Checks if a PageLatch for the frame is still held.
+    ///
+    pub(crate) fn is_latched(&self) -> bool {
+        Arc::strong_count(&self.data) > 1
+    }
+
+    ///
+    /// Clears the flag set by writes through a PageLatch.
+    ///
+    /// # Returns
+    /// * `true` if the page was written through a PageLatch since the last call.
+    ///
+    pub(crate) fn take_latch_dirty(&self) -> bool {
+        self.latch_dirty.swap(false, Ordering::AcqRel)
+    }
 }
 
 impl Default for Frame {
     fn default() -> Self {
         Frame {
-            data: Arc::new([0u8; PAGE_SIZE]),
+            data: Arc::new(RwLock::new([0u8; PAGE_SIZE])),
+            latch_dirty: Arc::new(AtomicBool::new(false)),
         }
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct FrameMetadata {
     pub(crate) page_id: Option<PageId>,
     pub(crate) is_dirty: bool,
-}
-
-impl Default for FrameMetadata {
-    fn default() -> Self {
-        FrameMetadata {
-            page_id: None,
-            is_dirty: false,
-        }
-    }
+    pub(crate) pin_count: usize,
 }
 
 ///
-/// Wrapper for a Frame.
+/// Wrapper for a Frame. The frame is pinned for as long as the handler lives, and unpinned
+/// when it's dropped.
 ///
 pub struct FrameHandler<'a> {
     frame: &'a mut Frame,
@@ -45,9 +74,10 @@ pub struct FrameHandler<'a> {
 
 impl<'a> FrameHandler<'a> {
     ///
-    /// Creates a new instance of FrameHandler.
+    /// Creates a new instance of FrameHandler, pinning the frame.
     ///
     pub(crate) fn new(frame: &'a mut Frame, frame_metadata: &'a mut FrameMetadata) -> Self {
+        frame_metadata.pin_count += 1;
         FrameHandler {
             frame,
             frame_metadata,
@@ -59,29 +89,101 @@ impl<'a> FrameHandler<'a> {
     /// # Returns
     /// * `true` if frame is dirty, `false` otherwise.
     ///
-    fn is_dirty(&self) -> bool {
+    pub fn is_dirty(&self) -> bool {
         self.frame_metadata.is_dirty
     }
 
     ///
-    /// Fetches data from the frame.
+    /// Fetches data from the frame, taking its latch in shared mode.
     /// # Returns
-    /// * `[u8; PAGE_SIZE]` containing the frame data.
+    /// * Guard over the `[u8; PAGE_SIZE]` containing the frame data.
     ///
-    fn get_data(&self) -> &[u8; PAGE_SIZE] {
-        &self.frame.data.deref()
+    pub fn page(&self) -> RwLockReadGuard<'_, [u8; PAGE_SIZE]> {
+        self.frame.data.read().unwrap()
     }
 
     ///
-    /// Updates the data in the frame.
+    /// Gives mutable access to the data in the frame and marks the frame dirty, so that
+    /// it is written back to disk when it's evicted. The frame's latch is held in
+    /// exclusive mode while `f` runs.
     ///
     /// # Arguments
-    /// * `data`: New data to be updated into the buffer pool.
+    /// * `f`: Closure that modifies the page data in place.
     ///
-    fn set_data(&mut self, data: [u8; PAGE_SIZE]) {
-        // TODO: Ensure there aren't any other references to this data.
-        self.frame.data = Arc::from(data);
+    pub fn with_page_mut<R>(&mut self, f: impl FnOnce(&mut [u8; PAGE_SIZE]) -> R) -> R {
+        let result = f(&mut self.frame.data.write().unwrap());
         self.frame_metadata.is_dirty = true;
+        result
+    }
+}
+
+impl Drop for FrameHandler<'_> {
+    ///
+    /// Releases the pin taken by `new`.
+    ///
+    fn drop(&mut self) {
+        debug_assert!(
+            self.frame_metadata.pin_count > 0,
+            "Frame was unpinned while a FrameHandler was held"
+        );
+        self.frame_metadata.pin_count -= 1;
+    }
+}
+
+///
+/// A latch on a page in the buffer pool, that can be sent to other threads. The page
+/// can't be evicted while any latch on it is held.
+///
+/// `read` takes the latch in shared mode and `write` takes it in exclusive mode, so any
+/// number of threads can read the page at once, and a writer has it to itself.
+///
+#[derive(Clone)]
+pub struct PageLatch {
+    page_id: PageId,
+    data: Arc<RwLock<[u8; PAGE_SIZE]>>,
+    latch_dirty: Arc<AtomicBool>,
+}
+
+impl PageLatch {
+    ///
+    /// Creates a latch on the page held in a frame.
+    ///
+    pub(crate) fn new(page_id: PageId, frame: &Frame) -> Self {
+        PageLatch {
+            page_id,
+            data: Arc::clone(&frame.data),
+            latch_dirty: Arc::clone(&frame.latch_dirty),
+        }
+    }
+
+    ///
+    /// Returns the Page ID of the latched page.
+    ///
+    pub fn page_id(&self) -> PageId {
+        self.page_id
+    }
+
+    ///
+    /// Takes the latch in shared mode, blocking while it is held in exclusive mode.
+    ///
+    /// # Returns
+    /// * Guard over the page data.
+    ///
+    pub fn read(&self) -> RwLockReadGuard<'_, [u8; PAGE_SIZE]> {
+        self.data.read().unwrap()
+    }
+
+    ///
+    /// Takes the latch in exclusive mode, blocking while it is held in any mode. The page
+    /// is marked dirty, so that it is written back to disk.
+    ///
+    /// # Returns
+    /// * Guard over the page data.
+    ///
+    pub fn write(&self) -> RwLockWriteGuard<'_, [u8; PAGE_SIZE]> {
+        let guard = self.data.write().unwrap();
+        self.latch_dirty.store(true, Ordering::Release);
+        guard
     }
 }
 
@@ -92,12 +194,12 @@ mod tests {
     fn frame_works() {
         let mut frame = Frame::default();
 
-        assert_eq!(frame.data.deref().len(), PAGE_SIZE);
+        assert_eq!(frame.data.read().unwrap().len(), PAGE_SIZE);
 
         let test_data: [u8; PAGE_SIZE] = [100u8; PAGE_SIZE];
-        frame.data = Arc::from(test_data);
+        frame.reset(test_data);
 
-        assert_eq!(*frame.data.deref(), test_data);
+        assert_eq!(*frame.data.read().unwrap(), test_data);
     }
 
     #[test]
@@ -118,9 +220,60 @@ mod tests {
         let mut frame_handler = FrameHandler::new(&mut frame, &mut frame_metadata);
 
         let new_data = [100u8; PAGE_SIZE];
-        frame_handler.set_data(new_data);
+        frame_handler.with_page_mut(|data| *data = new_data);
 
-        assert_eq!(*frame_handler.get_data(), new_data);
+        assert_eq!(*frame_handler.page(), new_data);
         assert_eq!(frame_handler.is_dirty(), true);
     }
+
+    #[test]
+    fn frame_handler_with_page_mut_marks_dirty() {
+        let mut frame = Frame::default();
+        let mut frame_metadata = FrameMetadata::default();
+
+        let mut frame_handler = FrameHandler::new(&mut frame, &mut frame_metadata);
+        assert_eq!(frame_handler.is_dirty(), false);
+
+        frame_handler.with_page_mut(|page| page[..3].copy_from_slice(&[1, 2, 3]));
+        assert_eq!(frame_handler.page()[..3], [1, 2, 3]);
+        assert_eq!(frame_handler.is_dirty(), true);
+    }
+
+    #[test]
+    fn frame_handler_pins_until_dropped() {
+        let mut frame = Frame::default();
+        let mut frame_metadata = FrameMetadata::default();
+
+        {
+            let mut frame_handler = FrameHandler::new(&mut frame, &mut frame_metadata);
+            assert_eq!(frame_handler.frame_metadata.pin_count, 1);
+            frame_handler.with_page_mut(|page| page[0] = 1);
+        }
+        assert_eq!(frame_metadata.pin_count, 0);
+        assert!(frame_metadata.is_dirty);
+
+        // Pins taken elsewhere are kept.
+        frame_metadata.pin_count = 2;
+        drop(FrameHandler::new(&mut frame, &mut frame_metadata));
+        assert_eq!(frame_metadata.pin_count, 2);
+    }
+
+    #[test]
+    fn page_latch_works() {
+        let frame = Frame::default();
+        assert!(!frame.is_latched());
+
+        let latch = PageLatch::new(PageId::new(1), &frame);
+        assert!(frame.is_latched());
+        assert_eq!(latch.page_id(), PageId::new(1));
+
+        latch.write()[..3].copy_from_slice(&[1, 2, 3]);
+        assert_eq!(latch.read()[..3], [1, 2, 3]);
+        assert_eq!(frame.data.read().unwrap()[..3], [1, 2, 3]);
+        assert!(frame.take_latch_dirty());
+        assert!(!frame.take_latch_dirty());
+
+        drop(latch);
+        assert!(!frame.is_latched());
+    }
 }
@@ -0,0 +1,88 @@
+// Bloom filters stored as raw bit arrays, so that they can live inside a page. A filter
+// never reports a key it was given as absent; keys it wasn't given are reported as present
+// with a probability that grows with the number of keys per bit.
+
+// Number of bits set per key.
+const NUM_HASHES: u64 = 4;
+
+///
+/// Adds a key to a filter.
+/// # Arguments:
+/// * `filter`: Bits of the filter. Must not be empty.
+/// * `key`: Key to be added.
+///
+pub fn bloom_insert(filter: &mut [u8], key: &[u8]) {
+    for bit in bit_positions(filter.len(), key) {
+        filter[bit / 8] |= 1 << (bit % 8);
+    }
+}
+
+///
+/// Checks if a key may have been added to a filter.
+/// # Arguments:
+/// * `filter`: Bits of the filter. Must not be empty.
+/// * `key`: Key to look up.
+/// # Returns:
+/// * `bool`: `false` if the key was definitely never added, `true` if it may have been.
+///
+pub fn bloom_may_contain(filter: &[u8], key: &[u8]) -> bool {
+    bit_positions(filter.len(), key).all(|bit| filter[bit / 8] & (1 << (bit % 8)) != 0)
+}
+
+///
+/// Bits of a key in a filter of `filter_size` bytes, by double hashing: the i-th bit is
+/// `h1 + i * h2`, with both hashes taken from one 64-bit hash of the key.
+///
+fn bit_positions(filter_size: usize, key: &[u8]) -> impl Iterator<Item = usize> {
+    let num_bits = filter_size as u64 * 8;
+    let hash = hash64(key);
+    let (h1, h2) = (hash & 0xFFFF_FFFF, (hash >> 32) | 1);
+    (0..NUM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+}
+
+///
+/// FNV-1a, followed by the SplitMix64 finalizer so that every bit of the hash depends on
+/// every byte of the key.
+///
+fn hash64(key: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in key {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter_has_no_false_negatives() {
+        let mut filter = [0u8; 128];
+        let key = |index: usize| format!("key{}", index).into_bytes();
+        for index in 0..100 {
+            bloom_insert(&mut filter, &key(index));
+        }
+        for index in 0..100 {
+            assert!(bloom_may_contain(&filter, &key(index)));
+        }
+
+        // About 1% of absent keys are false positives with 100 keys in 1024 bits.
+        let false_positives = (100..10_100)
+            .filter(|index| bloom_may_contain(&filter, &key(*index)))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_empty_bloom_filter_contains_nothing() {
+        let filter = [0u8; 128];
+        assert!(!bloom_may_contain(&filter, b""));
+        assert!(!bloom_may_contain(&filter, b"key"));
+    }
+}
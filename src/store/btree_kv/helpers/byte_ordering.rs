@@ -1,14 +1,44 @@
+use std::cmp::Ordering;
+
+///
+/// Compares two byte slices lexicographically, left to right. If one slice is a
+/// prefix of the other, the shorter one is less. This is the ordering used for keys.
+///
+/// Note that this is not a numeric ordering for little-endian encoded integers.
+/// Integer keys need an order-preserving (big-endian) encoding to sort correctly, see
+/// the `encoding` module.
+///
 #[inline(always)]
+pub fn cmp_lex_bytes(a: &[u8], b: &[u8]) -> Ordering {
+    a.cmp(b)
+}
+
+///
+/// Returns the length of the longest prefix shared by two byte slices.
+///
+pub fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub fn cmp_le_bytes(a: &[u8], b: &[u8]) -> std::cmp::Ordering {
-    let max_len = a.len().max(b.len());
+    #[test]
+    fn test_cmp_lex_bytes() {
+        assert_eq!(cmp_lex_bytes(b"apple", b"banana"), Ordering::Less);
+        assert_eq!(cmp_lex_bytes(b"banana", b"apple"), Ordering::Greater);
+        assert_eq!(cmp_lex_bytes(b"app", b"apple"), Ordering::Less);
+        assert_eq!(cmp_lex_bytes(b"apple", b"apple"), Ordering::Equal);
+        assert_eq!(cmp_lex_bytes(b"", b"a"), Ordering::Less);
+        assert_eq!(cmp_lex_bytes(b"b", b"abc"), Ordering::Greater);
+    }
 
-    for i in (0..max_len).rev() {
-        let av = a.get(i).copied().unwrap_or(0);
-        let bv = b.get(i).copied().unwrap_or(0);
-        if av != bv {
-            return av.cmp(&bv);
-        }
+    #[test]
+    fn test_common_prefix_len() {
+        assert_eq!(common_prefix_len(b"user:1001", b"user:1002"), 8);
+        assert_eq!(common_prefix_len(b"user", b"user:1"), 4);
+        assert_eq!(common_prefix_len(b"abc", b"xyz"), 0);
+        assert_eq!(common_prefix_len(b"", b"abc"), 0);
     }
-    std::cmp::Ordering::Equal
 }
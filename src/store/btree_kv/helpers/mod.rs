@@ -1 +1,3 @@
+pub(crate) mod bloom_filter;
 pub(crate) mod byte_ordering;
+pub(crate) mod checksum;
@@ -0,0 +1,195 @@
+use crate::store::btree_kv::commons::{PAGE_SIZE, PageId};
+use crate::store::btree_kv::disk_manager::{DiskManager, METADATA_PAGE_ID};
+use crate::store::btree_kv::error::RustyKVError;
+use crate::store::btree_kv::storage_backend::StorageBackend;
+use std::io::{Error, ErrorKind, Write};
+use std::path::Path;
+
+///
+/// Keeps pages in memory instead of a data file, for tests and ephemeral stores. Pages are
+/// allocated, read and written the same way as with a DiskManager, but nothing outlives
+/// the MemoryDiskManager.
+///
+/// Page 0 is reserved, like the DiskManager's metadata page, so that Page IDs are handed
+/// out in the same order.
+///
+pub struct MemoryDiskManager {
+    // Pages by Page ID. Pages that have never been written are None, and read as zeroes.
+    pages: Vec<Option<Box<[u8; PAGE_SIZE]>>>,
+    // Pages that have been deallocated and can be handed out again.
+    free_pages: Vec<PageId>,
+}
+
+impl MemoryDiskManager {
+    ///
+    /// Creates an empty MemoryDiskManager, with only page 0 allocated.
+    ///
+    pub fn new() -> Self {
+        MemoryDiskManager {
+            pages: vec![None],
+            free_pages: Vec::new(),
+        }
+    }
+}
+
+impl Default for MemoryDiskManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StorageBackend for MemoryDiskManager {
+    fn read_page(&mut self, id: &PageId, buffer: &mut [u8; PAGE_SIZE]) -> Result<(), Error> {
+        match self.pages.get(id.value() as usize) {
+            None => Err(Error::new(ErrorKind::NotFound, RustyKVError::ItemNotFound)),
+            Some(None) => {
+                buffer.fill(0);
+                Ok(())
+            }
+            Some(Some(page)) => {
+                buffer.copy_from_slice(page.as_ref());
+                Ok(())
+            }
+        }
+    }
+
+    fn write_page(&mut self, id: &PageId, buffer: &[u8; PAGE_SIZE]) -> Result<(), Error> {
+        let index = id.value() as usize;
+        if index >= self.pages.len() {
+            // Like the DiskManager extending the file.
+            self.pages.resize(index + 1, None);
+        }
+        self.pages[index] = Some(Box::new(DiskManager::with_checksum(buffer)));
+        Ok(())
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId, Error> {
+        if let Some(page_id) = self.free_pages.pop() {
+            return Ok(page_id);
+        }
+        self.pages.push(None);
+        Ok(PageId::new(self.pages.len() as u64 - 1))
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), Error> {
+        DiskManager::check_deallocation(id, self.pages.len(), &self.free_pages)?;
+        self.free_pages.push(id);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    ///
+    /// Page 0 is exported as an empty metadata page, so the free list isn't kept when the
+    /// snapshot is imported; deallocated pages are just never reused.
+    ///
+    fn export_snapshot(&mut self, path: &Path) -> Result<(), Error> {
+        let mut snapshot = DiskManager::new_snapshot_file(path)?;
+        let metadata_page = DiskManager::with_checksum(&DiskManager::new_metadata_page());
+        let empty_page = [0u8; PAGE_SIZE];
+        for (index, page) in self.pages.iter().enumerate() {
+            let page = match page {
+                _ if index == METADATA_PAGE_ID.value() as usize => &metadata_page,
+                Some(page) => page.as_ref(),
+                None => &empty_page,
+            };
+            snapshot.write_all(page)?;
+        }
+        DiskManager::persist_snapshot_file(snapshot, path)
+    }
+
+    fn num_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    fn free_pages(&self) -> &[PageId] {
+        &self.free_pages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::btree_kv::commons::PAGE_CHECKSUM_SIZE;
+    use crate::store::btree_kv::disk_manager::SyncMode;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_allocate_write_and_read() {
+        let mut disk_manager = MemoryDiskManager::new();
+        assert_eq!(disk_manager.num_pages(), 1);
+
+        let first = disk_manager.allocate_page().unwrap();
+        let second = disk_manager.allocate_page().unwrap();
+        assert_eq!(first, PageId::new(1));
+        assert_eq!(second, PageId::new(2));
+        assert_eq!(disk_manager.num_pages(), 3);
+
+        // Allocated pages read as zeroes until they're written.
+        let mut data_read = [1u8; PAGE_SIZE];
+        disk_manager.read_page(&first, &mut data_read).unwrap();
+        assert_eq!(data_read, [0u8; PAGE_SIZE]);
+
+        let page = [5u8; PAGE_SIZE];
+        disk_manager.write_page(&second, &page).unwrap();
+        disk_manager.read_page(&second, &mut data_read).unwrap();
+        assert_eq!(
+            &page[PAGE_CHECKSUM_SIZE..],
+            &data_read[PAGE_CHECKSUM_SIZE..]
+        );
+
+        let error = disk_manager
+            .read_page(&PageId::new(3), &mut data_read)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert_eq!(RustyKVError::from(error), RustyKVError::ItemNotFound);
+    }
+
+    #[test]
+    fn test_deallocated_page_is_reused() {
+        let mut disk_manager = MemoryDiskManager::new();
+        let first = disk_manager.allocate_page().unwrap();
+        disk_manager.allocate_page().unwrap();
+
+        disk_manager.deallocate_page(first).unwrap();
+        // Like the DiskManager, invalid deallocations are rejected.
+        for page_id in [first, METADATA_PAGE_ID, PageId::new(3)] {
+            let error = disk_manager.deallocate_page(page_id).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+        assert_eq!(disk_manager.free_pages(), &[first]);
+        assert_eq!(disk_manager.allocate_page().unwrap(), first);
+        // Once the free list is exhausted, new pages are added again.
+        assert_eq!(disk_manager.allocate_page().unwrap(), PageId::new(3));
+    }
+
+    #[test]
+    fn test_snapshot_can_be_imported() {
+        let temp_dir = TempDir::new().unwrap();
+        let snapshot_file = temp_dir.path().join("snapshot.db");
+        let data_file = temp_dir.path().join("data.db");
+
+        let mut memory_disk_manager = MemoryDiskManager::new();
+        let first = memory_disk_manager.allocate_page().unwrap();
+        let second = memory_disk_manager.allocate_page().unwrap();
+        memory_disk_manager
+            .write_page(&second, &[9u8; PAGE_SIZE])
+            .unwrap();
+        memory_disk_manager.export_snapshot(&snapshot_file).unwrap();
+
+        DiskManager::import_snapshot(&snapshot_file, &data_file).unwrap();
+        let mut disk_manager = DiskManager::new(&data_file, SyncMode::Deferred).unwrap();
+        assert_eq!(disk_manager.num_pages(), 3);
+        let mut data_read = [1u8; PAGE_SIZE];
+        disk_manager.read_page(&first, &mut data_read).unwrap();
+        assert_eq!(data_read, [0u8; PAGE_SIZE]);
+        disk_manager.read_page(&second, &mut data_read).unwrap();
+        assert_eq!(
+            data_read[PAGE_CHECKSUM_SIZE..],
+            [9u8; PAGE_SIZE - PAGE_CHECKSUM_SIZE]
+        );
+    }
+}
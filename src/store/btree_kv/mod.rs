@@ -1,8 +1,16 @@
+pub mod btree;
 pub mod buffer_pool_manager;
 pub mod cache_policy_engine;
 pub mod commons;
+pub mod comparator;
 pub mod disk_manager;
-mod error;
+pub mod encoding;
+pub mod error;
 mod frame;
 mod helpers;
+pub mod memory_disk_manager;
 mod page;
+pub mod storage_backend;
+pub mod typed_store;
+
+pub use page::{PageStats, SaveOutcome};
@@ -0,0 +1,149 @@
+use crate::store::btree_kv::commons::{PAGE_SIZE, PageId};
+use std::io::Error;
+use std::path::Path;
+
+///
+/// Stores the pages cached by the buffer pool.
+///
+/// `DiskManager` stores them in a data file. `MemoryDiskManager` keeps them in memory, for
+/// tests and ephemeral stores. Page 0 is reserved by both, so allocated Page IDs start at 1.
+///
+pub trait StorageBackend {
+    ///
+    /// Fetches a page and populates the buffer.
+    ///
+    /// # Arguments
+    /// * `id`: Page ID which needs to be fetched.
+    /// * `buffer`: Buffer that needs to be populated.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the page was read. An allocated page that has never been written reads
+    ///   as all zeroes.
+    /// * `Err(std::io::Error)` if the page couldn't be read. If the page hasn't been
+    ///   allocated, the error is of kind `NotFound` and wraps `RustyKVError::ItemNotFound`.
+    ///
+    fn read_page(&mut self, id: &PageId, buffer: &mut [u8; PAGE_SIZE]) -> Result<(), Error>;
+
+    ///
+    /// Fetches the first `buffer.len()` bytes of a page, e.g. to check its header without
+    /// reading the rest of it. The checksum covers the whole page, so the bytes aren't
+    /// verified.
+    ///
+    /// # Arguments
+    /// * `id`: Page ID which needs to be fetched.
+    /// * `buffer`: Buffer that needs to be populated. At most PAGE_SIZE bytes long.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the bytes were read.
+    /// * `Err(std::io::Error)` if the page couldn't be read, as for `read_page`.
+    ///
+    /// # Impl Note
+    /// The default implementation reads the whole page with `read_page`.
+    ///
+    fn read_page_head(&mut self, id: &PageId, buffer: &mut [u8]) -> Result<(), Error> {
+        let mut page = [0u8; PAGE_SIZE];
+        self.read_page(id, &mut page)?;
+        buffer.copy_from_slice(&page[..buffer.len()]);
+        Ok(())
+    }
+
+    ///
+    /// Writes a page. The first PAGE_CHECKSUM_SIZE bytes of the buffer are ignored; a
+    /// checksum of the rest of the page is stored in their place.
+    ///
+    /// # Arguments
+    /// * `id`: Page ID of the page.
+    /// * `buffer`: Data that needs to be written.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the page was written.
+    /// * `Err(std::io::Error)` if the page couldn't be written.
+    ///
+    fn write_page(&mut self, id: &PageId, buffer: &[u8; PAGE_SIZE]) -> Result<(), Error>;
+
+    ///
+    /// Allocates a page, reusing a deallocated one if there is any.
+    ///
+    /// # Returns
+    /// * `Ok(PageId)`: Page ID of the allocated page.
+    /// * `Err(std::io::Error)` if the page couldn't be allocated.
+    ///
+    fn allocate_page(&mut self) -> Result<PageId, Error>;
+
+    ///
+    /// Deallocates a page so that it can be handed out again by `allocate_page`.
+    ///
+    /// # Arguments
+    /// * `id`: Page ID of the page to be deallocated.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the page was deallocated.
+    /// * `Err(std::io::Error)` if the page couldn't be deallocated.
+    ///
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), Error>;
+
+    ///
+    /// Makes every written page durable, if the backend supports it.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the pages were synced.
+    /// * `Err(std::io::Error)` if an error occurred while syncing.
+    ///
+    fn sync(&mut self) -> Result<(), Error>;
+
+    ///
+    /// Writes every page to `path` in the data file format, so that it can be restored with
+    /// `DiskManager::import_snapshot`.
+    ///
+    /// # Arguments
+    /// * `path`: Path the snapshot is written to. An existing file is replaced.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the snapshot was written.
+    /// * `Err(std::io::Error)` if an error occurred while writing the snapshot.
+    ///
+    fn export_snapshot(&mut self, path: &Path) -> Result<(), Error>;
+
+    ///
+    /// Returns the number of pages allocated, including page 0 and pages that have been
+    /// deallocated but not handed out again.
+    ///
+    fn num_pages(&self) -> usize;
+
+    ///
+    /// Returns the Page IDs of the pages that have been deallocated but not handed out
+    /// again.
+    ///
+    fn free_pages(&self) -> &[PageId];
+
+    ///
+    /// Returns the name of the comparator the keys are ordered by, as recorded by
+    /// `set_comparator_name`.
+    ///
+    /// # Returns
+    /// * `Some(name)` if a name was recorded, `None` otherwise.
+    ///
+    /// # Impl Note
+    /// The default implementation doesn't record names, and always returns `None`.
+    ///
+    fn get_comparator_name(&self) -> Option<&str> {
+        None
+    }
+
+    ///
+    /// Records the name of the comparator the keys are ordered by.
+    ///
+    /// # Arguments
+    /// * `name`: Name of the comparator.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the name was recorded.
+    /// * `Err(std::io::Error)` if the name couldn't be recorded.
+    ///
+    /// # Impl Note
+    /// The default implementation discards the name.
+    ///
+    fn set_comparator_name(&mut self, _name: &str) -> Result<(), Error> {
+        Ok(())
+    }
+}
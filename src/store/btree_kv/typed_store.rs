@@ -0,0 +1,261 @@
+use crate::store::btree_kv::SaveOutcome;
+use crate::store::btree_kv::btree::BTree;
+use crate::store::btree_kv::encoding::{decode_i64, decode_u64, encode_i64, encode_u64};
+use std::io::{Error, ErrorKind};
+
+type Encoder<T> = Box<dyn Fn(&T) -> Vec<u8>>;
+type Decoder<T> = Box<dyn Fn(&[u8]) -> Result<T, Error>>;
+
+///
+/// Converts values of type `T` to bytes and back.
+///
+/// Codecs used for keys must preserve order: encoded keys must compare lexicographically
+/// the same way the keys themselves compare, so that the BTree's sorted scans follow key
+/// order. Big-endian unsigned integers do, little-endian ones don't.
+///
+pub struct Codec<T> {
+    encode: Encoder<T>,
+    decode: Decoder<T>,
+}
+
+impl<T> Codec<T> {
+    ///
+    /// Creates a codec from an encoding function and its inverse.
+    ///
+    /// # Arguments
+    /// * `encode`: Converts a value to bytes.
+    /// * `decode`: Converts bytes produced by `encode` back to the value. Returns an error,
+    ///   usually of kind `InvalidData`, if the bytes aren't a valid encoding.
+    ///
+    pub fn new(
+        encode: impl Fn(&T) -> Vec<u8> + 'static,
+        decode: impl Fn(&[u8]) -> Result<T, Error> + 'static,
+    ) -> Self {
+        Codec {
+            encode: Box::new(encode),
+            decode: Box::new(decode),
+        }
+    }
+
+    fn encode(&self, value: &T) -> Vec<u8> {
+        (self.encode)(value)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<T, Error> {
+        (self.decode)(bytes)
+    }
+
+    ///
+    /// Checks that an encoded integer has the size of the integer type.
+    ///
+    fn fixed_size<const N: usize>(bytes: &[u8]) -> Result<[u8; N], Error> {
+        bytes
+            .try_into()
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))
+    }
+}
+
+impl Codec<String> {
+    ///
+    /// UTF-8 codec for strings. Byte order is the same as string order.
+    ///
+    pub fn string() -> Self {
+        Codec::new(
+            |value: &String| value.as_bytes().to_vec(),
+            |bytes| {
+                String::from_utf8(bytes.to_vec())
+                    .map_err(|error| Error::new(ErrorKind::InvalidData, error))
+            },
+        )
+    }
+}
+
+impl Codec<u64> {
+    ///
+    /// Order-preserving codec for u64s, see `encoding::encode_u64`.
+    ///
+    pub fn u64() -> Self {
+        Codec::new(
+            |value: &u64| encode_u64(*value).to_vec(),
+            |bytes| Ok(decode_u64(Self::fixed_size(bytes)?)),
+        )
+    }
+}
+
+impl Codec<i64> {
+    ///
+    /// Order-preserving codec for i64s, see `encoding::encode_i64`.
+    ///
+    pub fn i64() -> Self {
+        Codec::new(
+            |value: &i64| encode_i64(*value).to_vec(),
+            |bytes| Ok(decode_i64(Self::fixed_size(bytes)?)),
+        )
+    }
+}
+
+///
+/// A BTree of typed keys and values. Keys and values are converted to bytes with a codec
+/// on the way in, and back on the way out.
+///
+pub struct TypedStore<K, V> {
+    btree: BTree,
+    key_codec: Codec<K>,
+    value_codec: Codec<V>,
+}
+
+impl<K, V> TypedStore<K, V> {
+    ///
+    /// Wraps a BTree.
+    ///
+    /// # Arguments
+    /// * `btree`: BTree the rows are stored in.
+    /// * `key_codec`: Codec for the keys. It must preserve order, see `Codec`.
+    /// * `value_codec`: Codec for the values.
+    ///
+    pub fn new(btree: BTree, key_codec: Codec<K>, value_codec: Codec<V>) -> Self {
+        TypedStore {
+            btree,
+            key_codec,
+            value_codec,
+        }
+    }
+
+    ///
+    /// Saves a key value. If the key already exists, its value is updated.
+    ///
+    /// # Returns
+    /// * `Ok(SaveOutcome)`: Whether the key was inserted or its value updated.
+    /// * `Err(std::io::Error)` if the BTree couldn't save the row.
+    ///
+    pub fn save(&mut self, key: &K, value: &V) -> Result<SaveOutcome, Error> {
+        self.btree
+            .save(&self.key_codec.encode(key), &self.value_codec.encode(value))
+    }
+
+    ///
+    /// Fetches the value of a key.
+    ///
+    /// # Returns
+    /// * `Ok(Some(V))` if the key exists, `Ok(None)` otherwise.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched, or the value couldn't be
+    ///   decoded.
+    ///
+    pub fn get(&mut self, key: &K) -> Result<Option<V>, Error> {
+        match self.btree.get(&self.key_codec.encode(key))? {
+            None => Ok(None),
+            Some(value) => self.value_codec.decode(&value).map(Some),
+        }
+    }
+
+    ///
+    /// Fetches every row whose key falls within `[start, end)`.
+    ///
+    /// # Returns
+    /// * `Ok(rows)`: The keys in the range and their values, in key order.
+    /// * `Err(std::io::Error)` if a page couldn't be fetched, or a row couldn't be decoded.
+    ///
+    pub fn range(&mut self, start: &K, end: &K) -> Result<Vec<(K, V)>, Error> {
+        self.btree
+            .range(&self.key_codec.encode(start), &self.key_codec.encode(end))?
+            .map(|(key, value)| {
+                Ok((
+                    self.key_codec.decode(&key)?,
+                    self.value_codec.decode(&value)?,
+                ))
+            })
+            .collect()
+    }
+
+    ///
+    /// Returns the underlying BTree.
+    ///
+    pub fn into_inner(self) -> BTree {
+        self.btree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::btree_kv::buffer_pool_manager::BufferManager;
+    use crate::store::btree_kv::commons::PAGE_SIZE;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_typed_save_get_and_range() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_typed_store");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let btree = BTree::new(bpm).unwrap();
+        let mut store = TypedStore::new(btree, Codec::u64(), Codec::string());
+
+        // 256 and 1 would be out of order if the keys were little-endian.
+        let keys = [1u64, 256, 2, 1 << 40, 255, 65536, 3000];
+        for key in keys {
+            store.save(&key, &format!("value{}", key)).unwrap();
+        }
+        assert_eq!(store.get(&256).unwrap(), Some("value256".to_string()));
+        assert_eq!(store.get(&7).unwrap(), None);
+
+        let rows = store.range(&2, &70000).unwrap();
+        assert_eq!(
+            rows,
+            [2, 255, 256, 3000, 65536]
+                .map(|key: u64| (key, format!("value{}", key)))
+                .to_vec()
+        );
+        let keys: Vec<u64> = store
+            .range(&0, &u64::MAX)
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![1, 2, 255, 256, 3000, 65536, 1 << 40]);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_typed_signed_keys() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_typed_store_signed");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let btree = BTree::new(bpm).unwrap();
+        let mut store = TypedStore::new(btree, Codec::i64(), Codec::string());
+        for key in [5i64, -1, i64::MIN, 0, -300, i64::MAX] {
+            store.save(&key, &key.to_string()).unwrap();
+        }
+
+        let keys: Vec<i64> = store
+            .range(&-300, &6)
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![-300, -1, 0, 5]);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_typed_get_reports_undecodable_values() {
+        let temp_dir = env::temp_dir().join("rusty_kv_test_typed_store_invalid");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let test_file = temp_dir.join("test.db");
+
+        let bpm = BufferManager::new_with_path(4 * PAGE_SIZE, &test_file).unwrap();
+        let mut btree = BTree::new(bpm).unwrap();
+        btree.save(&1u64.to_be_bytes(), &[0xff, 0xfe]).unwrap();
+        let mut store = TypedStore::new(btree, Codec::u64(), Codec::string());
+
+        assert_eq!(store.get(&1).err().unwrap().kind(), ErrorKind::InvalidData);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+}
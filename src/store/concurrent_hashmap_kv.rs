@@ -0,0 +1,301 @@
+use super::{MapRustyKV, RustyKV};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+///
+/// A thread-safe in-memory key-value store. It wraps a MapRustyKV in a read-write lock, so
+/// that it can be shared across threads and written to through a shared reference.
+///
+/// Reads take the lock in shared mode and writes take it in exclusive mode. The lock is
+/// only held for the duration of each call, so values are returned as clones.
+///
+/// The map is shared with the snapshots taken of it, copy-on-write: the first write after
+/// a snapshot copies the map, and later writes update the copy in place.
+///
+pub struct ConcurrentMapRustyKV<T> {
+    inner: RwLock<Arc<MapRustyKV<T>>>,
+}
+
+impl<T> ConcurrentMapRustyKV<T> {
+    ///
+    /// Creates a new, empty instance of the key-value store.
+    ///
+    /// # Examples
+    /// let kv_store: ConcurrentMapRustyKV<String> = ConcurrentMapRustyKV::new();
+    ///
+    pub fn new() -> Self {
+        ConcurrentMapRustyKV {
+            inner: RwLock::new(Arc::new(MapRustyKV::new())),
+        }
+    }
+
+    ///
+    /// Removes every key-value pair from the store.
+    ///
+    pub fn clear(&self) {
+        // Snapshots keep the old map, so there's no need to copy it first.
+        *self.lock_exclusive() = Arc::new(MapRustyKV::new());
+    }
+
+    ///
+    /// Checks if a key exists in the store.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key to be checked.
+    ///
+    /// # Returns
+    /// * `true` if the key exists, `false` otherwise.
+    ///
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.read().contains_key(key)
+    }
+
+    ///
+    /// Returns the number of key-value pairs in the store.
+    ///
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    ///
+    /// Returns `true` if the store contains no key-value pairs.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    ///
+    /// Captures a point-in-time view of the store. Reads of the snapshot don't take the
+    /// lock, and don't see writes made after it was taken. Taking a snapshot doesn't copy
+    /// the map, but the next write to the store does.
+    ///
+    /// Entries saved with a time-to-live still expire in the snapshot once their
+    /// time-to-live has passed.
+    ///
+    /// # Examples
+    /// let kv_store: ConcurrentMapRustyKV<u32> = ConcurrentMapRustyKV::new();
+    /// kv_store.save("hits", 1);
+    /// let snapshot = kv_store.snapshot();
+    /// kv_store.save("hits", 2);
+    /// assert_eq!(snapshot.get("hits"), Some(&1));
+    ///
+    pub fn snapshot(&self) -> Snapshot<T> {
+        Snapshot {
+            map: Arc::clone(&self.read()),
+        }
+    }
+
+    ///
+    /// Takes the lock in shared mode. A panic while the lock was held can't leave the map
+    /// half-updated, so a poisoned lock is still safe to use.
+    ///
+    fn read(&self) -> RwLockReadGuard<'_, Arc<MapRustyKV<T>>> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    ///
+    /// Takes the lock in exclusive mode. See `read` for how a poisoned lock is handled.
+    ///
+    fn lock_exclusive(&self) -> RwLockWriteGuard<'_, Arc<MapRustyKV<T>>> {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Clone> ConcurrentMapRustyKV<T> {
+    ///
+    /// Saves a key-value pair to the store. If the key already exists, its value is updated.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key.
+    /// * `value` - The value to be associated with the key.
+    ///
+    /// # Examples
+    /// let kv_store: ConcurrentMapRustyKV<String> = ConcurrentMapRustyKV::new();
+    /// kv_store.save("key1", "value1".to_string());
+    ///
+    pub fn save(&self, key: &str, value: T) {
+        self.write(|map| map.save(key, value));
+    }
+
+    ///
+    /// Deletes a key-value pair from the store.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key to be deleted.
+    ///
+    /// # Returns
+    /// * `true` if the key was found and deleted, `false` otherwise.
+    ///
+    pub fn delete(&self, key: &str) -> bool {
+        self.write(|map| map.delete(key))
+    }
+
+    ///
+    /// Applies `f` to the value of a key in place, if the key exists. No other thread can
+    /// access the store while `f` runs, so read-modify-write updates aren't lost.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key.
+    /// * `f` - Modifies the value.
+    ///
+    /// # Returns
+    /// * `true` if the key existed and `f` was applied, `false` otherwise.
+    ///
+    /// # Examples
+    /// let kv_store: ConcurrentMapRustyKV<u32> = ConcurrentMapRustyKV::new();
+    /// kv_store.save("hits", 1);
+    /// assert!(kv_store.update_with("hits", |hits| *hits += 1));
+    ///
+    pub fn update_with(&self, key: &str, f: impl FnOnce(&mut T)) -> bool {
+        self.write(|map| map.update_with(key, f))
+    }
+
+    ///
+    /// Retrieves a copy of the value associated with a given key.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key to be retrieved.
+    ///
+    /// # Returns
+    /// * `Some(T)` if the key exists, `None` otherwise.
+    ///
+    /// # Examples
+    /// let kv_store: ConcurrentMapRustyKV<String> = ConcurrentMapRustyKV::new();
+    /// kv_store.save("key1", "value1".to_string());
+    /// assert_eq!(kv_store.get("key1").unwrap(), "value1");
+    ///
+    pub fn get(&self, key: &str) -> Option<T> {
+        self.read().get(key).cloned()
+    }
+
+    ///
+    /// Takes the lock in exclusive mode, and updates the map with `f`. If a snapshot still
+    /// shares the map, it's copied first.
+    ///
+    fn write<R>(&self, f: impl FnOnce(&mut MapRustyKV<T>) -> R) -> R {
+        f(Arc::make_mut(&mut self.lock_exclusive()))
+    }
+}
+
+impl<T> Default for ConcurrentMapRustyKV<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// A point-in-time, read-only view of a ConcurrentMapRustyKV, see
+/// `ConcurrentMapRustyKV::snapshot`. It can be read without locking, and outlives the store.
+///
+pub struct Snapshot<T> {
+    map: Arc<MapRustyKV<T>>,
+}
+
+impl<T> Snapshot<T> {
+    ///
+    /// Retrieves the value a key had when the snapshot was taken.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key to be retrieved.
+    ///
+    /// # Returns
+    /// * `Some(&T)` if the key existed, `None` otherwise.
+    ///
+    pub fn get(&self, key: &str) -> Option<&T> {
+        self.map.get(key)
+    }
+
+    ///
+    /// Returns an iterator over the key-value pairs in the snapshot. The order is arbitrary.
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &T)> {
+        self.map.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_create_get_and_delete() {
+        let kv_store: ConcurrentMapRustyKV<String> = ConcurrentMapRustyKV::new();
+        kv_store.save("key1", "value1".to_string());
+        assert_eq!(kv_store.get("key1").unwrap(), "value1");
+        assert!(kv_store.contains_key("key1"));
+        assert_eq!(kv_store.len(), 1);
+
+        assert!(kv_store.delete("key1"));
+        assert!(!kv_store.delete("key1"));
+        assert_eq!(kv_store.get("key1"), None);
+        assert!(kv_store.is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_is_unaffected_by_writes() {
+        let kv_store: ConcurrentMapRustyKV<String> = ConcurrentMapRustyKV::new();
+        kv_store.save("key1", "value1".to_string());
+        kv_store.save("key2", "value2".to_string());
+        let snapshot = kv_store.snapshot();
+
+        kv_store.save("key1", "updated".to_string());
+        assert!(kv_store.delete("key2"));
+        kv_store.save("key3", "value3".to_string());
+        assert_eq!(kv_store.get("key1").unwrap(), "updated");
+
+        assert_eq!(snapshot.get("key1").unwrap(), "value1");
+        assert_eq!(snapshot.get("key2").unwrap(), "value2");
+        assert_eq!(snapshot.get("key3"), None);
+        let mut rows: Vec<(&String, &String)> = snapshot.iter().collect();
+        rows.sort();
+        assert_eq!(
+            rows,
+            vec![
+                (&"key1".to_string(), &"value1".to_string()),
+                (&"key2".to_string(), &"value2".to_string())
+            ]
+        );
+
+        // A snapshot of a cleared store stays empty as the store is written to again.
+        kv_store.clear();
+        let empty = kv_store.snapshot();
+        kv_store.save("key4", "value4".to_string());
+        assert_eq!(empty.iter().count(), 0);
+        assert_eq!(snapshot.get("key1").unwrap(), "value1");
+    }
+
+    #[test]
+    fn test_concurrent_saves_and_gets() {
+        let kv_store: Arc<ConcurrentMapRustyKV<u64>> = Arc::new(ConcurrentMapRustyKV::new());
+        kv_store.save("counter", 0);
+
+        let handles: Vec<_> = (0..8)
+            .map(|thread_index| {
+                let kv_store = Arc::clone(&kv_store);
+                thread::spawn(move || {
+                    for index in 0..500 {
+                        let key = format!("key{}-{}", thread_index, index);
+                        kv_store.save(&key, index);
+                        assert_eq!(kv_store.get(&key), Some(index));
+                        assert!(kv_store.update_with("counter", |counter| *counter += 1));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(kv_store.len(), 8 * 500 + 1);
+        assert_eq!(kv_store.get("counter"), Some(8 * 500));
+        for thread_index in 0..8 {
+            for index in 0..500 {
+                assert_eq!(
+                    kv_store.get(&format!("key{}-{}", thread_index, index)),
+                    Some(index)
+                );
+            }
+        }
+    }
+}
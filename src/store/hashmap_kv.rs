@@ -1,11 +1,540 @@
 use super::RustyKV;
-use std::collections::HashMap;
+use super::heap_size::HeapSize;
+use super::write_batch::{BatchOperation, WriteBatch};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize, Serializer, de::DeserializeOwned, ser::SerializeMap};
+use std::collections::{BTreeMap, HashMap, btree_map, hash_map};
+use std::mem::size_of;
+use std::time::{Duration, Instant};
 
 ///
 /// A simple in-memory key-value store using a HashMap.
 ///
+/// Entries saved with `save_with_ttl` expire once their time-to-live has passed. Expired
+/// entries are treated as absent, and are removed when they are next written to or when
+/// `purge_expired` is called.
+///
+/// With the `serde` feature, the store serializes as a map of its unexpired entries.
+/// Expiry times aren't serialized, so entries with a time-to-live that are still live never
+/// expire once deserialized.
+///
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct MapRustyKV<T> {
     data_store: HashMap<String, T>,
+    // Expiry time of the keys saved with a time-to-live.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    expiries: HashMap<String, Instant>,
+    // The keys in `expiries`, ordered by expiry time, so that the expired ones can be found
+    // without scanning every key with a time-to-live.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    expiry_order: BTreeMap<Instant, Vec<String>>,
+}
+
+impl<T> MapRustyKV<T> {
+    ///
+    /// Returns the number of key-value pairs in the store. Expired pairs that haven't been
+    /// removed yet aren't counted, so this is O(1) plus the number of those pairs.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+    /// kv_store.save("key1", "value1".to_string());
+    /// assert_eq!(kv_store.len(), 1);
+    ///
+    pub fn len(&self) -> usize {
+        let expired: usize = self
+            .expiry_order
+            .range(..=Instant::now())
+            .map(|(_, keys)| keys.len())
+            .sum();
+        self.data_store.len() - expired
+    }
+
+    ///
+    /// Checks if the store is empty.
+    ///
+    /// # Returns
+    /// * `true` if the store has no key-value pairs, `false` otherwise.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///
+    /// Returns an iterator over the key-value pairs in the store. The order is arbitrary.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+    /// kv_store.save("key1", "value1".to_string());
+    /// for (key, value) in kv_store.iter() {
+    ///     println!("{}: {}", key, value);
+    /// }
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &T)> {
+        let now = Instant::now();
+        self.data_store
+            .iter()
+            .filter(move |(key, _)| !self.is_expired_at(key, now))
+    }
+
+    ///
+    /// Returns an iterator over the keys in the store. The order is arbitrary.
+    ///
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    ///
+    /// Returns an iterator over the values in the store. The order is arbitrary.
+    ///
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    ///
+    /// Returns a mutable reference to the value of a key, inserting the value returned by
+    /// `default` first if the key doesn't exist. The key is only looked up once.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key.
+    /// * `default` - Creates the value to be inserted if the key doesn't exist.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+    /// *kv_store.get_or_insert_with("hits", || 0) += 1;
+    /// assert_eq!(kv_store.get("hits"), Some(&1));
+    ///
+    pub fn get_or_insert_with(&mut self, key: &str, default: impl FnOnce() -> T) -> &mut T {
+        self.remove_if_expired(key);
+        self.data_store
+            .entry(String::from(key))
+            .or_insert_with(default)
+    }
+
+    ///
+    /// Removes every key-value pair from the store, returning them as an iterator. Expired
+    /// pairs are dropped rather than returned. The store is empty afterwards, even if the
+    /// iterator isn't consumed.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+    /// kv_store.save("key1", "value1".to_string());
+    /// let drained: Vec<(String, String)> = kv_store.drain().collect();
+    /// assert!(kv_store.is_empty());
+    ///
+    pub fn drain(&mut self) -> impl Iterator<Item = (String, T)> + '_ {
+        let now = Instant::now();
+        let expiries = std::mem::take(&mut self.expiries);
+        self.expiry_order.clear();
+        self.data_store
+            .drain()
+            .filter(move |(key, _)| expiries.get(key).is_none_or(|expiry| *expiry > now))
+    }
+
+    ///
+    /// Retrieves a mutable reference to the value associated with a given key, so that it
+    /// can be changed in place.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key.
+    ///
+    /// # Returns
+    /// * `Some(&mut T)` if the key exists and hasn't expired, `None` otherwise.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<Vec<u8>> = MapRustyKV::new();
+    /// kv_store.save("key1", vec![1]);
+    /// kv_store.get_mut("key1").unwrap().push(2);
+    /// assert_eq!(kv_store.get("key1").unwrap(), &vec![1, 2]);
+    ///
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut T> {
+        self.remove_if_expired(key);
+        self.data_store.get_mut(key)
+    }
+
+    ///
+    /// Applies `f` to the value of a key in place, if the key exists.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key.
+    /// * `f` - Modifies the value.
+    ///
+    /// # Returns
+    /// * `true` if the key existed and `f` was applied, `false` otherwise.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+    /// kv_store.save("hits", 1);
+    /// assert!(kv_store.update_with("hits", |hits| *hits += 1));
+    /// assert!(!kv_store.update_with("misses", |misses| *misses += 1));
+    ///
+    pub fn update_with(&mut self, key: &str, f: impl FnOnce(&mut T)) -> bool {
+        self.remove_if_expired(key);
+        match self.data_store.get_mut(key) {
+            None => false,
+            Some(value) => {
+                f(value);
+                true
+            }
+        }
+    }
+
+    ///
+    /// Merges a value into the value of a key. If the key exists, its value is replaced by
+    /// `f(&old, &value)`, keeping any time-to-live it has. Otherwise, `value` is saved.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key.
+    /// * `value` - The value to be merged in.
+    /// * `f` - Combines the existing value with `value`.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+    /// kv_store.merge("hits", 1, |old, new| old + new);
+    /// kv_store.merge("hits", 2, |old, new| old + new);
+    /// assert_eq!(kv_store.get("hits"), Some(&3));
+    ///
+    pub fn merge(&mut self, key: &str, value: T, f: impl Fn(&T, &T) -> T) {
+        self.remove_if_expired(key);
+        match self.data_store.get_mut(key) {
+            None => {
+                self.data_store.insert(String::from(key), value);
+            }
+            Some(old) => *old = f(old, &value),
+        }
+    }
+
+    ///
+    /// Saves a key-value pair that expires after `ttl`. If the key already exists, its
+    /// value and time-to-live are replaced.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key.
+    /// * `value` - The value to be associated with the key.
+    /// * `ttl` - How long the key-value pair lives for.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+    /// kv_store.save_with_ttl("session", "token".to_string(), Duration::from_secs(60));
+    /// assert!(kv_store.contains_key("session"));
+    ///
+    pub fn save_with_ttl(&mut self, key: &str, value: T, ttl: Duration) {
+        self.data_store.insert(String::from(key), value);
+        self.set_expiry(key, Instant::now() + ttl);
+    }
+
+    ///
+    /// Removes every expired key-value pair from the store. Only the expired pairs are
+    /// visited.
+    ///
+    /// # Returns
+    /// * The number of key-value pairs removed.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+    /// kv_store.save_with_ttl("session", "token".to_string(), Duration::ZERO);
+    /// assert_eq!(kv_store.purge_expired(), 1);
+    ///
+    pub fn purge_expired(&mut self) -> usize {
+        let now = Instant::now();
+        let mut purged = 0;
+        while let Some(entry) = self.expiry_order.first_entry() {
+            if *entry.key() > now {
+                break;
+            }
+            for key in entry.remove() {
+                self.expiries.remove(&key);
+                self.data_store.remove(&key);
+                purged += 1;
+            }
+        }
+        purged
+    }
+
+    ///
+    /// Applies every operation in a batch, in order. Puts behave like `save` and deletes
+    /// like `delete`. Neither can fail, since they only insert into or remove from the
+    /// in-memory maps, and running out of memory aborts the process rather than returning.
+    /// So unlike `BTree::apply_batch`, there is nothing to roll back, and the batch is
+    /// always applied in full.
+    ///
+    /// # Arguments
+    /// * `batch` - The operations to be applied.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+    /// let mut batch = WriteBatch::new();
+    /// batch.put("key1".to_string(), "value1".to_string()).delete("key2".to_string());
+    /// kv_store.apply_batch(batch);
+    ///
+    pub fn apply_batch(&mut self, batch: WriteBatch<String, T>) {
+        for operation in batch {
+            match operation {
+                BatchOperation::Put(key, value) => {
+                    self.clear_expiry(&key);
+                    self.data_store.insert(key, value);
+                }
+                BatchOperation::Delete(key) => {
+                    self.delete(&key);
+                }
+            }
+        }
+    }
+
+    ///
+    /// Checks if a key has a time-to-live that had passed by `now`.
+    ///
+    fn is_expired_at(&self, key: &str, now: Instant) -> bool {
+        self.expiries.get(key).is_some_and(|expiry| *expiry <= now)
+    }
+
+    ///
+    /// Removes a key-value pair if it has expired, so that it can be written to as if it
+    /// were absent.
+    ///
+    fn remove_if_expired(&mut self, key: &str) {
+        if self.is_expired_at(key, Instant::now()) {
+            self.clear_expiry(key);
+            self.data_store.remove(key);
+        }
+    }
+
+    ///
+    /// Sets the expiry time of a key, replacing any it had.
+    ///
+    fn set_expiry(&mut self, key: &str, expiry: Instant) {
+        self.clear_expiry(key);
+        self.expiries.insert(String::from(key), expiry);
+        self.expiry_order
+            .entry(expiry)
+            .or_default()
+            .push(String::from(key));
+    }
+
+    ///
+    /// Clears the expiry time of a key, if it has one.
+    ///
+    fn clear_expiry(&mut self, key: &str) {
+        let Some(expiry) = self.expiries.remove(key) else {
+            return;
+        };
+        if let btree_map::Entry::Occupied(mut entry) = self.expiry_order.entry(expiry) {
+            entry.get_mut().retain(|ordered_key| ordered_key != key);
+            if entry.get().is_empty() {
+                entry.remove();
+            }
+        }
+    }
+}
+
+impl<T: PartialEq> MapRustyKV<T> {
+    ///
+    /// Saves a value only if the key's current value is `expected`.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key.
+    /// * `expected` - The value the key must have. `None` if the key must be absent.
+    /// * `new` - The value to be associated with the key. Like `save`, this clears any
+    ///   time-to-live the key had.
+    ///
+    /// # Returns
+    /// * `true` if the value was swapped, `false` if the current value didn't match.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+    /// assert!(kv_store.compare_and_swap("version", None, 1));
+    /// assert!(!kv_store.compare_and_swap("version", None, 2));
+    /// assert!(kv_store.compare_and_swap("version", Some(&1), 2));
+    ///
+    pub fn compare_and_swap(&mut self, key: &str, expected: Option<&T>, new: T) -> bool {
+        self.remove_if_expired(key);
+        if self.data_store.get(key) != expected {
+            return false;
+        }
+        self.save(key, new);
+        true
+    }
+}
+
+impl MapRustyKV<i64> {
+    ///
+    /// Adds `delta` to the value of a key, treating a missing or expired key as 0, and saves
+    /// the result. An existing key keeps any time-to-live it has.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key.
+    /// * `delta` - The amount to be added. Negative to decrement.
+    ///
+    /// # Returns
+    /// * `Some(i64)` with the new value of the key.
+    /// * `None` if the new value would overflow an i64. The store is left unchanged.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<i64> = MapRustyKV::new();
+    /// assert_eq!(kv_store.incr("hits", 1), Some(1));
+    /// assert_eq!(kv_store.incr("hits", 5), Some(6));
+    ///
+    pub fn incr(&mut self, key: &str, delta: i64) -> Option<i64> {
+        self.remove_if_expired(key);
+        let value = self.get(key).copied().unwrap_or(0).checked_add(delta)?;
+        *self.get_or_insert_with(key, || 0) = value;
+        Some(value)
+    }
+
+    ///
+    /// Subtracts `delta` from the value of a key, treating a missing or expired key as 0, and
+    /// saves the result. See `incr`.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key.
+    /// * `delta` - The amount to be subtracted.
+    ///
+    /// # Returns
+    /// * `Some(i64)` with the new value of the key.
+    /// * `None` if the new value would overflow an i64. The store is left unchanged.
+    ///
+    pub fn decr(&mut self, key: &str, delta: i64) -> Option<i64> {
+        self.remove_if_expired(key);
+        let value = self.get(key).copied().unwrap_or(0).checked_sub(delta)?;
+        *self.get_or_insert_with(key, || 0) = value;
+        Some(value)
+    }
+}
+
+///
+/// An owning iterator over the key-value pairs of a MapRustyKV, in arbitrary order. Pairs
+/// that had expired when the iterator was created are skipped.
+///
+pub struct IntoIter<T> {
+    inner: hash_map::IntoIter<String, T>,
+    expiries: HashMap<String, Instant>,
+    now: Instant,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = (String, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let now = self.now;
+        let expiries = &self.expiries;
+        self.inner
+            .by_ref()
+            .find(|(key, _)| expiries.get(key).is_none_or(|expiry| *expiry > now))
+    }
+}
+
+impl<T> IntoIterator for MapRustyKV<T> {
+    type Item = (String, T);
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            inner: self.data_store.into_iter(),
+            expiries: self.expiries,
+            now: Instant::now(),
+        }
+    }
+}
+
+impl<T> FromIterator<(String, T)> for MapRustyKV<T> {
+    ///
+    /// Builds a store from key-value pairs, see `save_batch`. If a key appears more than
+    /// once, its last value is kept.
+    ///
+    /// # Examples
+    /// let kv_store: MapRustyKV<u32> = vec![("key1".to_string(), 1)].into_iter().collect();
+    ///
+    fn from_iter<I: IntoIterator<Item = (String, T)>>(iter: I) -> Self {
+        let mut kv_store = MapRustyKV::new();
+        kv_store.save_batch(iter);
+        kv_store
+    }
+}
+
+impl<T: HeapSize> MapRustyKV<T> {
+    ///
+    /// Estimates the memory used by the store: the store itself, the capacity of its maps,
+    /// and the heap memory of every key and value. Expired entries that haven't been
+    /// removed yet are counted.
+    ///
+    /// # Returns
+    /// * The estimated number of bytes.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+    /// kv_store.save("key1", "value1".to_string());
+    /// assert!(kv_store.estimated_bytes() >= "key1".len() + "value1".len());
+    ///
+    pub fn estimated_bytes(&self) -> usize {
+        // Each bucket of a HashMap holds an entry, plus a control byte.
+        let data_store_bytes = self.data_store.capacity() * (size_of::<(String, T)>() + 1)
+            + self
+                .data_store
+                .iter()
+                .map(|(key, value)| key.heap_size() + value.heap_size())
+                .sum::<usize>();
+        let expiries_bytes = self.expiries.capacity() * (size_of::<(String, Instant)>() + 1)
+            + self.expiries.keys().map(HeapSize::heap_size).sum::<usize>();
+        let expiry_order_bytes = self
+            .expiry_order
+            .values()
+            .map(|keys| {
+                size_of::<(Instant, Vec<String>)>()
+                    + keys.capacity() * size_of::<String>()
+                    + keys.iter().map(HeapSize::heap_size).sum::<usize>()
+            })
+            .sum::<usize>();
+        size_of::<Self>() + data_store_bytes + expiries_bytes + expiry_order_bytes
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize> Serialize for MapRustyKV<T> {
+    ///
+    /// Serializes the unexpired entries of the store as a map.
+    ///
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entries: Vec<(&String, &T)> = self.iter().collect();
+        let mut map = serializer.serialize_map(Some(entries.len()))?;
+        for (key, value) in entries {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize + DeserializeOwned> MapRustyKV<T> {
+    ///
+    /// Serializes the store with bincode.
+    ///
+    /// # Returns
+    /// * The serialized store.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+    /// kv_store.save("key1", "value1".to_string());
+    /// let restored = MapRustyKV::<String>::from_bytes(&kv_store.to_bytes()).unwrap();
+    /// assert_eq!(restored.get("key1").unwrap(), "value1");
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to serialize the store")
+    }
+
+    ///
+    /// Deserializes a store serialized with `to_bytes`.
+    ///
+    /// # Arguments
+    /// * `data` - The serialized store.
+    ///
+    /// # Returns
+    /// * `Ok(MapRustyKV)` if the store was deserialized.
+    /// * `Err(bincode::Error)` if `data` isn't a valid serialized store.
+    ///
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
 }
 
 ///
@@ -21,11 +550,14 @@ impl<T> RustyKV<T> for MapRustyKV<T> {
     fn new() -> Self {
         MapRustyKV {
             data_store: HashMap::new(),
+            expiries: HashMap::new(),
+            expiry_order: BTreeMap::new(),
         }
     }
 
     ///
-    /// Saves a key-value pair to the store. If the key already exists, its value is updated.
+    /// Saves a key-value pair to the store. If the key already exists, its value is updated
+    /// and any time-to-live it had is cleared.
     ///
     /// # Arguments
     /// * `key` - A string slice that holds the key.
@@ -36,9 +568,33 @@ impl<T> RustyKV<T> for MapRustyKV<T> {
     /// kv_store.save("key1", "value1".to_string());
     ///
     fn save(&mut self, key: &str, value: T) {
+        self.clear_expiry(key);
         self.data_store.insert(String::from(key), value);
     }
 
+    ///
+    /// Saves a batch of key-value pairs to the store. The keys are moved into the store
+    /// without being copied, and space is reserved up front from the batch's size hint.
+    ///
+    /// # Arguments
+    /// * `items` - The key-value pairs to be saved.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+    /// kv_store.save_batch(vec![("key1".to_string(), "value1".to_string())]);
+    /// assert_eq!(kv_store.get("key1").unwrap(), "value1");
+    ///
+    fn save_batch(&mut self, items: impl IntoIterator<Item = (String, T)>) {
+        if self.expiries.is_empty() {
+            self.data_store.extend(items);
+            return;
+        }
+        for (key, value) in items {
+            self.clear_expiry(&key);
+            self.data_store.insert(key, value);
+        }
+    }
+
     ///
     /// Deletes a key-value pair from the store.
     ///
@@ -55,9 +611,27 @@ impl<T> RustyKV<T> for MapRustyKV<T> {
     /// assert!(!kv_store.delete("key1")); // Deleting again should return false
     ///
     fn delete(&mut self, key: &str) -> bool {
+        self.remove_if_expired(key);
+        self.clear_expiry(key);
         self.data_store.remove(key).is_some()
     }
 
+    ///
+    /// Removes every key-value pair from the store. The memory allocated for the store is
+    /// kept for reuse.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+    /// kv_store.save("key1", "value1".to_string());
+    /// kv_store.clear();
+    /// assert!(kv_store.is_empty());
+    ///
+    fn clear(&mut self) {
+        self.data_store.clear();
+        self.expiries.clear();
+        self.expiry_order.clear();
+    }
+
     ///
     /// Retrieves the value associated with a given key.
     ///
@@ -65,7 +639,7 @@ impl<T> RustyKV<T> for MapRustyKV<T> {
     /// * `key` - A string slice that holds the key to be retrieved.
     ///
     /// # Returns
-    /// * `Some(&T)` if the key exists, `None` otherwise.
+    /// * `Some(&T)` if the key exists and hasn't expired, `None` otherwise.
     ///
     /// # Examples
     /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
@@ -74,13 +648,36 @@ impl<T> RustyKV<T> for MapRustyKV<T> {
     /// assert_eq!(kv_store.get("key2"), None); // Non-existent key
     ///
     fn get(&self, key: &str) -> Option<&T> {
+        if self.is_expired_at(key, Instant::now()) {
+            return None;
+        }
         self.data_store.get(key)
     }
+
+    ///
+    /// Checks if a key exists in the store.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key to be checked.
+    ///
+    /// # Returns
+    /// * `true` if the key exists and hasn't expired, `false` otherwise.
+    ///
+    /// # Examples
+    /// let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+    /// kv_store.save("key1", "value1".to_string());
+    /// assert!(kv_store.contains_key("key1"));
+    /// assert!(!kv_store.contains_key("key2"));
+    ///
+    fn contains_key(&self, key: &str) -> bool {
+        !self.is_expired_at(key, Instant::now()) && self.data_store.contains_key(key)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::thread;
 
     #[test]
     fn test_create_and_get() {
@@ -110,4 +707,495 @@ mod tests {
         let kv_store: MapRustyKV<String> = MapRustyKV::new();
         assert_eq!(kv_store.get("key1"), None);
     }
+
+    #[test]
+    fn test_len_and_is_empty() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+        assert_eq!(kv_store.len(), 0);
+        assert!(kv_store.is_empty());
+
+        kv_store.save("key1", "value1".to_string());
+        kv_store.save("key2", "value2".to_string());
+        assert_eq!(kv_store.len(), 2);
+        assert!(!kv_store.is_empty());
+
+        // Overwriting an existing key doesn't change the length.
+        kv_store.save("key1", "value3".to_string());
+        assert_eq!(kv_store.len(), 2);
+
+        assert!(kv_store.delete("key1"));
+        assert_eq!(kv_store.len(), 1);
+        assert!(!kv_store.delete("key1"));
+        assert_eq!(kv_store.len(), 1);
+    }
+
+    #[test]
+    fn test_contains_key() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+        kv_store.save("key1", "value1".to_string());
+        assert!(kv_store.contains_key("key1"));
+        assert!(!kv_store.contains_key("key2"));
+
+        kv_store.delete("key1");
+        assert!(!kv_store.contains_key("key1"));
+    }
+
+    #[test]
+    fn test_iter_keys_and_values() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+        kv_store.save("key1", "value1".to_string());
+        kv_store.save("key2", "value2".to_string());
+        kv_store.save("key3", "value3".to_string());
+
+        let mut entries: Vec<(&String, &String)> = kv_store.iter().collect();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                (&"key1".to_string(), &"value1".to_string()),
+                (&"key2".to_string(), &"value2".to_string()),
+                (&"key3".to_string(), &"value3".to_string()),
+            ]
+        );
+
+        let mut keys: Vec<&String> = kv_store.keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["key1", "key2", "key3"]);
+
+        let mut values: Vec<&String> = kv_store.values().collect();
+        values.sort();
+        assert_eq!(values, vec!["value1", "value2", "value3"]);
+    }
+
+    #[test]
+    fn test_save_batch_matches_individual_saves() {
+        let items: Vec<(String, String)> = (0..100)
+            .map(|index| (format!("key{}", index % 80), format!("value{}", index)))
+            .collect();
+
+        let mut individual: MapRustyKV<String> = MapRustyKV::new();
+        for (key, value) in items.clone() {
+            individual.save(&key, value);
+        }
+        let mut batched: MapRustyKV<String> = MapRustyKV::new();
+        batched.save("key0", "existing".to_string());
+        batched.save_batch(items);
+
+        assert_eq!(batched.len(), individual.len());
+        for (key, value) in individual.iter() {
+            assert_eq!(batched.get(key), Some(value));
+        }
+        // Repeated keys keep the last value in the batch.
+        assert_eq!(batched.get("key5").unwrap(), "value85");
+    }
+
+    #[test]
+    fn test_get_many() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+        kv_store.save("key1", "value1".to_string());
+        kv_store.save("key3", "value3".to_string());
+
+        assert_eq!(
+            kv_store.get_many(&["key3", "key2", "key1", "key3", "key4"]),
+            vec![
+                Some(&"value3".to_string()),
+                None,
+                Some(&"value1".to_string()),
+                Some(&"value3".to_string()),
+                None
+            ]
+        );
+        assert!(kv_store.get_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_get_or_and_get_or_default() {
+        let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+        kv_store.save("key1", 5);
+        kv_store.save_with_ttl("key2", 7, Duration::ZERO);
+
+        let default = 42;
+        assert_eq!(*kv_store.get_or("key1", &default), 5);
+        assert_eq!(*kv_store.get_or("missing", &default), 42);
+        // Expired keys are absent.
+        assert_eq!(*kv_store.get_or("key2", &default), 42);
+
+        assert_eq!(kv_store.get_or_default("key1"), 5);
+        assert_eq!(kv_store.get_or_default("missing"), 0);
+        assert_eq!(kv_store.get_or_default("key2"), 0);
+    }
+
+    #[test]
+    fn test_get_or_insert_with_counts() {
+        let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+        for word in ["a", "b", "a", "c", "a", "b"] {
+            *kv_store.get_or_insert_with(word, || 0) += 1;
+        }
+        assert_eq!(kv_store.get("a"), Some(&3));
+        assert_eq!(kv_store.get("b"), Some(&2));
+        assert_eq!(kv_store.get("c"), Some(&1));
+
+        // The default isn't called for existing keys.
+        assert_eq!(*kv_store.get_or_insert_with("a", || unreachable!()), 3);
+    }
+
+    #[test]
+    fn test_incr_and_decr() {
+        let mut kv_store: MapRustyKV<i64> = MapRustyKV::new();
+        // Missing keys start at 0.
+        assert_eq!(kv_store.incr("hits", 1), Some(1));
+        assert_eq!(kv_store.decr("misses", 2), Some(-2));
+
+        assert_eq!(kv_store.incr("hits", 1), Some(2));
+        assert_eq!(kv_store.incr("hits", 10), Some(12));
+        assert_eq!(kv_store.incr("hits", -20), Some(-8));
+        assert_eq!(kv_store.decr("hits", -3), Some(-5));
+        assert_eq!(kv_store.get("hits"), Some(&-5));
+
+        // Expired keys start at 0 too.
+        kv_store.save_with_ttl("session", 100, Duration::ZERO);
+        assert_eq!(kv_store.incr("session", 1), Some(1));
+    }
+
+    #[test]
+    fn test_incr_overflow_leaves_store_unchanged() {
+        let mut kv_store: MapRustyKV<i64> = MapRustyKV::new();
+        kv_store.save("hits", i64::MAX);
+        assert_eq!(kv_store.incr("hits", 1), None);
+        assert_eq!(kv_store.get("hits"), Some(&i64::MAX));
+
+        // A missing key isn't created when the first update overflows.
+        assert_eq!(kv_store.decr("misses", i64::MIN), None);
+        assert!(!kv_store.contains_key("misses"));
+        assert_eq!(kv_store.decr("misses", i64::MAX), Some(-i64::MAX));
+    }
+
+    #[test]
+    fn test_update_with() {
+        let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+        kv_store.save("counter", 10);
+
+        assert!(kv_store.update_with("counter", |counter| *counter += 1));
+        assert!(kv_store.update_with("counter", |counter| *counter *= 2));
+        assert_eq!(kv_store.get("counter"), Some(&22));
+
+        // Missing keys aren't created.
+        assert!(!kv_store.update_with("missing", |_| unreachable!()));
+        assert!(!kv_store.contains_key("missing"));
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+        kv_store.save("key1", "value1".to_string());
+        kv_store.save("key2", "value2".to_string());
+
+        kv_store.clear();
+        assert_eq!(kv_store.len(), 0);
+        assert!(kv_store.is_empty());
+        assert_eq!(kv_store.get("key1"), None);
+        assert!(!kv_store.contains_key("key2"));
+
+        // The store is still usable.
+        kv_store.save("key1", "value3".to_string());
+        assert_eq!(kv_store.get("key1").unwrap(), "value3");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut kv_store: MapRustyKV<Vec<u32>> = MapRustyKV::new();
+        for index in 0..100 {
+            kv_store.save(&format!("key{}", index), (0..index).collect());
+        }
+
+        let restored = MapRustyKV::<Vec<u32>>::from_bytes(&kv_store.to_bytes()).unwrap();
+        assert_eq!(restored.len(), kv_store.len());
+        for (key, value) in kv_store.iter() {
+            assert_eq!(restored.get(key), Some(value));
+        }
+
+        let empty: MapRustyKV<Vec<u32>> = MapRustyKV::new();
+        assert!(
+            MapRustyKV::<Vec<u32>>::from_bytes(&empty.to_bytes())
+                .unwrap()
+                .is_empty()
+        );
+        assert!(MapRustyKV::<Vec<u32>>::from_bytes(&[1, 2, 3]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_bytes_round_trip_drops_expired_entries() {
+        let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+        kv_store.save("forever", 1);
+        kv_store.save_with_ttl("expired", 2, Duration::ZERO);
+
+        let restored = MapRustyKV::<u32>::from_bytes(&kv_store.to_bytes()).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored.get("forever"), Some(&1));
+        assert_eq!(restored.get("expired"), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serializes_as_map() {
+        let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+        kv_store.save("key1", 1);
+
+        let mut map = HashMap::new();
+        map.insert("key1".to_string(), 1u32);
+        assert_eq!(kv_store.to_bytes(), bincode::serialize(&map).unwrap());
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+        kv_store.save("key1", "value1".to_string());
+        kv_store.save("key2", "value2".to_string());
+        kv_store.save_with_ttl("key3", "value3".to_string(), Duration::ZERO);
+
+        let mut drained: Vec<(String, String)> = kv_store.drain().collect();
+        drained.sort();
+        assert_eq!(
+            drained,
+            vec![
+                ("key1".to_string(), "value1".to_string()),
+                ("key2".to_string(), "value2".to_string())
+            ]
+        );
+        assert_eq!(kv_store.len(), 0);
+        assert!(kv_store.is_empty());
+
+        // The store is emptied even if the iterator isn't consumed.
+        kv_store.save("key1", "value1".to_string());
+        drop(kv_store.drain());
+        assert!(kv_store.is_empty());
+        assert_eq!(kv_store.drain().count(), 0);
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+        for index in 0..10 {
+            kv_store.save(&format!("key{}", index), index);
+        }
+        kv_store.save_with_ttl("expired", 10, Duration::ZERO);
+
+        let mut values: Vec<u32> = Vec::new();
+        for (key, value) in kv_store {
+            assert_eq!(key, format!("key{}", value));
+            values.push(value);
+        }
+        values.sort();
+        assert_eq!(values, (0..10).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let pairs = vec![
+            ("key1".to_string(), 1),
+            ("key2".to_string(), 2),
+            ("key1".to_string(), 3),
+        ];
+        let kv_store: MapRustyKV<u32> = pairs.into_iter().collect();
+        assert_eq!(kv_store.len(), 2);
+        assert_eq!(kv_store.get("key1"), Some(&3));
+        assert_eq!(kv_store.get("key2"), Some(&2));
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut kv_store: MapRustyKV<Vec<u8>> = MapRustyKV::new();
+        kv_store.save("key1", vec![1, 2]);
+        kv_store.get_mut("key1").unwrap().push(3);
+        assert_eq!(kv_store.get("key1").unwrap(), &vec![1, 2, 3]);
+
+        assert!(kv_store.get_mut("key2").is_none());
+        kv_store.save_with_ttl("key3", vec![1], Duration::ZERO);
+        assert!(kv_store.get_mut("key3").is_none());
+        assert_eq!(kv_store.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_sums_integers() {
+        let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+        for hits in 1..=10 {
+            kv_store.merge("hits", hits, |old, new| old + new);
+        }
+        assert_eq!(kv_store.get("hits"), Some(&55));
+        assert_eq!(kv_store.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_concatenates_strings() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+        let concat = |old: &String, new: &String| format!("{},{}", old, new);
+        kv_store.merge("tags", "a".to_string(), concat);
+        kv_store.merge("tags", "b".to_string(), concat);
+        kv_store.merge("tags", "c".to_string(), concat);
+        assert_eq!(kv_store.get("tags").unwrap(), "a,b,c");
+
+        // An expired value is replaced rather than merged into.
+        kv_store.save_with_ttl("expired", "old".to_string(), Duration::ZERO);
+        kv_store.merge("expired", "new".to_string(), concat);
+        assert_eq!(kv_store.get("expired").unwrap(), "new");
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+
+        // Insert when the key must be absent.
+        assert!(kv_store.compare_and_swap("key1", None, "value1".to_string()));
+        assert_eq!(kv_store.get("key1").unwrap(), "value1");
+        assert!(!kv_store.compare_and_swap("key1", None, "value2".to_string()));
+
+        // Swap on a matching value, fail on a mismatch.
+        assert!(kv_store.compare_and_swap(
+            "key1",
+            Some(&"value1".to_string()),
+            "value2".to_string()
+        ));
+        assert_eq!(kv_store.get("key1").unwrap(), "value2");
+        assert!(!kv_store.compare_and_swap(
+            "key1",
+            Some(&"value1".to_string()),
+            "value3".to_string()
+        ));
+        assert_eq!(kv_store.get("key1").unwrap(), "value2");
+
+        // An absent key never matches a value, and an expired key is absent.
+        assert!(!kv_store.compare_and_swap(
+            "key2",
+            Some(&"value1".to_string()),
+            "value2".to_string()
+        ));
+        assert!(!kv_store.contains_key("key2"));
+        kv_store.save_with_ttl("key3", "value1".to_string(), Duration::ZERO);
+        assert!(kv_store.compare_and_swap("key3", None, "value2".to_string()));
+        assert_eq!(kv_store.get("key3").unwrap(), "value2");
+    }
+
+    #[test]
+    fn test_apply_batch() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+        kv_store.save("key1", "value1".to_string());
+        kv_store.save_with_ttl("key2", "value2".to_string(), Duration::from_secs(3600));
+
+        let mut batch = WriteBatch::new();
+        batch
+            .put("key3".to_string(), "value3".to_string())
+            .delete("key1".to_string())
+            .put("key2".to_string(), "new".to_string())
+            .put("key4".to_string(), "value4".to_string())
+            .delete("key4".to_string())
+            .delete("missing".to_string());
+        assert_eq!(batch.len(), 6);
+        kv_store.apply_batch(batch);
+
+        assert_eq!(kv_store.get("key1"), None);
+        assert_eq!(kv_store.get("key2").unwrap(), "new");
+        assert_eq!(kv_store.get("key3").unwrap(), "value3");
+        assert_eq!(kv_store.get("key4"), None);
+        assert_eq!(kv_store.len(), 2);
+        // Puts clear the time-to-live, like `save`.
+        assert!(kv_store.expiries.is_empty());
+    }
+
+    #[test]
+    fn test_estimated_bytes() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+        let empty = kv_store.estimated_bytes();
+        assert_eq!(empty, size_of::<MapRustyKV<String>>());
+
+        for index in 0..100 {
+            kv_store.save(&format!("key{:03}", index), "v".repeat(100));
+        }
+        let filled = kv_store.estimated_bytes();
+        // At least the keys and values themselves.
+        assert!(filled >= empty + 100 * (6 + 100));
+        // And not wildly more, given the map's buckets.
+        assert!(filled <= empty + 100 * (6 + 100) + 4 * 100 * (size_of::<(String, String)>() + 1));
+
+        // A larger value grows the estimate by at least its size.
+        kv_store.save("key000", "v".repeat(10_000));
+        assert!(kv_store.estimated_bytes() >= filled + 10_000 - 100);
+
+        // Keys with a time-to-live are counted again in the expiry map.
+        let before_ttl = kv_store.estimated_bytes();
+        kv_store.save_with_ttl("key001", "v".repeat(100), Duration::from_secs(3600));
+        assert!(kv_store.estimated_bytes() > before_ttl);
+    }
+
+    #[test]
+    fn test_ttl_expires_entries() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+        kv_store.save_with_ttl("short", "value1".to_string(), Duration::from_millis(50));
+        kv_store.save_with_ttl("long", "value2".to_string(), Duration::from_secs(3600));
+        kv_store.save("forever", "value3".to_string());
+        assert_eq!(kv_store.get("short").unwrap(), "value1");
+        assert_eq!(kv_store.len(), 3);
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(kv_store.get("short"), None);
+        assert!(!kv_store.contains_key("short"));
+        assert_eq!(kv_store.get("long").unwrap(), "value2");
+        assert_eq!(kv_store.get("forever").unwrap(), "value3");
+        assert_eq!(kv_store.len(), 2);
+        assert!(kv_store.keys().all(|key| key != "short"));
+
+        // Expired entries behave as absent when written to.
+        assert!(!kv_store.update_with("short", |value| value.push('!')));
+        assert!(!kv_store.delete("short"));
+        assert_eq!(kv_store.get_or_insert_with("short", String::new), "");
+    }
+
+    #[test]
+    fn test_save_clears_ttl() {
+        let mut kv_store: MapRustyKV<String> = MapRustyKV::new();
+        kv_store.save_with_ttl("key1", "value1".to_string(), Duration::ZERO);
+        kv_store.save_with_ttl("key2", "value2".to_string(), Duration::ZERO);
+        kv_store.save("key1", "value3".to_string());
+        kv_store.save_batch(vec![("key2".to_string(), "value4".to_string())]);
+
+        assert_eq!(kv_store.get("key1").unwrap(), "value3");
+        assert_eq!(kv_store.get("key2").unwrap(), "value4");
+        assert_eq!(kv_store.purge_expired(), 0);
+    }
+
+    #[test]
+    fn test_replacing_ttl_updates_expiry_order() {
+        let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+        kv_store.save_with_ttl("key1", 1, Duration::ZERO);
+        kv_store.save_with_ttl("key2", 2, Duration::ZERO);
+        // Moving a key to a later expiry takes it out of the expired prefix.
+        kv_store.save_with_ttl("key1", 3, Duration::from_secs(3600));
+        assert_eq!(kv_store.len(), 1);
+
+        kv_store.save("key2", 4);
+        assert_eq!(kv_store.len(), 2);
+        assert_eq!(kv_store.purge_expired(), 0);
+        assert_eq!(kv_store.expiry_order.len(), 1);
+
+        kv_store.delete("key1");
+        assert!(kv_store.expiry_order.is_empty());
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let mut kv_store: MapRustyKV<u32> = MapRustyKV::new();
+        for index in 0..10 {
+            kv_store.save_with_ttl(&format!("expired{}", index), index, Duration::ZERO);
+        }
+        kv_store.save_with_ttl("live", 10, Duration::from_secs(3600));
+        kv_store.save("forever", 11);
+
+        assert_eq!(kv_store.len(), 2);
+        assert_eq!(kv_store.purge_expired(), 10);
+        assert_eq!(kv_store.purge_expired(), 0);
+        assert_eq!(kv_store.len(), 2);
+        assert_eq!(kv_store.get("live"), Some(&10));
+        assert_eq!(kv_store.get("forever"), Some(&11));
+    }
 }
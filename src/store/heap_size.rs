@@ -0,0 +1,80 @@
+use std::mem::size_of;
+
+///
+/// Estimates the heap memory owned by a value, not counting the value itself. Used by
+/// `MapRustyKV::estimated_bytes`.
+///
+pub trait HeapSize {
+    ///
+    /// Returns the number of bytes the value owns on the heap.
+    ///
+    fn heap_size(&self) -> usize;
+}
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_size(&self) -> usize {
+        size_of::<T>() + self.as_ref().heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_size)
+    }
+}
+
+///
+/// Types that don't own any heap memory.
+///
+macro_rules! impl_heap_size_for_inline_types {
+    ($($t:ty),*) => {
+        $(
+            impl HeapSize for $t {
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+impl_heap_size_for_inline_types!(
+    bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heap_size() {
+        assert_eq!(5u64.heap_size(), 0);
+        assert_eq!(String::new().heap_size(), 0);
+        assert_eq!(String::with_capacity(32).heap_size(), 32);
+
+        let bytes: Vec<u8> = Vec::with_capacity(100);
+        assert_eq!(bytes.heap_size(), 100);
+        // Elements' own heap memory is counted too.
+        let strings = vec!["abc".to_string(), String::with_capacity(10)];
+        assert_eq!(
+            strings.heap_size(),
+            strings.capacity() * size_of::<String>() + 3 + 10
+        );
+
+        assert_eq!(Some("abc".to_string()).heap_size(), 3);
+        assert_eq!(None::<String>.heap_size(), 0);
+        assert_eq!(Box::new(7u32).heap_size(), 4);
+    }
+}
@@ -16,6 +16,44 @@ pub trait RustyKV<T> {
     ///
     fn get(&self, key: &str) -> Option<&T>;
 
+    ///
+    /// Retrieves the values associated with several keys.
+    ///
+    /// # Arguments
+    /// * `keys` - The keys to be retrieved.
+    ///
+    /// # Returns
+    /// * The value of each key, in the same order as `keys`. `None` for keys that don't exist.
+    ///
+    fn get_many<'a>(&'a self, keys: &[&str]) -> Vec<Option<&'a T>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    ///
+    /// Retrieves the value associated with a key, or `default` if the key doesn't exist.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key to be retrieved.
+    /// * `default` - The value returned if the key doesn't exist.
+    ///
+    fn get_or<'a>(&'a self, key: &str, default: &'a T) -> &'a T {
+        self.get(key).unwrap_or(default)
+    }
+
+    ///
+    /// Retrieves a copy of the value associated with a key, or `T::default()` if the key
+    /// doesn't exist.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key to be retrieved.
+    ///
+    fn get_or_default(&self, key: &str) -> T
+    where
+        T: Default + Clone,
+    {
+        self.get(key).cloned().unwrap_or_default()
+    }
+
     ///
     /// Saves a key-value pair to the store. If the key already exists, its value
     /// is updated.
@@ -26,6 +64,19 @@ pub trait RustyKV<T> {
     ///
     fn save(&mut self, key: &str, value: T);
 
+    ///
+    /// Saves a batch of key-value pairs to the store. Keys that already exist have their
+    /// values updated. If a key appears more than once in the batch, the last value wins.
+    ///
+    /// # Arguments
+    /// * `items` - The key-value pairs to be saved.
+    ///
+    fn save_batch(&mut self, items: impl IntoIterator<Item = (String, T)>) {
+        for (key, value) in items {
+            self.save(&key, value);
+        }
+    }
+
     ///
     /// Deletes a key-value pair from the store.
     ///
@@ -36,9 +87,37 @@ pub trait RustyKV<T> {
     /// * `true` if the key was found and deleted, `false` otherwise.
     ///
     fn delete(&mut self, key: &str) -> bool;
+
+    ///
+    /// Removes every key-value pair from the store.
+    ///
+    fn clear(&mut self);
+
+    ///
+    /// Checks if a key exists in the store, without fetching its value.
+    ///
+    /// # Arguments
+    /// * `key` - A string slice that holds the key to be checked.
+    ///
+    /// # Returns
+    /// * `true` if the key exists, `false` otherwise.
+    ///
+    fn contains_key(&self, key: &str) -> bool;
 }
 
 pub mod hashmap_kv;
 pub use hashmap_kv::MapRustyKV;
 
+pub mod concurrent_hashmap_kv;
+pub use concurrent_hashmap_kv::ConcurrentMapRustyKV;
+
+pub mod bounded_hashmap_kv;
+pub use bounded_hashmap_kv::BoundedMapRustyKV;
+
+pub mod heap_size;
+pub use heap_size::HeapSize;
+
+pub mod write_batch;
+pub use write_batch::{BatchOperation, WriteBatch};
+
 pub mod btree_kv;
@@ -0,0 +1,88 @@
+///
+/// An operation recorded in a WriteBatch.
+///
+#[derive(Clone, PartialEq, Debug)]
+pub enum BatchOperation<K, V> {
+    ///
+    /// Saves a value, updating it if the key already exists.
+    ///
+    Put(K, V),
+    ///
+    /// Deletes a key, if it exists.
+    ///
+    Delete(K),
+}
+
+///
+/// A sequence of puts and deletes, applied all-or-nothing by a store's `apply_batch`.
+/// Operations are applied in the order they were recorded, so a later operation on a key
+/// overrides an earlier one.
+///
+/// # Examples
+/// let mut batch = WriteBatch::new();
+/// batch.put("key1".to_string(), 1).delete("key2".to_string());
+/// kv_store.apply_batch(batch);
+///
+#[derive(Clone, PartialEq, Debug)]
+pub struct WriteBatch<K, V> {
+    operations: Vec<BatchOperation<K, V>>,
+}
+
+impl<K, V> WriteBatch<K, V> {
+    ///
+    /// Creates an empty batch.
+    ///
+    pub fn new() -> Self {
+        WriteBatch {
+            operations: Vec::new(),
+        }
+    }
+
+    ///
+    /// Records a put of a key value.
+    ///
+    pub fn put(&mut self, key: K, value: V) -> &mut Self {
+        self.operations.push(BatchOperation::Put(key, value));
+        self
+    }
+
+    ///
+    /// Records a delete of a key.
+    ///
+    pub fn delete(&mut self, key: K) -> &mut Self {
+        self.operations.push(BatchOperation::Delete(key));
+        self
+    }
+
+    ///
+    /// Returns the number of operations in the batch.
+    ///
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    ///
+    /// Returns `true` if the batch has no operations.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+impl<K, V> Default for WriteBatch<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> IntoIterator for WriteBatch<K, V> {
+    type Item = BatchOperation<K, V>;
+    type IntoIter = std::vec::IntoIter<BatchOperation<K, V>>;
+
+    ///
+    /// Consumes the batch, yielding its operations in the order they were recorded.
+    ///
+    fn into_iter(self) -> Self::IntoIter {
+        self.operations.into_iter()
+    }
+}